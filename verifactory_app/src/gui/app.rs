@@ -1,30 +1,45 @@
 use std::{
     collections::{HashMap, HashSet},
     path::PathBuf,
+    time::Duration,
 };
 
 use egui::{Align2, Direction, Event, InputState, Key};
 use egui_file::FileDialog;
 use egui_toast::{Toast, ToastOptions, Toasts};
+use fraction::GenericFraction;
 
 use verifactory_lib::{
+    analyze::{
+        analyze, analyze_components, min_throughput_unlimited_tier, prove_throughput_unlimited,
+        with_output_priority, Analysis, AnalyzeOptions, GraphSummary, TuMethod,
+    },
     backends::{
-        belt_balancer_f, equal_drain_f, throughput_unlimited, universal_balancer,
-        BlueprintProofEntity, ModelFlags, ProofResult,
+        approx_balancer_f, belt_balancer_f, equal_drain_f, saturated_flow, universal_balancer,
+        universal_equal_drain_f, BlueprintProofEntity, CounterExample, ModelFlags, ProofContext,
+        ProofResult,
     },
-    entities::{EntityId, FBEntity},
+    entities::{entities_within, EntityId, FBEntity, Priority},
+    export::entities_to_string,
     frontend::{Compiler, RelMap},
-    import::string_to_entities,
-    ir::{CoalesceStrength, FlowGraph, FlowGraphFun, Node, Reversable},
+    import::{string_to_entities, ImportError},
+    ir::{CoalesceStrength, FlowGraph, FlowGraphAnalysis, FlowGraphFun, Node, Reversable},
+    render::{ExportFormat, ExportOptions, FlowGraphRender},
+    session::Session,
     utils::Position,
 };
 
 use super::menu::BlueprintString;
 
+/// Time after which the quantified throughput unlimited proof falls back to simpler methods
+const TU_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Default)]
 pub struct FileState {
     pub opened_file: Option<PathBuf>,
     pub open_file_dialog: Option<FileDialog>,
+    /// Dialog to open or save a session, see [`MyApp::session`]
+    pub session_dialog: Option<FileDialog>,
 }
 
 pub struct GridSettings {
@@ -51,6 +66,8 @@ pub struct IOState {
     pub output_candidates: HashSet<EntityId>,
     pub input_entities: HashSet<EntityId>,
     pub output_entities: HashSet<EntityId>,
+    /// Belts compiled into both an input and an output
+    pub taps: HashSet<EntityId>,
 }
 
 impl IOState {
@@ -71,6 +88,7 @@ impl IOState {
             output_candidates,
             input_entities,
             output_entities,
+            taps: HashSet::new(),
         }
     }
 }
@@ -78,9 +96,35 @@ impl IOState {
 #[derive(Default)]
 pub struct ProofState {
     balancer: Option<ProofResult>,
+    /// Set if the balancer proof was skipped as the graph has no splitters
+    trivial: bool,
+    /// Set to an input and an output that are not connected, ruling out a balancer without a proof
+    unreachable: Option<(EntityId, EntityId)>,
+    /// Input and output throughputs for which the design does not balance
+    counter_example: Option<CounterExample>,
+    /// Whether the outputs are balanced within [`MyApp::tolerance`]
+    approx_balancer: Option<ProofResult>,
     equal_drain: Option<ProofResult>,
     throughput_unlimited: Option<ProofResult>,
+    /// Method that decided the throughput unlimited result
+    throughput_unlimited_method: TuMethod,
+    /// Flags of the model the throughput unlimited result was proven with
+    throughput_unlimited_flags: ModelFlags,
+    /// Lowest belt tier making the design throughput unlimited, `Some(None)` if there is none
+    tu_tier: Option<Option<f64>>,
     universal: Option<ProofResult>,
+    universal_equal_drain: Option<ProofResult>,
+    /// Results of proving each connected component separately
+    components: Vec<Analysis>,
+    /// Overview of the design, cached for the selection of inputs and outputs it was computed for
+    summary: Option<(AnalyzeOptions, GraphSummary)>,
+    /// Maximum throughput and the entities on the critical edges limiting it
+    bottleneck: Option<String>,
+    pub critical_entities: Vec<EntityId>,
+    /// Results of the last run of all the proofs, saved along with a session
+    analysis: Option<Analysis>,
+    /// Results of proving only the entities within [`MyApp::region`]
+    pub region: Option<Analysis>,
 }
 
 pub type EntityGrid = Vec<Vec<Option<FBEntity<i32>>>>;
@@ -94,7 +138,16 @@ pub struct MyApp {
     pub selection: Option<FBEntity<i32>>,
     pub blueprint_string: BlueprintString,
     pub feeds_from: RelMap<Position<i32>>,
-    pub show_error: bool,
+    pub compiler: Compiler,
+    pub entities: Vec<FBEntity<i32>>,
+    /// Message of the error window, if one is shown
+    pub error: Option<String>,
+    /// Corners of the region dragged over the grid, see [`entities_within`]
+    pub region: Option<(Position<i32>, Position<i32>)>,
+    /// Screen position where the drag of the region started, while it is being dragged
+    pub region_start: Option<egui::Pos2>,
+    /// Tolerance in items/s up to which the outputs count as balanced, as entered by the user, e.g. `1/2`
+    pub tolerance: String,
 }
 
 impl Default for MyApp {
@@ -108,7 +161,12 @@ impl Default for MyApp {
         let selection = None;
         let blueprint_string = BlueprintString::default();
         let feeds_from = HashMap::new();
-        let show_error = false;
+        let compiler = Compiler::new(vec![]);
+        let entities = vec![];
+        let error = None;
+        let region = None;
+        let region_start = None;
+        let tolerance = "1".to_owned();
         Self {
             grid,
             grid_settings,
@@ -119,30 +177,56 @@ impl Default for MyApp {
             selection,
             blueprint_string,
             feeds_from,
-            show_error,
+            compiler,
+            entities,
+            error,
+            region,
+            region_start,
+            tolerance,
         }
     }
 }
 
 impl MyApp {
-    fn generate_graph(&self, reversed: bool) -> FlowGraph {
-        let mut graph = self.graph.clone();
+    /// Returns the options of an analysis with the current selection of inputs, outputs and taps
+    fn analyze_options(&self) -> AnalyzeOptions {
         let io_state = &self.io_state;
         let removed_inputs = io_state
             .input_candidates
-            .difference(&io_state.input_entities);
+            .difference(&io_state.input_entities)
+            .cloned()
+            .collect();
         let removed_outputs = io_state
             .output_candidates
-            .difference(&io_state.output_entities);
-
-        let removed = removed_inputs
-            .chain(removed_outputs)
+            .difference(&io_state.output_entities)
             .cloned()
-            .collect::<Vec<_>>();
+            .collect();
+        let taps = io_state.taps.iter().cloned().collect();
+        AnalyzeOptions {
+            removed_inputs,
+            removed_outputs,
+            taps,
+            ..Default::default()
+        }
+    }
 
-        println!("Remove list: {:?}", removed);
+    fn generate_graph(&self, reversed: bool) -> FlowGraph {
+        self.generate_graph_with(reversed, CoalesceStrength::Aggressive)
+    }
+
+    /// Same as [`MyApp::generate_graph`], but simplified with the given `strength`
+    fn generate_graph_with(&self, reversed: bool, strength: CoalesceStrength) -> FlowGraph {
+        let mut graph = self.graph.clone();
+        let options = self.analyze_options();
 
-        graph.simplify(&removed, CoalesceStrength::Aggressive);
+        tracing::debug!(
+            "Remove list: inputs {:?}, outputs {:?}",
+            options.removed_inputs,
+            options.removed_outputs
+        );
+
+        graph.remove_io(&options.removed_inputs, &options.removed_outputs);
+        graph.simplify(&[], strength);
         if reversed {
             Reversable::reverse(&graph)
         } else {
@@ -150,29 +234,180 @@ impl MyApp {
         }
     }
 
-    pub fn load_file(&mut self, file: PathBuf) -> anyhow::Result<()> {
+    pub fn load_file(&mut self, file: PathBuf) -> Result<(), ImportError> {
         let blueprint_string = std::fs::read_to_string(file.clone())?;
         self.open_file_state.opened_file = Some(file);
         self.load_string(&blueprint_string)
     }
 
-    pub fn load_string(&mut self, blueprint: &str) -> anyhow::Result<()> {
+    pub fn load_string(&mut self, blueprint: &str) -> Result<(), ImportError> {
         let loaded_entities = string_to_entities(blueprint)?;
+        self.load_entities(loaded_entities);
+        Ok(())
+    }
+
+    /// Shows the reason why a blueprint could not be loaded in the error window
+    pub fn show_import_error(&mut self, err: ImportError) {
+        let hint = match err {
+            ImportError::Empty => "Paste a blueprint string exported from Factorio.".to_owned(),
+            ImportError::Io(_) => "The file could not be read.".to_owned(),
+            ImportError::Base64(_) | ImportError::Decompress(_) => {
+                "This is not a blueprint string, make sure it was copied completely.".to_owned()
+            }
+            ImportError::Json(_) => {
+                "The string does not contain a single blueprint. To open a blueprint of a book, export that blueprint on its own."
+                    .to_owned()
+            }
+            ImportError::TooLarge { count, limit } => format!(
+                "The blueprint has {} entities, only blueprints with up to {} entities can be verified.",
+                count, limit
+            ),
+            ImportError::Overlap { x, y, .. } => format!(
+                "Two entities overlap at ({}, {}), the blueprint was probably modded or edited by hand.",
+                x, y
+            ),
+            ImportError::UnsupportedEntity { name } => format!(
+                "The blueprint contains no belts, splitters, inserters or assemblers, {} is not supported.",
+                name
+            ),
+        };
+        self.error = Some(hint);
+    }
+
+    /// Replaces the current blueprint with the given entities
+    fn load_entities(&mut self, loaded_entities: Vec<FBEntity<i32>>) {
         self.grid = Self::entities_to_grid(loaded_entities.clone());
         self.grid_settings = GridSettings::from(&self.grid);
 
+        self.entities = loaded_entities.clone();
         let compiler = Compiler::new(loaded_entities);
         self.feeds_from = compiler.feeds_from.clone();
-        self.graph = compiler.create_graph();
+        self.compiler = compiler;
+        self.io_state.taps.clear();
+        self.region = None;
+        /* the highlighted entities belong to the previous blueprint */
+        self.proof_state.bottleneck = None;
+        self.proof_state.critical_entities.clear();
+        self.rebuild_graph();
+    }
+
+    /// Cycles the output priority of the splitter with the given id and proves all the properties again
+    fn cycle_output_priority(&mut self, id: EntityId) {
+        let Some(FBEntity::Splitter(splitter)) = self
+            .entities
+            .iter()
+            .find(|e| matches!(e, FBEntity::Splitter(s) if s.base.id == id))
+            .copied()
+        else {
+            return;
+        };
+        let priority = match splitter.output_prio {
+            Priority::None => Priority::Left,
+            Priority::Left => Priority::Right,
+            Priority::Right => Priority::None,
+        };
+        let Some(entities) = with_output_priority(&self.entities, id, priority) else {
+            return;
+        };
+        /* the priority does not change the inputs and outputs, so the selection is kept */
+        let options = self.analyze_options();
+        self.load_entities(entities);
+        self.restore_selection(options);
+        self.selection = Some(FBEntity::Splitter(splitter.with_output_priority(priority)));
+        self.prove_all();
+    }
+
+    /// Proves all the properties with the current selection of inputs, outputs and taps
+    fn prove_all(&mut self) {
+        let options = self.analyze_options();
+        let analysis = analyze(self.entities.clone(), &options);
+        self.show_analysis(analysis);
+    }
+
+    /// Shows the results of all the proofs
+    fn show_analysis(&mut self, analysis: Analysis) {
+        self.proof_state.analysis = Some(analysis);
+        self.proof_state.balancer = Some(analysis.balancer);
+        self.proof_state.equal_drain = Some(analysis.equal_drain);
+        self.proof_state.throughput_unlimited = Some(analysis.throughput_unlimited);
+        self.proof_state.throughput_unlimited_flags = analysis.throughput_unlimited_flags;
+        self.proof_state.universal = Some(analysis.universal);
+    }
+
+    /// Returns a snapshot of the loaded blueprint, the selection of inputs, outputs and taps and the proof results
+    pub fn session(&self) -> Session {
+        Session::new(
+            self.entities.clone(),
+            self.analyze_options(),
+            self.proof_state.analysis,
+        )
+    }
+
+    /// Restores a snapshot taken with [`MyApp::session`]
+    pub fn restore_session(&mut self, session: Session) {
+        self.load_entities(session.entities);
+        self.restore_selection(session.options);
+        if let Some(analysis) = session.analysis {
+            self.show_analysis(analysis);
+        }
+    }
+
+    /// Selects the taps and removes the inputs and outputs as in the given `options`
+    fn restore_selection(&mut self, options: AnalyzeOptions) {
+        self.io_state.taps = options.taps.into_iter().collect();
+        self.rebuild_graph();
+        for id in &options.removed_inputs {
+            self.io_state.input_entities.remove(id);
+        }
+        for id in &options.removed_outputs {
+            self.io_state.output_entities.remove(id);
+        }
+    }
+
+    /// Returns the overview of the design, computing it again if the selection of inputs and outputs changed
+    fn summary(&mut self) -> GraphSummary {
+        let options = self.analyze_options();
+        match &self.proof_state.summary {
+            Some((cached, summary)) if *cached == options => *summary,
+            _ => {
+                let summary = GraphSummary::new(&self.generate_graph(false));
+                self.proof_state.summary = Some((options, summary));
+                summary
+            }
+        }
+    }
+
+    /// Compiles the graph again with the current taps, resetting the selection of inputs and outputs.
+    fn rebuild_graph(&mut self) {
+        let taps = std::mem::take(&mut self.io_state.taps);
+        let tap_list = taps.iter().cloned().collect::<Vec<_>>();
+        self.graph = self.compiler.create_graph_with_taps(&tap_list);
         self.graph.simplify(&[], CoalesceStrength::Lossless);
         self.io_state = IOState::from_graph(&self.graph);
+        self.io_state.taps = taps;
         self.proof_state = ProofState::default();
-        Ok(())
+    }
+
+    /// Marks or unmarks the belt with the given id as tap
+    fn toggle_tap(&mut self, id: EntityId) {
+        if !self.io_state.taps.remove(&id) {
+            self.io_state.taps.insert(id);
+        }
+        self.rebuild_graph();
+    }
+}
+
+/// Shows the result of a proof, highlighting the results that neither prove nor disprove the property
+fn proof_label(ui: &mut egui::Ui, text: String, res: ProofResult) {
+    if res.is_conclusive() {
+        ui.label(text);
+    } else {
+        ui.colored_label(ui.visuals().warn_fg_color, text);
     }
 }
 
 impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Set up toast notifications in the top right
         let mut toasts = Toasts::new()
             .anchor(Align2::RIGHT_TOP, (-10.0, 10.0))
@@ -187,9 +422,9 @@ impl eframe::App for MyApp {
                 _ => None,
             });
             if let Some(pasted_string) = pasted_string {
-                if self.load_string(pasted_string).is_err() {
+                if let Err(err) = self.load_string(pasted_string) {
                     toasts.add(Toast {
-                        text: "Failed to load blueprint from clipboard!".into(),
+                        text: format!("Failed to load blueprint from clipboard: {}", err).into(),
                         kind: egui_toast::ToastKind::Error,
                         options: ToastOptions::default().duration_in_seconds(10.0),
                     });
@@ -208,6 +443,9 @@ impl eframe::App for MyApp {
         });
 
         let io_state = &mut self.io_state;
+        let compiler = &self.compiler;
+        let mut toggled_tap = None;
+        let mut cycled_priority = None;
         if let Some(sel) = self.selection {
             let (i_pressed, o_pressed) =
                 ctx.input(|i: &InputState| (i.key_pressed(Key::I), i.key_pressed(Key::O)));
@@ -218,6 +456,16 @@ impl eframe::App for MyApp {
                 ui.separator();
                 ui.label(format!("Entity ID: {}", id));
                 ui.label(format!("Throughput: {}/s", base.throughput as i32));
+                if let Some(quality) = base.quality {
+                    ui.label(format!("Quality: {}", quality.name()));
+                }
+                let explanation = compiler.explain_tile(base.position);
+                ui.label(format!("Compiled as: {:?}", explanation.role));
+                ui.label(format!(
+                    "Feeds into {} tile(s), fed by {} tile(s)",
+                    explanation.feeds_to.len(),
+                    explanation.feeds_from.len()
+                ));
 
                 ui.horizontal(|ui| {
                     if io_state.input_entities.contains(&id) {
@@ -247,34 +495,205 @@ impl eframe::App for MyApp {
                         }
                     }
                 });
+                if let FBEntity::Splitter(s) = sel {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Output priority: {:?}", s.output_prio));
+                        if ui.button("Change priority and prove again").clicked() {
+                            cycled_priority = Some(id);
+                        }
+                    });
+                }
+                if matches!(sel, FBEntity::Belt(_)) {
+                    ui.horizontal(|ui| {
+                        if io_state.taps.contains(&id) {
+                            ui.label("Selected as tap (input and output)");
+                            if ui.button("Remove tap").clicked() {
+                                toggled_tap = Some(id);
+                            }
+                        } else if ui.button("Select as tap").clicked() {
+                            toggled_tap = Some(id);
+                        }
+                    });
+                }
             });
         }
+        if let Some(id) = toggled_tap {
+            self.toggle_tap(id);
+        }
+        if let Some(id) = cycled_priority {
+            self.cycle_output_priority(id);
+        }
 
-        if self.show_error {
+        if let Some(error) = &self.error {
+            let mut close = false;
             egui::Window::new("Error").title_bar(false).show(ctx, |ui| {
                 ui.heading("Error whilst loading blueprint!");
-                ui.label("Blueprint string is either malformed or uses non supported entities.");
-                if ui.button("Close").clicked() {
-                    self.show_error = false;
-                }
+                ui.label(error);
+                close = ui.button("Close").clicked();
             });
+            if close {
+                self.error = None;
+            }
         }
 
         egui::TopBottomPanel::top("proof_panel").show(ctx, |ui| {
             ui.heading("Proofs");
             ui.separator();
+            let summary = self.summary();
+            ui.label(summary.to_string());
+
+            if ui.button("Prove all").clicked() {
+                self.prove_all();
+            }
+
+            if ui.button("Find bottleneck").clicked() {
+                let mut graph = self.graph.clone();
+                let options = self.analyze_options();
+                graph.remove_io(&options.removed_inputs, &options.removed_outputs);
+                let mut critical = graph
+                    .critical_edges()
+                    .iter()
+                    .map(|e| graph[graph.edge_endpoints(*e).unwrap().0].get_id())
+                    .collect::<Vec<_>>();
+                critical.sort();
+                critical.dedup();
+                self.proof_state.bottleneck = Some(format!(
+                    "Max throughput: {}/s, limited by entities {:?}",
+                    graph.min_cut_value(),
+                    critical
+                ));
+                self.proof_state.critical_entities = critical;
+            }
+            if let Some(bottleneck) = &self.proof_state.bottleneck {
+                ui.label(bottleneck);
+            }
+
+            if ui.button("Prove each component").clicked() {
+                let options = self.analyze_options();
+                self.proof_state.components = analyze_components(self.entities.clone(), &options);
+            }
+            for (i, analysis) in self.proof_state.components.iter().enumerate() {
+                ui.label(format!(
+                    "Component {}: balancer {}, equal drain {}, throughput unlimited {}, universal {}",
+                    i + 1,
+                    analysis.balancer,
+                    analysis.equal_drain,
+                    analysis.throughput_unlimited,
+                    analysis.universal
+                ));
+            }
+
+            if let Some((a, b)) = self.region {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Selected region from {} to {}", a, b));
+                    if ui.button("Prove selection").clicked() {
+                        let entities = entities_within(&self.entities, a, b);
+                        self.proof_state.region =
+                            Some(analyze(entities, &AnalyzeOptions::default()));
+                    }
+                    if ui.button("Clear selection").clicked() {
+                        self.region = None;
+                        self.proof_state.region = None;
+                    }
+                });
+                if let Some(analysis) = &self.proof_state.region {
+                    ui.label(analysis.to_string());
+                }
+            }
+
+            ui.label("\n");
 
             // TODO: figure out lifetimes and fix code duplication
             ui.heading("Is it a belt-balancer?");
             ui.horizontal(|ui| {
                 if ui.button("Prove").clicked() {
                     let graph = self.generate_graph(false);
-                    let mut proof = BlueprintProofEntity::new(graph);
-                    let res = proof.model(belt_balancer_f, ModelFlags::empty());
+                    self.proof_state.trivial = graph.is_trivial();
+                    self.proof_state.unreachable = graph.unreachable_io_pairs().first().copied();
+                    let res = if self.proof_state.trivial {
+                        let inputs = graph
+                            .node_weights()
+                            .filter(|n| matches!(n, Node::Input(_)))
+                            .count();
+                        if inputs == 1 {
+                            ProofResult::Sat
+                        } else {
+                            ProofResult::Unsat
+                        }
+                    } else if self.proof_state.unreachable.is_some() {
+                        ProofResult::Unsat
+                    } else {
+                        let mut proof = BlueprintProofEntity::new(graph, Some("QF_LRA"));
+                        if let Some(err) = proof.solver_error() {
+                            self.error = Some(err.to_string());
+                        }
+                        let res = proof.model(belt_balancer_f, ModelFlags::empty());
+                        let positions = self.compiler.entity_positions();
+                        self.proof_state.counter_example = proof
+                            .counter_example(belt_balancer_f, ModelFlags::empty())
+                            .map(|example| example.with_positions(&positions));
+                        res
+                    };
+                    if res != ProofResult::Unsat {
+                        self.proof_state.counter_example = None;
+                    }
                     self.proof_state.balancer = Some(res);
                 }
                 if let Some(proof_res) = self.proof_state.balancer {
-                    ui.label(format!("Proof result: {}", proof_res));
+                    proof_label(ui, format!("Proof result: {}", proof_res), proof_res);
+                }
+                if self.proof_state.trivial {
+                    ui.label("No splitters: trivially balanced only if 1 input.");
+                } else if let Some((input, output)) = self.proof_state.unreachable {
+                    ui.label(format!(
+                        "Input {} cannot reach output {}: this cannot be a balancer.",
+                        input, output
+                    ));
+                } else if let Some(example) = &self.proof_state.counter_example {
+                    ui.label(example.to_string());
+                    ui.label(format!("Max imbalance: {:.2}/s", example.max_imbalance()));
+                    if ui.button("Copy counter-example").clicked() {
+                        match entities_to_string(&self.entities, Some(&example.to_string())) {
+                            Ok(bp) => ui.output_mut(|o| o.copied_text = bp),
+                            Err(err) => self.error = Some(err.to_string()),
+                        }
+                    }
+                    /* select the entity of an input or output to locate it on the grid */
+                    let inputs = example.inputs.iter().map(|(id, _)| id);
+                    let ids = inputs.chain(example.outputs.iter().map(|(id, _)| id));
+                    for (id, pos) in ids.filter_map(|id| Some((id, example.positions.get(id)?))) {
+                        if ui.button(format!("Select {} at {}", id, pos)).clicked() {
+                            self.selection =
+                                self.entities.iter().find(|e| e.get_base().id == *id).copied();
+                        }
+                    }
+                }
+            });
+
+            ui.label("\n");
+
+            ui.heading("Is it balanced within a tolerance?");
+            ui.horizontal(|ui| {
+                ui.label("Tolerance (items/s):");
+                ui.add(egui::TextEdit::singleline(&mut self.tolerance).desired_width(60.));
+                if ui.button("Prove").clicked() {
+                    match self.tolerance.trim().parse::<GenericFraction<u128>>() {
+                        Ok(epsilon) if epsilon.is_finite() && !epsilon.is_sign_negative() => {
+                            let graph = self.generate_graph(false);
+                            let mut proof = BlueprintProofEntity::new(graph, Some("QF_LRA"));
+                            let res = proof.model(approx_balancer_f(epsilon), ModelFlags::empty());
+                            self.proof_state.approx_balancer = Some(res);
+                        }
+                        _ => {
+                            self.error = Some(format!(
+                                "The tolerance \"{}\" is not a non-negative number or a fraction like 1/2.",
+                                self.tolerance
+                            ))
+                        }
+                    }
+                }
+                if let Some(proof_res) = self.proof_state.approx_balancer {
+                    proof_label(ui, format!("Proof result: {}", proof_res), proof_res);
                 }
             });
 
@@ -284,12 +703,12 @@ impl eframe::App for MyApp {
             ui.horizontal(|ui| {
                 if ui.button("Prove").clicked() {
                     let graph = self.generate_graph(true);
-                    let mut proof = BlueprintProofEntity::new(graph);
+                    let mut proof = BlueprintProofEntity::new(graph, Some("QF_LRA"));
                     let res = proof.model(equal_drain_f, ModelFlags::empty());
                     self.proof_state.equal_drain = Some(res);
                 }
                 if let Some(proof_res) = self.proof_state.equal_drain {
-                    ui.label(format!("Proof result: {}", proof_res));
+                    proof_label(ui, format!("Proof result: {}", proof_res), proof_res);
                 }
             });
 
@@ -301,13 +720,35 @@ impl eframe::App for MyApp {
             ui.horizontal(|ui| {
                 if ui.button("Prove").clicked() {
                     let graph = self.generate_graph(false);
-                    let mut proof = BlueprintProofEntity::new(graph);
                     let entities = self.grid.iter().flatten().flatten().cloned().collect();
-                    let res = proof.model(throughput_unlimited(entities), ModelFlags::Relaxed);
+                    let (res, method) = prove_throughput_unlimited(&graph, entities, TU_TIMEOUT);
                     self.proof_state.throughput_unlimited = Some(res);
+                    self.proof_state.throughput_unlimited_method = method;
+                    self.proof_state.throughput_unlimited_flags = ModelFlags::Relaxed;
                 }
                 if let Some(proof_res) = self.proof_state.throughput_unlimited {
-                    ui.label(format!("Proof result: {}", proof_res));
+                    let text = format!(
+                        "Proof result: {} (by {})",
+                        proof_res, self.proof_state.throughput_unlimited_method
+                    );
+                    proof_label(ui, text, proof_res);
+                    for note in self.proof_state.throughput_unlimited_flags.notes() {
+                        ui.label(format!("Note: {}", note));
+                    }
+                }
+                if ui.button("Find belt tier").clicked() {
+                    let options = self.analyze_options();
+                    self.proof_state.tu_tier =
+                        Some(min_throughput_unlimited_tier(&self.entities, &options));
+                }
+                match self.proof_state.tu_tier {
+                    Some(Some(tier)) => {
+                        ui.label(format!("Throughput unlimited with belts of at least {}/s", tier));
+                    }
+                    Some(None) => {
+                        ui.label("Not throughput unlimited with any belt tier");
+                    }
+                    None => (),
                 }
             });
             ui.label("\n");
@@ -316,22 +757,61 @@ impl eframe::App for MyApp {
             ui.horizontal(|ui| {
                 if ui.button("Prove").clicked() {
                     let graph = self.generate_graph(false);
-                    let mut proof = BlueprintProofEntity::new(graph);
+                    let mut proof = BlueprintProofEntity::new(graph, None);
                     let res = proof.model(universal_balancer, ModelFlags::Blocked);
                     self.proof_state.universal = Some(res);
                 }
                 if let Some(proof_res) = self.proof_state.universal {
-                    ui.label(format!("Proof result: {}", proof_res));
+                    proof_label(ui, format!("Proof result: {}", proof_res), proof_res);
+                }
+            });
+
+            ui.label("\n");
+
+            ui.heading("Is it a universal equal drain belt-balancer?");
+            ui.horizontal(|ui| {
+                if ui.button("Prove").clicked() {
+                    let graph = self.generate_graph(true);
+                    let mut proof = BlueprintProofEntity::new(graph, None);
+                    let res = proof.model(universal_equal_drain_f, ModelFlags::Blocked);
+                    self.proof_state.universal_equal_drain = Some(res);
+                }
+                if let Some(proof_res) = self.proof_state.universal_equal_drain {
+                    proof_label(ui, format!("Proof result: {}", proof_res), proof_res);
                 }
             });
 
             ui.label("\n");
 
             if ui.button("Save svg").clicked() {
-                self.generate_graph(false).to_svg("out.svg").unwrap();
+                self.generate_graph_with(false, CoalesceStrength::DisplayFriendly)
+                    .to_svg("out.svg")
+                    .unwrap();
             }
             if ui.button("Save reversed svg").clicked() {
-                self.generate_graph(true).to_svg("out.svg").unwrap();
+                self.generate_graph_with(true, CoalesceStrength::DisplayFriendly)
+                    .to_svg("out.svg")
+                    .unwrap();
+            }
+            if ui.button("Save flow diagram svg").clicked() {
+                let graph = self.generate_graph_with(false, CoalesceStrength::DisplayFriendly);
+                let proof_ctx = ProofContext::new();
+                match saturated_flow(&graph, proof_ctx.ctx(), ModelFlags::empty()) {
+                    Some(flow) => graph
+                        .export_flow("out.svg", &flow, &ExportOptions::default())
+                        .unwrap(),
+                    None => self.error = Some("Could not solve the saturated flow".to_owned()),
+                }
+            }
+            if ui.button("Save high resolution png").clicked() {
+                let options = ExportOptions {
+                    format: ExportFormat::Png,
+                    dpi: Some(300),
+                    size: None,
+                };
+                self.generate_graph_with(false, CoalesceStrength::DisplayFriendly)
+                    .export("out.png", &options)
+                    .unwrap();
             }
             ui.label("\n");
         });
@@ -1,6 +1,4 @@
-use std::f32::consts::PI;
-
-use egui::{Color32, Image, Pos2, Rect, Sense, Vec2};
+use egui::{Color32, Image, Pos2, Rect, Sense, Stroke, Vec2};
 
 use verifactory_lib::{
     entities::{BeltType, FBBelt, FBEntity, FBSplitter, Priority},
@@ -106,12 +104,51 @@ impl MyApp {
     }
 
     pub fn draw_grid(&mut self, ui: &mut egui::Ui) {
+        self.drag_region(ui);
         for entity in self.grid.iter().flatten().flatten() {
             let selection = self.draw_img(ui, entity);
             if selection.is_some() {
                 self.selection = selection;
             }
         }
+        if let Some((a, b)) = self.region {
+            let rect = self.get_grid_rect(a).union(self.get_grid_rect(b));
+            ui.painter()
+                .rect_stroke(rect, 0., Stroke::new(2., Color32::LIGHT_BLUE));
+        }
+    }
+
+    /// Lets the user drag a rectangle over the grid to select the region to analyze on its own
+    fn drag_region(&mut self, ui: &mut egui::Ui) {
+        let s = &self.grid_settings;
+        let width = self.grid.iter().map(Vec::len).max().unwrap_or(0) as i32;
+        let area = Rect::from_min_size(
+            Pos2::new(s.x_offset as f32, s.y_offset as f32),
+            Vec2::new((width * s.size) as f32, ((s.max_y + 1) * s.size) as f32),
+        );
+        let response = ui.interact(area, ui.id().with("region"), Sense::drag());
+        if response.drag_started() {
+            self.region_start = response.interact_pointer_pos();
+        }
+        if let (Some(start), Some(end)) = (self.region_start, response.interact_pointer_pos()) {
+            self.region = Some((self.get_grid_position(start), self.get_grid_position(end)));
+        }
+        if response.drag_released() {
+            self.region_start = None;
+            self.proof_state.region = None;
+            /* a click selects an entity rather than a region */
+            if matches!(self.region, Some((a, b)) if a == b) {
+                self.region = None;
+            }
+        }
+    }
+
+    /// Inverse of [`MyApp::get_grid_rect`], returns the position of the tile containing `pos`
+    fn get_grid_position(&self, pos: Pos2) -> Position<i32> {
+        let s = &self.grid_settings;
+        let x = (pos.x as i32 - s.x_offset).div_euclid(s.size);
+        let y = s.max_y - (pos.y as i32 - s.y_offset).div_euclid(s.size);
+        Position { x, y }
     }
 
     fn get_grid_rect(&self, position: Position<i32>) -> Rect {
@@ -180,7 +217,7 @@ impl MyApp {
         ui.put(rect, img);
     }
 
-    fn get_entity_img(entity: &FBEntity<i32>, belt_rotation: Option<Rotation>) -> Image {
+    fn get_entity_img(entity: &FBEntity<i32>, belt_rotation: Option<Rotation>) -> Image<'_> {
         let base = entity.get_base();
         let rotation = base.direction.radians();
         match entity {
@@ -221,6 +258,13 @@ impl MyApp {
         .sense(Sense::click())
     }
 
+    fn draw_critical(&self, ui: &mut egui::Ui, rect: Rect) {
+        let img = Image::new(egui::include_image!("../../imgs/selection.svg"))
+            .tint(Color32::RED)
+            .fit_to_exact_size(Vec2::splat(self.grid_settings.size as f32));
+        ui.put(rect, img);
+    }
+
     fn draw_img(&self, ui: &mut egui::Ui, entity: &FBEntity<i32>) -> Option<FBEntity<i32>> {
         let s = &self.grid_settings;
         let base = entity.get_base();
@@ -258,6 +302,9 @@ impl MyApp {
             Some(sel) if sel.get_base().id == base.id => self.draw_selection(ui, pos_rect),
             _ => (),
         }
+        if self.proof_state.critical_entities.contains(&base.id) {
+            self.draw_critical(ui, pos_rect);
+        }
         if let FBEntity::Splitter(s) = entity {
             self.draw_prio(ui, pos_rect, s);
         }
@@ -1,7 +1,8 @@
 use std::path::Path;
 
 use egui::{Ui, Window};
-use egui_file::FileDialog;
+use egui_file::{DialogType, FileDialog};
+use verifactory_lib::session::Session;
 
 use super::app::MyApp;
 
@@ -31,8 +32,8 @@ impl MyApp {
             self.blueprint_string.show(ui);
             if self.blueprint_string.should_load {
                 let blueprint = self.blueprint_string.blueprint.clone();
-                if self.load_string(&blueprint).is_err() {
-                    self.show_error = true;
+                if let Err(err) = self.load_string(&blueprint) {
+                    self.show_import_error(err);
                 }
                 self.blueprint_string.should_load = false;
                 self.blueprint_string.open = false;
@@ -57,6 +58,18 @@ impl MyApp {
                             blueprint: String::new(),
                         };
                     }
+                    if ui.button("Open session").clicked() {
+                        ui.close_menu();
+                        let mut dialog = FileDialog::open_file(None);
+                        dialog.open();
+                        self.open_file_state.session_dialog = Some(dialog);
+                    }
+                    if ui.button("Save session").clicked() {
+                        ui.close_menu();
+                        let mut dialog = FileDialog::save_file(None);
+                        dialog.open();
+                        self.open_file_state.session_dialog = Some(dialog);
+                    }
                     /* Close button, terminates the application */
                     if ui.button("Close").clicked() {
                         std::process::exit(0);
@@ -72,8 +85,27 @@ impl MyApp {
                     }
                 });
                 if let Some(path) = path {
-                    if self.load_file(path).is_err() {
-                        self.show_error = true;
+                    if let Err(err) = self.load_file(path) {
+                        self.show_import_error(err);
+                    }
+                }
+                /* Handle the "Open session" and "Save session" dialogs */
+                let dialog = &mut self.open_file_state.session_dialog;
+                let selected = dialog.as_mut().and_then(|d| {
+                    if d.show(ctx).selected() {
+                        Some((d.dialog_type(), d.path()?.to_path_buf()))
+                    } else {
+                        None
+                    }
+                });
+                if let Some((dialog_type, path)) = selected {
+                    let path = path.to_string_lossy();
+                    let res = match dialog_type {
+                        DialogType::SaveFile => self.session().save(&path),
+                        _ => Session::load(&path).map(|s| self.restore_session(s)),
+                    };
+                    if let Err(err) = res {
+                        self.error = Some(err.to_string());
                     }
                 }
                 /* View submenu */
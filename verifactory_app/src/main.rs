@@ -1,18 +1,109 @@
 mod gui;
-use std::{fs::File, sync::Arc};
+use std::{collections::BTreeSet, fs::File, process::ExitCode, sync::Arc};
 
 use eframe::NativeOptions;
 use gui::MyApp;
+use verifactory_lib::{
+    analyze::{compile, verify_dir, AnalyzeOptions},
+    backends::ProofResult,
+    entities::remap_ids,
+    import::file_to_entities,
+    ir::FlowGraphFun,
+};
+
+/// Prints the simplified graph of the blueprint stored in `path` as Graphviz DOT to stdout.
+///
+/// The entity ids are remapped to `0..n` for readability, the original entity numbers are listed in comments.
+fn dump_dot(path: &str) -> anyhow::Result<()> {
+    let mut entities = file_to_entities(path)?;
+    let map = remap_ids(&mut entities);
+    let ids = entities
+        .iter()
+        .map(|e| e.get_base().id)
+        .collect::<BTreeSet<_>>();
+    for id in ids {
+        println!("// {}: entity {}", id, map.original(id).unwrap());
+    }
+    let graph = compile(entities, &AnalyzeOptions::default());
+    println!("{}", graph.to_dot_string());
+    Ok(())
+}
+
+/// Analyzes all the blueprints in the directory at `path` and prints a summary table to stdout.
+///
+/// Returns false if any of the blueprints is not a belt balancer.
+fn verify_dir_summary(path: &str) -> anyhow::Result<bool> {
+    let results = verify_dir(path)?;
+    let width = results
+        .iter()
+        .map(|(path, _)| path.display().to_string().len())
+        .chain(["Blueprint".len()])
+        .max()
+        .unwrap();
+    println!(
+        "{:width$}  {:8}  {:11}  {:20}  Universal",
+        "Blueprint", "Balancer", "Equal drain", "Throughput unlimited"
+    );
+    for (path, analysis) in &results {
+        println!(
+            "{:width$}  {:8}  {:11}  {:20}  {}",
+            path.display(),
+            analysis.balancer.to_string(),
+            analysis.equal_drain.to_string(),
+            analysis.throughput_unlimited.to_string(),
+            analysis.universal,
+        );
+    }
+    Ok(results
+        .iter()
+        .all(|(_, analysis)| analysis.balancer == ProofResult::Sat))
+}
+
+pub fn main() -> ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(pos) = args.iter().position(|a| a == "--dir") {
+        let Some(path) = args.get(pos + 1) else {
+            eprintln!("Usage: verifactory_app --dir <directory of blueprint files>");
+            return ExitCode::FAILURE;
+        };
+        return match verify_dir_summary(path) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("Failed to read directory: {}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--dump-dot") {
+        let Some(path) = args.get(pos + 1) else {
+            eprintln!("Usage: verifactory_app --dump-dot <blueprint file>");
+            return ExitCode::FAILURE;
+        };
+        return match dump_dot(path) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Failed to compile blueprint: {}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-pub fn main() -> Result<(), eframe::Error> {
     let file = File::create("debug.log").unwrap();
     tracing_subscriber::fmt().with_writer(Arc::new(file)).init();
-    eframe::run_native(
+    let res = eframe::run_native(
         "VeriFactory",
         NativeOptions::default(),
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
             Box::<MyApp>::default()
         }),
-    )
+    );
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,83 @@
+//! Benchmarks of the compile and proof times for balancers of increasing size.
+//!
+//! Run with `cargo bench -p verifactory_lib`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use verifactory_lib::{
+    analyze::{analyze, compile, AnalyzeOptions},
+    backends::{belt_balancer_f, model_f, IncrementalModel, ModelFlags},
+    frontend::Compiler,
+    import::file_to_entities,
+    ir::{CoalesceStrength, FlowGraphFun, Node},
+};
+use z3::{Config, Context};
+
+/// Representative balancers, from small to large
+const FIXTURES: [&str; 4] = ["3-2", "4-4", "6-3-tu", "4-4-univ"];
+
+fn bench_compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+    for fixture in FIXTURES {
+        let entities = file_to_entities(&format!("tests/{}", fixture)).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(fixture), &entities, |b, e| {
+            b.iter(|| compile(e.clone(), &AnalyzeOptions::default()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_analyze(c: &mut Criterion) {
+    let mut group = c.benchmark_group("analyze");
+    group.sample_size(10);
+    for fixture in FIXTURES {
+        let entities = file_to_entities(&format!("tests/{}", fixture)).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(fixture), &entities, |b, e| {
+            b.iter(|| analyze(e.clone(), &AnalyzeOptions::default()))
+        });
+    }
+    group.finish();
+}
+
+/// Proving the belt balancer property with each output of the largest fixture deselected,
+/// encoding the graph again for every selection against encoding it once in an [`IncrementalModel`]
+fn bench_output_selection(c: &mut Criterion) {
+    let entities = file_to_entities("tests/4-4-univ").unwrap();
+    let mut full_graph = Compiler::new(entities).create_graph();
+    full_graph.simplify(&[], CoalesceStrength::Lossless);
+    let outputs = full_graph
+        .node_weights()
+        .filter(|n| matches!(n, Node::Output(_)))
+        .map(|n| n.get_id())
+        .collect::<Vec<_>>();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+
+    let mut group = c.benchmark_group("output_selection");
+    group.sample_size(10);
+    group.bench_function("rebuild", |b| {
+        b.iter(|| {
+            for output in &outputs {
+                let mut graph = full_graph.clone();
+                graph.simplify(&[*output], CoalesceStrength::Aggressive);
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+            }
+        })
+    });
+    group.bench_function("incremental", |b| {
+        b.iter(|| {
+            let incremental = IncrementalModel::new(&full_graph, &ctx, ModelFlags::empty());
+            for output in &outputs {
+                incremental.check(belt_balancer_f, &[*output]);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_compile,
+    bench_analyze,
+    bench_output_selection
+);
+criterion_main!(benches);
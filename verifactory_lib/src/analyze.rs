@@ -0,0 +1,1278 @@
+//! Non-GUI entry point compiling a blueprint and running all the proofs on it.
+//!
+//! Everything returned is `Send`, so the analysis can be run on a worker thread or in benchmarks.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use fraction::GenericFraction;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    backends::{lp_belt_balancer, lp_equal_drain, ProofResult},
+    entities::{exact_throughput, EntityId, FBEntity, InserterTrait, Priority},
+    frontend::{Compiler, IoRule, RelMap},
+    ir::{CoalesceStrength, FlowGraph, FlowGraphAnalysis, FlowGraphFun, PrunedIo, Reversable},
+    utils::Position,
+};
+
+/* the proofs that need z3 */
+#[cfg(feature = "z3")]
+use {
+    crate::{
+        backends::{
+            belt_balancer_f, equal_drain_f, throughput_unlimited, universal_balancer,
+            BlueprintProofEntity, ModelFlags,
+        },
+        import::{string_to_book, string_to_entities, ImportError},
+        ir::Node,
+    },
+    anyhow::Result,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::{Duration, Instant},
+    },
+    tracing::warn,
+};
+
+/// Options of an analysis
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnalyzeOptions {
+    /// Inputs that are not part of the analysis
+    pub removed_inputs: Vec<EntityId>,
+    /// Outputs that are not part of the analysis
+    pub removed_outputs: Vec<EntityId>,
+    /// Belts that are both an input and an output, see [`Compiler::create_graph_with_taps`]
+    pub taps: Vec<EntityId>,
+    /// Which open ends of the belts are inputs and outputs, see [`IoRule`]
+    #[serde(default)]
+    pub io_rule: IoRule,
+    /// Whether to remove the inputs and outputs that can not carry any flow, see [`FlowGraphFun::prune_dead_io`]
+    #[serde(default)]
+    pub prune_dead_io: bool,
+}
+
+#[cfg(feature = "z3")]
+/// Results of all the proofs on a blueprint together with the time spent on each phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Analysis {
+    /// Result of [`belt_balancer_f`]
+    pub balancer: ProofResult,
+    /// Result of [`equal_drain_f`] on the reversed graph
+    pub equal_drain: ProofResult,
+    /// Result of [`throughput_unlimited`]
+    pub throughput_unlimited: ProofResult,
+    /// Flags of the model [`Analysis::throughput_unlimited`] was proven with
+    pub throughput_unlimited_flags: ModelFlags,
+    /// Result of [`universal_balancer`]
+    pub universal: ProofResult,
+    /// Flags of the model [`Analysis::universal`] was proven with
+    pub universal_flags: ModelFlags,
+    /// Time spent compiling the entities into a simplified [`FlowGraph`]
+    pub compile_time: Duration,
+    /// Time spent proving all the properties
+    pub proof_time: Duration,
+}
+
+#[cfg(feature = "z3")]
+/// Reports the result of each proof, noting the ones that were not proven on the normal model.
+impl Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let proofs = [
+            ("Balancer", self.balancer, ModelFlags::empty()),
+            ("Equal drain", self.equal_drain, ModelFlags::empty()),
+            (
+                "Throughput unlimited",
+                self.throughput_unlimited,
+                self.throughput_unlimited_flags,
+            ),
+            ("Universal", self.universal, self.universal_flags),
+        ];
+        for (name, result, flags) in proofs {
+            write!(f, "{}: {}", name, result)?;
+            let notes = flags.notes();
+            if !notes.is_empty() {
+                write!(f, " ({})", notes.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dimensions, maximum throughput and bottleneck of a graph, to get an overview of a design without a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphSummary {
+    /// Number of inputs
+    pub inputs: usize,
+    /// Number of outputs
+    pub outputs: usize,
+    /// Maximum throughput from all the inputs to all the outputs, see [`FlowGraphAnalysis::min_cut_value`]
+    pub max_throughput: GenericFraction<u128>,
+    /// Entity and capacity of the slowest of the [`FlowGraphAnalysis::critical_edges`]
+    pub bottleneck: Option<(EntityId, GenericFraction<u128>)>,
+}
+
+impl GraphSummary {
+    pub fn new(graph: &FlowGraph) -> Self {
+        let (inputs, outputs) = graph.io_dimensions();
+        let bottleneck = graph
+            .critical_edges()
+            .into_iter()
+            .map(|e| {
+                let (src, _) = graph.edge_endpoints(e).unwrap();
+                (graph[src].get_id(), graph[e].capacity)
+            })
+            .min_by_key(|(_, capacity)| *capacity);
+        Self {
+            inputs,
+            outputs,
+            max_throughput: graph.min_cut_value(),
+            bottleneck,
+        }
+    }
+}
+
+impl Display for GraphSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\u{2192}{}, max {}/s",
+            self.inputs, self.outputs, self.max_throughput
+        )?;
+        if let Some((id, capacity)) = self.bottleneck {
+            write!(f, ", bottleneck at entity {} ({}/s)", id, capacity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compiles the entities into a [`FlowGraph`] and simplifies it without the removed inputs and outputs.
+pub fn compile(entities: Vec<FBEntity<i32>>, options: &AnalyzeOptions) -> FlowGraph {
+    compile_with_report(entities, options).0
+}
+
+/// Same as [`compile`], but also returns the inputs and outputs that were pruned
+/// if [`AnalyzeOptions::prune_dead_io`] is set.
+pub fn compile_with_report(
+    entities: Vec<FBEntity<i32>>,
+    options: &AnalyzeOptions,
+) -> (FlowGraph, PrunedIo) {
+    let mut graph = Compiler::new(entities)
+        .with_io_rule(options.io_rule)
+        .create_graph_with_taps(&options.taps);
+    graph.remove_io(&options.removed_inputs, &options.removed_outputs);
+    let pruned = if options.prune_dead_io {
+        graph.prune_dead_io()
+    } else {
+        PrunedIo::default()
+    };
+    if !pruned.is_empty() {
+        info!("{}", pruned);
+    }
+    graph.simplify(&[], CoalesceStrength::Aggressive);
+    (graph, pruned)
+}
+
+#[cfg(feature = "z3")]
+/// Compiles the entities and proves all the properties on the resulting graph.
+pub fn analyze(entities: Vec<FBEntity<i32>>, options: &AnalyzeOptions) -> Analysis {
+    let start = Instant::now();
+    let graph = compile(entities.clone(), options);
+    let compile_time = start.elapsed();
+
+    prove_all(graph, entities, compile_time)
+}
+
+#[cfg(feature = "z3")]
+/// Compiles the entities and proves all the properties on each connected component separately.
+///
+/// The components are returned in the order of [`FlowGraphAnalysis::connected_components`],
+/// all of them share the same `compile_time`.
+pub fn analyze_components(entities: Vec<FBEntity<i32>>, options: &AnalyzeOptions) -> Vec<Analysis> {
+    let start = Instant::now();
+    let graph = compile(entities.clone(), options);
+    let compile_time = start.elapsed();
+
+    graph
+        .connected_components()
+        .iter()
+        .map(|nodes| {
+            prove_all(
+                graph.component_subgraph(nodes),
+                entities.clone(),
+                compile_time,
+            )
+        })
+        .collect()
+}
+
+/// Results of the proofs available without z3, see [`analyze_lp`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LpAnalysis {
+    /// Result of [`lp_belt_balancer`]
+    pub balancer: ProofResult,
+    /// Result of [`lp_equal_drain`] on the reversed graph
+    pub equal_drain: ProofResult,
+}
+
+/// Compiles the entities and proves the balancer and equal drain properties with the linear programming back-end.
+///
+/// This is the proof path for targets without z3, e.g. WASM.
+/// Throughput unlimited and universal balancers can only be proven by [`analyze`].
+pub fn analyze_lp(entities: Vec<FBEntity<i32>>, options: &AnalyzeOptions) -> LpAnalysis {
+    let graph = compile(entities, options);
+    LpAnalysis {
+        balancer: lp_belt_balancer(&graph),
+        equal_drain: lp_equal_drain(&Reversable::reverse(&graph)),
+    }
+}
+
+#[cfg(feature = "z3")]
+fn prove_all(graph: FlowGraph, entities: Vec<FBEntity<i32>>, compile_time: Duration) -> Analysis {
+    prove(graph, entities, compile_time, false)
+}
+
+#[cfg(feature = "z3")]
+/// Proves all the properties, or only the balancer property if `balancer_first` is set and it does not hold.
+/// The skipped properties are [`ProofResult::Invalid`], as they assume a balancer.
+fn prove(
+    graph: FlowGraph,
+    entities: Vec<FBEntity<i32>>,
+    compile_time: Duration,
+    balancer_first: bool,
+) -> Analysis {
+    let start = Instant::now();
+    let balancer = BlueprintProofEntity::new(graph.clone(), Some("QF_LRA"))
+        .model(belt_balancer_f, ModelFlags::empty());
+    if balancer_first && balancer != ProofResult::Sat {
+        return Analysis {
+            balancer,
+            equal_drain: ProofResult::Invalid,
+            throughput_unlimited: ProofResult::Invalid,
+            throughput_unlimited_flags: ModelFlags::empty(),
+            universal: ProofResult::Invalid,
+            universal_flags: ModelFlags::empty(),
+            compile_time,
+            proof_time: start.elapsed(),
+        };
+    }
+    let equal_drain = BlueprintProofEntity::new(Reversable::reverse(&graph), Some("QF_LRA"))
+        .model(equal_drain_f, ModelFlags::empty());
+    let mut tu_proof = BlueprintProofEntity::new(graph.clone(), None);
+    let throughput_unlimited = tu_proof.model(throughput_unlimited(entities), ModelFlags::Relaxed);
+    let mut universal_proof = BlueprintProofEntity::new(graph, None);
+    let universal = universal_proof.model(universal_balancer, ModelFlags::Blocked);
+    let proof_time = start.elapsed();
+
+    Analysis {
+        balancer,
+        equal_drain,
+        throughput_unlimited,
+        throughput_unlimited_flags: tu_proof.flags().unwrap_or_default(),
+        universal,
+        universal_flags: universal_proof.flags().unwrap_or_default(),
+        compile_time,
+        proof_time,
+    }
+}
+
+/// Category of a balancer, as balancers are commonly described
+///
+/// The categories are ordered from the weakest to the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BalancerClass {
+    /// The outputs are not always balanced
+    NotBalancer,
+    /// The outputs are balanced
+    Balancer,
+    /// A balancer whose inputs are also drained equally
+    EqualDrain,
+    /// A balancer that is also throughput unlimited
+    ThroughputUnlimited,
+    /// A balancer that also balances when some of its outputs back up
+    Universal,
+}
+
+impl Display for BalancerClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::NotBalancer => "Not a balancer",
+            Self::Balancer => "Balancer",
+            Self::EqualDrain => "Equal drain balancer",
+            Self::ThroughputUnlimited => "Throughput unlimited balancer",
+            Self::Universal => "Universal balancer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(feature = "z3")]
+impl Analysis {
+    /// Returns the strongest [`BalancerClass`] whose property holds.
+    ///
+    /// The properties of the weaker classes don't necessarily hold too,
+    /// e.g. a universal balancer need not be throughput unlimited, which the results of the proofs tell.
+    pub fn class(&self) -> BalancerClass {
+        if self.balancer != ProofResult::Sat {
+            BalancerClass::NotBalancer
+        } else if self.universal == ProofResult::Sat {
+            BalancerClass::Universal
+        } else if self.throughput_unlimited == ProofResult::Sat {
+            BalancerClass::ThroughputUnlimited
+        } else if self.equal_drain == ProofResult::Sat {
+            BalancerClass::EqualDrain
+        } else {
+            BalancerClass::Balancer
+        }
+    }
+}
+
+#[cfg(feature = "z3")]
+/// Classifies the graph into a [`BalancerClass`], returning the class together with the results of the proofs.
+///
+/// The other properties are only proven if the graph is a balancer, otherwise they are [`ProofResult::Invalid`].
+/// The `compile_time` of the returned [`Analysis`] is zero, as the graph is already compiled.
+pub fn classify(graph: &FlowGraph, entities: Vec<FBEntity<i32>>) -> (BalancerClass, Analysis) {
+    let analysis = prove(graph.clone(), entities, Duration::ZERO, true);
+    (analysis.class(), analysis)
+}
+
+#[cfg(feature = "z3")]
+/// Analyzes the entities after changing the output priority of the splitter with the given `id`,
+/// e.g. to find out whether another priority makes the design throughput unlimited.
+///
+/// Returns `None` if there is no splitter with the given `id`.
+pub fn analyze_with_output_priority(
+    entities: &[FBEntity<i32>],
+    options: &AnalyzeOptions,
+    id: EntityId,
+    priority: Priority,
+) -> Option<Analysis> {
+    let entities = with_output_priority(entities, id, priority)?;
+    Some(analyze(entities, options))
+}
+
+/// Returns a copy of the entities with the output priority of the splitter with the given `id` changed.
+///
+/// Returns `None` if there is no splitter with the given `id`.
+pub fn with_output_priority(
+    entities: &[FBEntity<i32>],
+    id: EntityId,
+    priority: Priority,
+) -> Option<Vec<FBEntity<i32>>> {
+    let mut found = false;
+    let entities = entities
+        .iter()
+        .map(|e| match e {
+            FBEntity::Splitter(s) if s.base.id == id => {
+                found = true;
+                FBEntity::Splitter(s.with_output_priority(priority))
+            }
+            e => *e,
+        })
+        .collect();
+    found.then_some(entities)
+}
+
+/// Throughputs of the belt tiers: yellow, red, blue and turbo
+pub const BELT_TIERS: [f64; 4] = [15.0, 30.0, 45.0, 60.0];
+
+/// Returns a copy of the entities with all the belts, underground belts and splitters that are
+/// slower than `throughput` upgraded to it. Faster entities are kept as they are.
+pub fn with_min_belt_tier(entities: &[FBEntity<i32>], throughput: f64) -> Vec<FBEntity<i32>> {
+    entities
+        .iter()
+        .map(|e| {
+            let mut e = *e;
+            if matches!(
+                e,
+                FBEntity::Belt(_)
+                    | FBEntity::Underground(_)
+                    | FBEntity::Splitter(_)
+                    | FBEntity::SplitterPhantom(_)
+            ) {
+                let base = e.get_base_mut();
+                base.throughput = base.throughput.max(throughput);
+            }
+            e
+        })
+        .collect()
+}
+
+/// Returns a copy of the entities with the belt of each output in `caps`, identified by the id of its last tile,
+/// set to the given throughput, e.g. to assume blue outputs even though they are drawn yellow.
+///
+/// The belt consists of the belt and underground tiles feeding the output one-to-one, up to the splitter or merge
+/// in front of it. Proving [`throughput_unlimited`](crate::backends::throughput_unlimited) on the graph compiled
+/// from the returned entities then bounds both the demand on the outputs and the capacities of their belts by the caps.
+pub fn with_output_caps(
+    entities: &[FBEntity<i32>],
+    caps: &HashMap<EntityId, f64>,
+) -> Vec<FBEntity<i32>> {
+    let tiles = entities
+        .iter()
+        .map(|e| (e.get_base().position, e))
+        .collect::<HashMap<_, _>>();
+    let feeds_from = Compiler::new(entities.to_vec()).feeds_from;
+    let capped = entities
+        .iter()
+        .filter_map(|e| Some((e.get_base().position, *caps.get(&e.get_base().id)?)))
+        .flat_map(|(pos, cap)| {
+            belt_tiles(&tiles, &feeds_from, pos)
+                .into_iter()
+                .map(move |tile| (tile, cap))
+        })
+        .collect::<HashMap<_, _>>();
+    entities
+        .iter()
+        .map(|e| {
+            let mut e = *e;
+            if matches!(e, FBEntity::Belt(_) | FBEntity::Underground(_)) {
+                let base = e.get_base_mut();
+                if let Some(cap) = capped.get(&base.position) {
+                    base.throughput = *cap;
+                }
+            }
+            e
+        })
+        .collect()
+}
+
+#[cfg(feature = "z3")]
+/// Returns the lowest of the [`BELT_TIERS`] that makes the design throughput unlimited
+/// when all the slower belts are upgraded to it, see [`with_min_belt_tier`].
+///
+/// Returns `None` if the design is not throughput unlimited even when upgrading to the highest tier.
+pub fn min_throughput_unlimited_tier(
+    entities: &[FBEntity<i32>],
+    options: &AnalyzeOptions,
+) -> Option<f64> {
+    BELT_TIERS.into_iter().find(|tier| {
+        let upgraded = with_min_belt_tier(entities, *tier);
+        let graph = compile(upgraded.clone(), options);
+        let res = BlueprintProofEntity::new(graph, None)
+            .model(throughput_unlimited(upgraded), ModelFlags::Relaxed);
+        res == ProofResult::Sat
+    })
+}
+
+/// Maximum number of inputs plus outputs for which [`TuMethod::MaxFlow`] is tried,
+/// as it computes a minimum cut for each pair of subsets of the inputs and the outputs
+pub const MAX_FLOW_IO_LIMIT: usize = 16;
+
+/// Method that decided whether a design is throughput unlimited, see [`prove_throughput_unlimited`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TuMethod {
+    /// The quantified [`throughput_unlimited`] proof
+    #[default]
+    Quantified,
+    /// A minimum cut between each pair of subsets of the inputs and the outputs
+    MaxFlow,
+    /// The minimum cut between all the inputs and all the outputs, which can only refute the property
+    FullLoad,
+}
+
+impl Display for TuMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Quantified => "quantified proof",
+            Self::MaxFlow => "max-flow",
+            Self::FullLoad => "full load bound",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(feature = "z3")]
+/// Proves that the graph is throughput unlimited, falling back to simpler methods if the
+/// quantified [`throughput_unlimited`] proof does not finish within `timeout`.
+///
+/// The proof uses the [`ModelFlags::Relaxed`] model, which is a plain flow network. So the design is
+/// throughput unlimited iff the minimum cut between any subset of the inputs and any subset of the outputs
+/// is at least the throughput of the smaller of the two, which is checked for up to [`MAX_FLOW_IO_LIMIT`]
+/// inputs and outputs. Otherwise only the cut between all the inputs and all the outputs is checked,
+/// which can show that the design is not throughput unlimited but not that it is. If it doesn't, the
+/// inconclusive result of the quantified proof, e.g. [`ProofResult::Timeout`], is returned: the property is
+/// valid and supported, it just wasn't decided, so neither `Invalid` nor `Unsupported` would be accurate.
+///
+/// Returns the result together with the method that produced it.
+pub fn prove_throughput_unlimited(
+    graph: &FlowGraph,
+    entities: Vec<FBEntity<i32>>,
+    timeout: Duration,
+) -> (ProofResult, TuMethod) {
+    let mut proof = BlueprintProofEntity::with_timeout(graph.clone(), None, timeout);
+    let res = proof.model(throughput_unlimited(entities.clone()), ModelFlags::Relaxed);
+    if res.is_conclusive() {
+        return (res, TuMethod::Quantified);
+    }
+    throughput_unlimited_by_cuts(graph, &entities, MAX_FLOW_IO_LIMIT, res)
+}
+
+#[cfg(feature = "z3")]
+/// Fallback of [`prove_throughput_unlimited`] checking the minimum cuts, for all the subsets of the inputs
+/// and outputs if there are at most `io_limit` of them. Returns `undecided` if the full load bound holds.
+fn throughput_unlimited_by_cuts(
+    graph: &FlowGraph,
+    entities: &[FBEntity<i32>],
+    io_limit: usize,
+    undecided: ProofResult,
+) -> (ProofResult, TuMethod) {
+    let ids = |f: fn(&Node) -> bool| {
+        graph
+            .node_weights()
+            .filter(|n| f(n))
+            .map(Node::get_id)
+            .collect::<Vec<_>>()
+    };
+    let inputs = ids(|n| matches!(n, Node::Input(_)));
+    let outputs = ids(|n| matches!(n, Node::Output(_)));
+    let throughput = |ids: &[EntityId]| {
+        ids.iter()
+            .map(|id| {
+                let throughput = entities
+                    .iter()
+                    .find(|e| e.get_base().id == *id)
+                    .map_or(0.0, |e| e.get_base().throughput);
+                exact_throughput(throughput).unwrap_or(throughput.into())
+            })
+            .fold(GenericFraction::from(0), |acc, t| acc + t)
+    };
+    /* the minimum cut between the selected inputs and outputs carries the throughput of the smaller side */
+    let unlimited_between = |selected_inputs: &[EntityId], selected_outputs: &[EntityId]| {
+        let mut subgraph = graph.clone();
+        let removed_inputs = inputs
+            .iter()
+            .filter(|id| !selected_inputs.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        let removed_outputs = outputs
+            .iter()
+            .filter(|id| !selected_outputs.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        subgraph.remove_io(&removed_inputs, &removed_outputs);
+        let bound = throughput(selected_inputs).min(throughput(selected_outputs));
+        subgraph.min_cut_value() >= bound
+    };
+
+    if inputs.len() + outputs.len() <= io_limit {
+        let subsets = |ids: &[EntityId]| {
+            (1..1usize << ids.len())
+                .map(|mask| {
+                    ids.iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << i) != 0)
+                        .map(|(_, id)| *id)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let output_subsets = subsets(&outputs);
+        let is_tu = subsets(&inputs).iter().all(|selected_inputs| {
+            output_subsets
+                .iter()
+                .all(|selected_outputs| unlimited_between(selected_inputs, selected_outputs))
+        });
+        let res = if is_tu {
+            ProofResult::Sat
+        } else {
+            ProofResult::Unsat
+        };
+        return (res, TuMethod::MaxFlow);
+    }
+
+    let res = if unlimited_between(&inputs, &outputs) {
+        undecided
+    } else {
+        ProofResult::Unsat
+    };
+    (res, TuMethod::FullLoad)
+}
+
+/// Rate at which the inserters feeding an assembler supply it, compared to the rate it consumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssemblerSupply {
+    /// Id of the assembler
+    pub id: EntityId,
+    /// Items/s the inserters can move from the belts into the assembler
+    pub supply: GenericFraction<u128>,
+    /// Items/s the assembler consumes at its crafting speed
+    pub required: GenericFraction<u128>,
+}
+
+impl AssemblerSupply {
+    /// Returns the items/s missing to keep the assembler running, zero if it is fed enough
+    pub fn shortfall(&self) -> GenericFraction<u128> {
+        if self.supply < self.required {
+            self.required - self.supply
+        } else {
+            GenericFraction::from(0)
+        }
+    }
+}
+
+impl Display for AssemblerSupply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Assembler {}: supplied {}/s, requires {}/s",
+            self.id, self.supply, self.required
+        )
+    }
+}
+
+/// Returns the tiles of the belt ending at `pos`, starting with `pos` and walking upstream through the belt
+/// and underground tiles that feed each other one-to-one. Splitters and merges start a new belt.
+fn belt_tiles(
+    tiles: &HashMap<Position<i32>, &FBEntity<i32>>,
+    feeds_from: &RelMap<Position<i32>>,
+    pos: Position<i32>,
+) -> Vec<Position<i32>> {
+    let is_belt = |pos: &Position<i32>| {
+        matches!(
+            tiles.get(pos),
+            Some(FBEntity::Belt(_)) | Some(FBEntity::Underground(_))
+        )
+    };
+    /* the number of belt tiles fed by `pos`, ignoring inserters picking up from it */
+    let feeds_to_count = |pos: &Position<i32>| {
+        feeds_from
+            .iter()
+            .filter(|(dest, sources)| is_belt(dest) && sources.contains(pos))
+            .count()
+    };
+    let mut belt = vec![pos];
+    let mut visited = HashSet::from([pos]);
+    while let Some(sources) = feeds_from.get(belt.last().unwrap()) {
+        let mut sources = sources.iter();
+        match (sources.next(), sources.next()) {
+            (Some(source), None)
+                if is_belt(belt.last().unwrap())
+                    && is_belt(source)
+                    && feeds_to_count(source) == 1
+                    && visited.insert(*source) =>
+            {
+                belt.push(*source)
+            }
+            _ => break,
+        }
+    }
+    belt
+}
+
+/// Returns the supply of each assembler fed by inserters picking up from belts, sorted by the id of the assembler.
+///
+/// An assembler at crafting speed 1 consumes `consumption` items/s, which depends on its recipe,
+/// so an assembler requires `consumption` times its crafting speed. Each inserter supplies at most
+/// its own throughput. Inserters picking up from the same belt, i.e. a chain of belt and underground
+/// tiles feeding each other one-to-one, share its throughput: if they draw more than the slowest
+/// tile they pick up from, each of them is scaled down proportionally. Inserters picking up from
+/// anything but a belt, e.g. another assembler, are ignored.
+pub fn assembler_supply(entities: &[FBEntity<i32>], consumption: f64) -> Vec<AssemblerSupply> {
+    let exact = |throughput: f64| exact_throughput(throughput).unwrap_or(throughput.into());
+    let tiles = entities
+        .iter()
+        .map(|e| (e.get_base().position, e))
+        .collect::<HashMap<_, _>>();
+
+    let mut supplies = entities
+        .iter()
+        .filter_map(|e| match e {
+            FBEntity::Assembler(a) => Some((
+                a.base.id,
+                AssemblerSupply {
+                    id: a.base.id,
+                    supply: GenericFraction::from(0),
+                    required: exact(consumption) * exact(a.base.throughput),
+                },
+            )),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let feeds_from = Compiler::new(entities.to_vec()).feeds_from;
+    let belt_head = |pos: Position<i32>| *belt_tiles(&tiles, &feeds_from, pos).last().unwrap();
+
+    /* (belt throughput, [(assembler, inserter throughput)]) of each belt */
+    let mut belts = HashMap::<_, (GenericFraction<u128>, Vec<_>)>::new();
+    for e in entities {
+        let (source, destination) = match e {
+            FBEntity::Inserter(i) => (i.get_source(), i.get_destination()),
+            FBEntity::LongInserter(l) => (l.get_source(), l.get_destination()),
+            _ => continue,
+        };
+        let belt_throughput = match tiles.get(&source) {
+            Some(FBEntity::Belt(b)) => b.base.throughput,
+            Some(FBEntity::Underground(u)) => u.base.throughput,
+            Some(FBEntity::Splitter(s)) => s.base.throughput,
+            Some(FBEntity::SplitterPhantom(s)) => s.base.throughput,
+            _ => continue,
+        };
+        let assembler = match tiles.get(&destination) {
+            Some(FBEntity::Assembler(a)) => a.base.id,
+            Some(FBEntity::AssemblerPhantom(a)) => a.base.id,
+            _ => continue,
+        };
+        let (throughput, inserters) = belts
+            .entry(belt_head(source))
+            .or_insert((exact(belt_throughput), vec![]));
+        *throughput = (*throughput).min(exact(belt_throughput));
+        inserters.push((assembler, exact(e.get_base().throughput)));
+    }
+
+    for (throughput, inserters) in belts.into_values() {
+        let drawn = inserters
+            .iter()
+            .fold(GenericFraction::from(0), |acc, (_, rate)| acc + *rate);
+        for (assembler, rate) in inserters {
+            let rate = if drawn > throughput {
+                rate * throughput / drawn
+            } else {
+                rate
+            };
+            supplies.get_mut(&assembler).unwrap().supply += rate;
+        }
+    }
+
+    let mut supplies = supplies.into_values().collect::<Vec<_>>();
+    supplies.sort_by_key(|s| s.id);
+    supplies
+}
+
+#[cfg(feature = "z3")]
+/// Imports a blueprint string and analyzes it, see [`analyze`].
+pub fn analyze_blueprint_string(blueprint: &str, options: &AnalyzeOptions) -> Result<Analysis> {
+    let entities = string_to_entities(blueprint)?;
+    Ok(analyze(entities, options))
+}
+
+#[cfg(feature = "z3")]
+/// Imports a blueprint book and analyzes each blueprint in it, see [`analyze`] and [`string_to_book`].
+///
+/// The blueprints are proven concurrently on up to [`thread::available_parallelism`] threads,
+/// the results are returned with the label of their blueprint in the order of the book.
+/// The same `options` are used for every blueprint.
+pub fn analyze_book(blueprint: &str, options: &AnalyzeOptions) -> Result<Vec<(String, Analysis)>> {
+    let book = string_to_book(blueprint)?;
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(book.len());
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((_, entities)) = book.get(i) else {
+                            return results;
+                        };
+                        results.push((i, analyze(entities.clone(), options)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results
+        .into_iter()
+        .map(|(i, analysis)| (book[i].0.clone(), analysis))
+        .collect())
+}
+
+#[cfg(feature = "z3")]
+/// Analyzes every blueprint string in the directory at `path`, e.g. to regression-test a library of balancers.
+///
+/// Files that can't be read or don't contain a blueprint string are skipped, subdirectories are not visited.
+/// The results are sorted by path.
+pub fn verify_dir(path: impl AsRef<Path>) -> Result<Vec<(PathBuf, Analysis)>> {
+    verify_dir_with(path, &AnalyzeOptions::default())
+}
+
+#[cfg(feature = "z3")]
+/// Same as [`verify_dir`], but analyzes every blueprint with the given `options`,
+/// e.g. [`IoRule::Boundary`] for balancers whose splitters have open sides inside the design.
+pub fn verify_dir_with(
+    path: impl AsRef<Path>,
+    options: &AnalyzeOptions,
+) -> Result<Vec<(PathBuf, Analysis)>> {
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    let mut results = vec![];
+    for path in paths.into_iter().filter(|p| p.is_file()) {
+        let entities = fs::read_to_string(&path)
+            .map_err(ImportError::from)
+            .and_then(|blueprint| string_to_entities(&blueprint));
+        match entities {
+            Ok(entities) => results.push((path, analyze(entities, options))),
+            Err(err) => warn!("Skipping {}: {}", path.display(), err),
+        }
+    }
+    Ok(results)
+}
+
+/* most of the tests run the z3 proofs, the linear programming back-end is also tested on its own */
+#[cfg(all(test, feature = "z3"))]
+mod tests {
+    use std::fs;
+
+    use crate::{entities::UndergroundReach, import::file_to_entities};
+
+    use super::*;
+
+    #[test]
+    fn analyze_4_4() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let analysis = std::thread::spawn(move || analyze(entities, &AnalyzeOptions::default()))
+            .join()
+            .unwrap();
+        assert_eq!(analysis.balancer, ProofResult::Sat);
+        assert_eq!(analysis.equal_drain, ProofResult::Sat);
+    }
+
+    #[test]
+    fn analyze_lp_4_4() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let analysis = analyze_lp(entities, &AnalyzeOptions::default());
+        assert_eq!(analysis.balancer, ProofResult::Sat);
+        assert_eq!(analysis.equal_drain, ProofResult::Sat);
+    }
+
+    #[test]
+    fn analyze_book() {
+        let book = fs::read_to_string("tests/book").unwrap();
+        let results = super::analyze_book(&book, &AnalyzeOptions::default()).unwrap();
+        let results = results
+            .iter()
+            .map(|(label, analysis)| (label.as_str(), analysis.balancer))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                ("4-4", ProofResult::Sat),
+                ("4-4 broken", ProofResult::Unsat)
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_library() {
+        let results = verify_dir("tests/library").unwrap();
+        let results = results
+            .iter()
+            .map(|(path, analysis)| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                (name, analysis.balancer)
+            })
+            .collect::<Vec<_>>();
+        // `README.md` is not a blueprint and skipped
+        assert_eq!(
+            results,
+            [
+                ("4-4", ProofResult::Sat),
+                ("4-4-broken", ProofResult::Unsat)
+            ]
+        );
+    }
+
+    /// Runs the whole pipeline on well-known designs and compares the verdicts with their accepted classes.
+    ///
+    /// Balancers are delimited by the belts at their edge. Splitters of the 4-4 universal, the 6-3 and the tree balancers
+    /// have open sides inside the design, which only the boundary rule does not mistake for inputs and outputs.
+    #[test]
+    fn community_balancers() {
+        let options = AnalyzeOptions {
+            io_rule: IoRule::Boundary,
+            ..Default::default()
+        };
+        let results = verify_dir_with("tests/community", &options).unwrap();
+        let classes = results
+            .iter()
+            .map(|(path, analysis)| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                (name, analysis.class())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            classes,
+            [
+                ("2-2-input-priority", BalancerClass::Universal),
+                ("2-4-tree", BalancerClass::EqualDrain),
+                ("4-lane-compactor", BalancerClass::NotBalancer),
+                ("bus-tap-priority", BalancerClass::NotBalancer),
+            ]
+        );
+        // the input priority does not change how the outputs are balanced, but the inputs are drained unequally
+        assert_eq!(results[0].1.equal_drain, ProofResult::Unsat);
+
+        // the balancers of the other tests
+        for (name, class) in [
+            ("2-2", BalancerClass::Universal),
+            ("3-3", BalancerClass::EqualDrain),
+            ("4-4-ntu", BalancerClass::EqualDrain),
+            ("4-4-tu", BalancerClass::ThroughputUnlimited),
+            ("4-4-tu-express-io", BalancerClass::EqualDrain),
+            ("4-4-univ", BalancerClass::Universal),
+            ("6-3-ntu", BalancerClass::EqualDrain),
+            ("6-3-tu", BalancerClass::ThroughputUnlimited),
+        ] {
+            let entities = file_to_entities(&format!("tests/{}", name)).unwrap();
+            assert_eq!(analyze(entities, &options).class(), class, "{}", name);
+        }
+
+        // with every open end as an input or output, the open splitter sides unbalance the 6-3 balancer
+        let entities = file_to_entities("tests/6-3-ntu").unwrap();
+        let analysis = analyze(entities, &AnalyzeOptions::default());
+        assert_eq!(analysis.balancer, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn underfed_assembler() {
+        // both assembling machines 2 craft at 0.75 and consume 2 * 0.75 = 1.5 items/s,
+        // one is fed by an inserter (0.83 items/s), the other by a fast inserter (2.31 items/s)
+        let entities = file_to_entities("tests/underfed_assembler").unwrap();
+        let supplies = assembler_supply(&entities, 2.0);
+        assert_eq!(supplies.len(), 2);
+        let required = GenericFraction::new(3u128, 2u128);
+        assert!(supplies.iter().all(|s| s.required == required));
+
+        assert_eq!(supplies[0].supply, GenericFraction::new(83u128, 100u128));
+        assert_eq!(
+            supplies[0].shortfall(),
+            GenericFraction::new(67u128, 100u128)
+        );
+        assert_eq!(supplies[1].supply, GenericFraction::new(231u128, 100u128));
+        assert_eq!(supplies[1].shortfall(), GenericFraction::from(0));
+
+        // the inserters pick up from the middle of the belt, an unsupported tee, so the graph only contains the belt
+        let graph = compile(entities, &AnalyzeOptions::default());
+        assert_eq!(graph.io_dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn inserters_share_belt() {
+        // slowing the belt down to 2 items/s, both inserters draw 0.83 + 2.31 = 3.14 items/s from it
+        let mut entities = file_to_entities("tests/underfed_assembler").unwrap();
+        for e in entities.iter_mut() {
+            if let FBEntity::Belt(b) = e {
+                b.base.throughput = 2.0;
+            }
+        }
+        let supplies = assembler_supply(&entities, 2.0);
+        assert_eq!(supplies[0].supply, GenericFraction::new(83u128, 157u128));
+        assert_eq!(supplies[1].supply, GenericFraction::new(231u128, 157u128));
+        assert_eq!(
+            supplies[0].supply + supplies[1].supply,
+            GenericFraction::from(2)
+        );
+    }
+
+    #[test]
+    fn classify_fixtures() {
+        let expected = [
+            ("2-2", BalancerClass::Universal),
+            ("3-2-broken", BalancerClass::NotBalancer),
+            ("3-3", BalancerClass::EqualDrain),
+            ("4-4-ntu", BalancerClass::EqualDrain),
+            ("4-4-tu", BalancerClass::ThroughputUnlimited),
+        ];
+        for (file, class) in expected {
+            let entities = file_to_entities(&format!("tests/{}", file)).unwrap();
+            let graph = compile(entities.clone(), &AnalyzeOptions::default());
+            let (actual, analysis) = classify(&graph, entities);
+            assert_eq!(actual, class, "{}", file);
+            if class == BalancerClass::NotBalancer {
+                // the other properties are not proven for a non-balancer
+                assert_eq!(analysis.throughput_unlimited, ProofResult::Invalid);
+            }
+        }
+    }
+
+    #[test]
+    fn report_model_flags() {
+        let entities = file_to_entities("tests/4-4-tu").unwrap();
+        let analysis = analyze(entities, &AnalyzeOptions::default());
+        assert_eq!(analysis.throughput_unlimited, ProofResult::Sat);
+        assert_eq!(analysis.throughput_unlimited_flags, ModelFlags::Relaxed);
+
+        let report = analysis.to_string();
+        assert!(report.contains("Balancer: Yes\n"));
+        assert!(report
+            .contains("Throughput unlimited: Yes (splitter conditions relaxed (sound for TU))\n"));
+        assert!(report.contains("outputs may be blocked"));
+    }
+
+    #[test]
+    fn analyze_string_3_2_broken() {
+        let blueprint = fs::read_to_string("tests/3-2-broken").unwrap();
+        let options = AnalyzeOptions {
+            removed_inputs: vec![4, 5, 6],
+            removed_outputs: vec![4, 5, 6],
+            ..Default::default()
+        };
+        let analysis = analyze_blueprint_string(&blueprint, &options).unwrap();
+        assert_eq!(analysis.balancer, ProofResult::Unsat);
+
+        assert!(analyze_blueprint_string("not a blueprint", &options).is_err());
+    }
+
+    #[test]
+    fn analyze_two_balancers() {
+        let entities = file_to_entities("tests/two_balancers").unwrap();
+        let options = AnalyzeOptions::default();
+        let components = analyze_components(entities.clone(), &options);
+        let results = components.iter().map(|a| a.balancer).collect::<Vec<_>>();
+        assert_eq!(results, [ProofResult::Sat, ProofResult::Unsat]);
+        assert_eq!(analyze(entities, &options).balancer, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn flip_output_priority() {
+        let entities = file_to_entities("tests/prio_splitter").unwrap();
+        let options = AnalyzeOptions::default();
+        assert_eq!(
+            analyze(entities.clone(), &options).balancer,
+            ProofResult::Unsat
+        );
+
+        let analysis = analyze_with_output_priority(&entities, &options, 1, Priority::None);
+        assert_eq!(analysis.unwrap().balancer, ProofResult::Sat);
+        assert!(analyze_with_output_priority(&entities, &options, 2, Priority::None).is_none());
+    }
+
+    #[test]
+    fn analyze_tap() {
+        let entities = file_to_entities("tests/simple_belt").unwrap();
+        // only keep the part of the belt before the tap
+        let options = AnalyzeOptions {
+            removed_inputs: vec![2],
+            removed_outputs: vec![3],
+            taps: vec![2],
+            ..Default::default()
+        };
+        let graph = compile(entities.clone(), &options);
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph.edge_count(), 1);
+        assert_eq!(analyze(entities, &options).balancer, ProofResult::Sat);
+    }
+
+    /// A stub of belt side-loading into a closed loop can not reach any output
+    #[test]
+    fn prune_dead_io() {
+        let entities = file_to_entities("tests/dead_stub").unwrap();
+        let (graph, pruned) = compile_with_report(entities.clone(), &AnalyzeOptions::default());
+        assert!(pruned.is_empty());
+        assert_eq!(graph.io_dimensions(), (2, 1));
+
+        let options = AnalyzeOptions {
+            prune_dead_io: true,
+            ..Default::default()
+        };
+        let (graph, pruned) = compile_with_report(entities.clone(), &options);
+        assert_eq!(pruned.inputs, vec![4]);
+        assert!(pruned.outputs.is_empty());
+        assert_eq!(graph.io_dimensions(), (1, 1));
+        assert_eq!(analyze(entities, &options).balancer, ProofResult::Sat);
+    }
+
+    /// Deselecting an output in the GUI removes it from the graph, so the proofs only concern the remaining outputs
+    #[test]
+    fn deselected_output() {
+        let entities = file_to_entities("tests/4-4-tu").unwrap();
+        let output_ids = |graph: &FlowGraph| {
+            let mut ids = graph
+                .node_weights()
+                .filter(|n| matches!(n, crate::ir::Node::Output(_)))
+                .map(|n| n.get_id())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        // the outputs can be selected by the ids of the belts, before simplifying.
+        // The outputs of this 4-4 balancer are belts, so that each id selects a single output.
+        let raw = Compiler::new(entities.clone()).create_graph();
+        let outputs = output_ids(&raw);
+        assert_eq!(outputs.len(), 4);
+        let removed = outputs[0];
+
+        let mut without = raw.clone();
+        without.remove_io(&[], &[removed]);
+        assert_eq!(output_ids(&without), outputs[1..]);
+
+        let options = AnalyzeOptions {
+            removed_outputs: vec![removed],
+            ..Default::default()
+        };
+        let graph = compile(entities.clone(), &options);
+        assert_eq!(output_ids(&graph).len(), 3);
+
+        // the exclude list of `simplify` removes the output as well
+        let mut excluded = raw;
+        excluded.simplify(&[removed], CoalesceStrength::Aggressive);
+        assert_eq!(output_ids(&excluded), output_ids(&graph));
+
+        BlueprintProofEntity::new(graph, Some("QF_LRA")).model(
+            |p| {
+                assert_eq!(p.output_bounds.len(), 3);
+                belt_balancer_f(p)
+            },
+            ModelFlags::empty(),
+        );
+    }
+
+    #[test]
+    fn throughput_unlimited_tier() {
+        // a TU 4-4 balancer built with yellow belts, but fed by and feeding express belts
+        let entities = file_to_entities("tests/4-4-tu-express-io").unwrap();
+        let options = AnalyzeOptions::default();
+        assert_eq!(
+            min_throughput_unlimited_tier(&entities, &options),
+            Some(45.0)
+        );
+
+        let entities = file_to_entities("tests/4-4-tu").unwrap();
+        assert_eq!(
+            min_throughput_unlimited_tier(&entities, &options),
+            Some(15.0)
+        );
+        let entities = file_to_entities("tests/4-4-ntu").unwrap();
+        assert_eq!(min_throughput_unlimited_tier(&entities, &options), None);
+    }
+
+    /// The caps of the outputs change the belts of the compiled graph, not only the demand on the outputs
+    #[test]
+    fn output_caps() {
+        let options = AnalyzeOptions::default();
+        let tu = |entities: &[FBEntity<i32>], caps: &[(EntityId, f64)]| {
+            let entities = with_output_caps(entities, &caps.iter().copied().collect());
+            let graph = compile(entities.clone(), &options);
+            prove_throughput_unlimited(&graph, entities, Duration::from_secs(60)).0
+        };
+        let caps = |outputs: [EntityId; 4], cap: f64| outputs.map(|id| (id, cap));
+
+        // express balancers drawn with yellow outputs
+        let entities = with_min_belt_tier(&file_to_entities("tests/4-4-tu").unwrap(), 45.0);
+        let tu_outputs = [32, 33, 34, 35];
+        let drawn = with_output_caps(&entities, &caps(tu_outputs, 15.0).into());
+        assert_eq!(tu(&drawn, &[]), ProofResult::Sat);
+        // the express belts behind the outputs keep up with express outputs
+        assert_eq!(tu(&drawn, &caps(tu_outputs, 45.0)), ProofResult::Sat);
+
+        // two full inputs of the same input splitter can't reach the two inner outputs at express speed,
+        // but they can at the speed of yellow outputs
+        let entities = with_min_belt_tier(&file_to_entities("tests/4-4-ntu").unwrap(), 45.0);
+        let ntu_outputs = [17, 18, 19, 20];
+        let drawn = with_output_caps(&entities, &caps(ntu_outputs, 15.0).into());
+        assert_eq!(tu(&drawn, &[]), ProofResult::Sat);
+        assert_eq!(tu(&drawn, &caps(ntu_outputs, 45.0)), ProofResult::Unsat);
+        // fractional caps are not truncated to 15
+        assert_eq!(tu(&drawn, &caps(ntu_outputs, 15.5)), ProofResult::Unsat);
+    }
+
+    #[test]
+    fn throughput_unlimited_fallback() {
+        let options = AnalyzeOptions::default();
+        for (file, expected) in [
+            ("tests/4-4-tu", ProofResult::Sat),
+            ("tests/4-4-ntu", ProofResult::Unsat),
+            ("tests/6-3-tu", ProofResult::Sat),
+            ("tests/6-3-ntu", ProofResult::Unsat),
+        ] {
+            let entities = file_to_entities(file).unwrap();
+            let graph = compile(entities.clone(), &options);
+            let quantified =
+                prove_throughput_unlimited(&graph, entities.clone(), Duration::from_secs(60));
+            assert_eq!(quantified, (expected, TuMethod::Quantified), "{}", file);
+
+            let max_flow = throughput_unlimited_by_cuts(
+                &graph,
+                &entities,
+                MAX_FLOW_IO_LIMIT,
+                ProofResult::Timeout,
+            );
+            assert_eq!(max_flow, (expected, TuMethod::MaxFlow), "{}", file);
+
+            // the full load bound can't show that these are throughput unlimited
+            let full_load =
+                throughput_unlimited_by_cuts(&graph, &entities, 0, ProofResult::Timeout);
+            assert_eq!(
+                full_load,
+                (ProofResult::Timeout, TuMethod::FullLoad),
+                "{}",
+                file
+            );
+        }
+
+        // the cuts are compared with the exact throughputs, truncating them would bound a 0.75/s belt by 0
+        let reach = UndergroundReach::default().with_tier(0.75, 5);
+        for (file, expected) in [
+            ("tests/4-4-tu", ProofResult::Sat),
+            ("tests/4-4-ntu", ProofResult::Unsat),
+        ] {
+            let entities = file_to_entities(file)
+                .unwrap()
+                .into_iter()
+                .map(|mut e| {
+                    e.get_base_mut().throughput /= 20.0;
+                    e
+                })
+                .collect::<Vec<_>>();
+            let mut graph =
+                Compiler::with_underground_reach(entities.clone(), &reach).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let max_flow = throughput_unlimited_by_cuts(
+                &graph,
+                &entities,
+                MAX_FLOW_IO_LIMIT,
+                ProofResult::Timeout,
+            );
+            assert_eq!(max_flow, (expected, TuMethod::MaxFlow), "{}", file);
+        }
+
+        // the underground in between carries less than the belts around it
+        let entities = file_to_entities("tests/underground_bottleneck").unwrap();
+        let graph = compile(entities.clone(), &options);
+        let full_load = throughput_unlimited_by_cuts(&graph, &entities, 0, ProofResult::Timeout);
+        assert_eq!(full_load, (ProofResult::Unsat, TuMethod::FullLoad));
+    }
+
+    #[test]
+    fn graph_summary() {
+        let options = AnalyzeOptions::default();
+        let graph = compile(file_to_entities("tests/belt_reduction").unwrap(), &options);
+        let summary = GraphSummary::new(&graph);
+        assert_eq!((summary.inputs, summary.outputs), (1, 1));
+        assert_eq!(summary.max_throughput, GenericFraction::from(15));
+        assert_eq!(summary.bottleneck, Some((1, GenericFraction::from(15))));
+        assert_eq!(
+            summary.to_string(),
+            "1\u{2192}1, max 15/s, bottleneck at entity 1 (15/s)"
+        );
+
+        let graph = compile(file_to_entities("tests/4-4").unwrap(), &options);
+        let summary = GraphSummary::new(&graph);
+        assert_eq!((summary.inputs, summary.outputs), (4, 4));
+        assert_eq!(summary.max_throughput, GenericFraction::from(60));
+        assert_eq!(summary.bottleneck.unwrap().1, GenericFraction::from(15));
+
+        let summary = GraphSummary::new(&FlowGraph::default());
+        assert_eq!(summary.bottleneck, None);
+        assert_eq!(summary.to_string(), "0\u{2192}0, max 0/s");
+    }
+}
@@ -0,0 +1,241 @@
+//! Incremental proving of properties for changing selections of inputs and outputs
+
+use std::collections::HashSet;
+
+use petgraph::prelude::{EdgeIndex, NodeIndex};
+use z3::{ast::Bool, Context};
+
+use crate::{
+    entities::EntityId,
+    ir::{FlowGraph, GraphHelper, Node},
+};
+
+use super::{
+    model_graph::{encode_graph, to_primitives, Z3QuantHelper},
+    solver::{SolverBackend, Z3Backend},
+    ModelFlags, ProofPrimitives, ProofResult,
+};
+
+/// Model of a [`FlowGraph`] that is encoded into z3 only once and can then be checked for
+/// different selections of inputs and outputs.
+///
+/// Instead of removing the deselected inputs and outputs from the graph and encoding it again,
+/// their removal is represented as assumptions:
+/// edges that can't reach a selected output, or can't be reached from a selected input, are
+/// forced to carry no flow and splitters left with a single live output lose their splitter
+/// condition, as if they had been coalesced into a connector.
+///
+/// The graph should be encoded with all the inputs and outputs present and at most
+/// [`CoalesceStrength::Lossless`](crate::ir::CoalesceStrength::Lossless) simplification,
+/// so that the capacities are not shrunk for a specific selection.
+///
+/// The GUI does not keep a model between proofs, as it borrows its z3 context and graph,
+/// so it still encodes the graph again for each proof.
+///
+/// # Panics
+///
+/// The [`ModelFlags::Blocked`] model is not supported as the blocking constraints of splitters
+/// and mergers can't be toggled.
+pub struct IncrementalModel<'a> {
+    graph: &'a FlowGraph,
+    backend: Z3Backend<'a>,
+    helper: Z3QuantHelper<'a>,
+    primitives: ProofPrimitives<'a>,
+}
+
+impl<'a> IncrementalModel<'a> {
+    pub fn new(graph: &'a FlowGraph, ctx: &'a Context, flags: ModelFlags) -> Self {
+        assert!(
+            !flags.contains(ModelFlags::Blocked),
+            "The blocked model can't be checked incrementally"
+        );
+        let backend = Z3Backend::new(ctx, None);
+        let mut helper = encode_graph(graph, &backend, flags | ModelFlags::Guarded);
+        let primitives = to_primitives(graph, ctx, &mut helper);
+        Self {
+            graph,
+            backend,
+            helper,
+            primitives,
+        }
+    }
+
+    /// Checks the property `f` with the inputs and outputs in `removed` deselected.
+    ///
+    /// Returns the same result as [`model_f`](super::model_f) on a graph simplified with `removed` as exclude list.
+    pub fn check<F>(&self, f: F, removed: &[EntityId]) -> ProofResult
+    where
+        F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
+    {
+        let primitives = self.select_io(removed);
+        let assumptions = self.removal_assumptions(removed);
+
+        self.backend.push();
+        self.backend.assert(&f(primitives));
+        let res = self.backend.check_assumptions(&assumptions);
+        self.backend.pop();
+        res.not()
+    }
+
+    /// Returns the [`ProofPrimitives`] without the inputs and outputs in `removed`
+    fn select_io(&self, removed: &[EntityId]) -> ProofPrimitives<'a> {
+        let mut primitives = self.primitives.clone();
+        let is_selected = |idx: &NodeIndex| !removed.contains(&self.graph[*idx].get_id());
+
+        primitives.input_map.retain(|idx, _| is_selected(idx));
+        primitives.input_bounds = primitives.input_map.values().cloned().collect();
+        primitives.output_map.retain(|idx, _| is_selected(idx));
+        primitives.output_bounds = primitives.output_map.values().cloned().collect();
+        primitives
+    }
+
+    /// Returns the assumptions killing the edges and splitters made superfluous by removing the
+    /// inputs and outputs in `removed`
+    fn removal_assumptions(&self, removed: &[EntityId]) -> Vec<Bool<'a>> {
+        let live = live_edges(self.graph, removed);
+
+        let dead_edges = self.helper.dead_edge_map.iter().map(|(idx, dead)| {
+            if live.contains(idx) {
+                dead.not()
+            } else {
+                dead.clone()
+            }
+        });
+        let active_splitters = self.helper.active_splitter_map.iter().map(|(idx, active)| {
+            let all_live = self
+                .graph
+                .out_edge_idx(*idx)
+                .iter()
+                .all(|e| live.contains(e));
+            if all_live {
+                active.clone()
+            } else {
+                active.not()
+            }
+        });
+        dead_edges.chain(active_splitters).collect()
+    }
+}
+
+/// Returns the edges that are reachable from a selected input and can reach a selected output.
+///
+/// These are exactly the edges that survive the coalescing after removing the inputs and outputs in `removed`.
+fn live_edges(graph: &FlowGraph, removed: &[EntityId]) -> HashSet<EdgeIndex> {
+    let selected = |kind: fn(&Node) -> bool| {
+        graph
+            .node_indices()
+            .filter(|idx| kind(&graph[*idx]) && !removed.contains(&graph[*idx].get_id()))
+            .collect::<Vec<_>>()
+    };
+    let inputs = selected(|n| matches!(n, Node::Input(_)));
+    let outputs = selected(|n| matches!(n, Node::Output(_)));
+
+    let from_inputs = reachable(inputs, |idx| graph.out_nodes(idx));
+    let to_outputs = reachable(outputs, |idx| graph.in_nodes(idx));
+
+    graph
+        .edge_indices()
+        .filter(|idx| {
+            let (src, dst) = graph.edge_endpoints(*idx).unwrap();
+            from_inputs.contains(&src) && to_outputs.contains(&dst)
+        })
+        .collect()
+}
+
+/// Returns all the nodes reachable from `start` following the `next` nodes
+fn reachable(
+    start: Vec<NodeIndex>,
+    next: impl Fn(NodeIndex) -> Vec<NodeIndex>,
+) -> HashSet<NodeIndex> {
+    let mut visited = HashSet::new();
+    let mut stack = start;
+    while let Some(idx) = stack.pop() {
+        if visited.insert(idx) {
+            stack.extend(next(idx));
+        }
+    }
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use z3::Config;
+
+    use super::*;
+    use crate::{
+        backends::{belt_balancer_f, model_f, model_graph::ENCODINGS},
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, FlowGraphFun},
+    };
+
+    /// Selections of removed inputs and outputs used by the proofs on the fixtures
+    fn selections() -> Vec<(&'static str, Vec<Vec<EntityId>>)> {
+        vec![
+            ("3-2-broken", vec![vec![], vec![4, 5, 6]]),
+            ("4-4", vec![vec![], vec![3]]),
+            ("6-3-tu", vec![vec![], vec![24, 36, 44]]),
+            ("6-3-ntu", vec![vec![], vec![25, 26]]),
+            (
+                "4-4-univ",
+                vec![vec![], vec![30, 33, 83, 55, 17, 46, 133, 71]],
+            ),
+        ]
+    }
+
+    #[test]
+    fn incremental_matches_rebuild() {
+        for (fixture, removed_lists) in selections() {
+            let entities = file_to_entities(&format!("tests/{}", fixture)).unwrap();
+            let mut full_graph = Compiler::new(entities).create_graph();
+            full_graph.simplify(&[], CoalesceStrength::Lossless);
+
+            let cfg = Config::new();
+            let ctx = Context::new(&cfg);
+            let incremental = IncrementalModel::new(&full_graph, &ctx, ModelFlags::empty());
+            for removed in removed_lists {
+                let mut graph = full_graph.clone();
+                graph.simplify(&removed, CoalesceStrength::Aggressive);
+                let rebuilt = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+                let checked = incremental.check(belt_balancer_f, &removed);
+                assert_eq!(rebuilt, checked, "{} with {:?} removed", fixture, removed);
+            }
+        }
+    }
+
+    /// Both give the same verdict for every selection, and the incremental model never encodes the graph again.
+    ///
+    /// The time spent on both is compared by the `output_selection` benchmark.
+    #[test]
+    fn incremental_encodes_once() {
+        let entities = file_to_entities("tests/4-4-univ").unwrap();
+        let mut full_graph = Compiler::new(entities).create_graph();
+        full_graph.simplify(&[], CoalesceStrength::Lossless);
+        let outputs = full_graph
+            .node_weights()
+            .filter(|n| matches!(n, Node::Output(_)))
+            .map(|n| n.get_id())
+            .collect::<Vec<_>>();
+
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let rebuilt = outputs
+            .iter()
+            .map(|output| {
+                let mut graph = full_graph.clone();
+                graph.simplify(&[*output], CoalesceStrength::Aggressive);
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty())
+            })
+            .collect::<Vec<_>>();
+
+        let incremental = IncrementalModel::new(&full_graph, &ctx, ModelFlags::empty());
+        let encodings = ENCODINGS.with(|n| n.get());
+        let checked = outputs
+            .iter()
+            .map(|output| incremental.check(belt_balancer_f, &[*output]))
+            .collect::<Vec<_>>();
+        assert_eq!(checked, rebuilt);
+        assert_eq!(ENCODINGS.with(|n| n.get()), encodings);
+    }
+}
@@ -0,0 +1,368 @@
+//! Linear programming back-end for the quantifier-free proofs, which does not depend on z3.
+//!
+//! The balancer and equal drain proofs only use linear arithmetic, apart from the case distinction of each splitter.
+//! This back-end encodes each property as a single mixed integer linear program, with a binary variable
+//! choosing the case of each splitter, and searches a counter-example by maximizing the difference between two outputs.
+//! The program is solved by [`microlp`], a solver written in plain Rust, using branch and bound on the binary variables.
+//! This allows running these proofs where z3 is not available, e.g. in a browser when compiled to WASM.
+//!
+//! The quantified proofs, i.e. `throughput_unlimited` and `universal_balancer`, are only available with z3.
+//!
+//! Unlike z3, the solver computes in floating point, so values within [`EPSILON`] are considered equal.
+
+use std::collections::HashMap;
+
+use microlp::{ComparisonOp, OptimizationDirection, Problem, Variable};
+use petgraph::{
+    prelude::{EdgeIndex, NodeIndex},
+    Direction::Outgoing,
+};
+
+use crate::{
+    entities::EntityId,
+    ir::{FlowGraph, GraphHelper, Node},
+    utils::Side,
+};
+
+use super::ProofResult;
+
+/// Tolerance of the floating point computations
+pub const EPSILON: f64 = 1e-6;
+
+/// Linear constraint `coeffs * x cmp rhs` over the variables of a [`Model`]
+type Constraint = (Vec<(Variable, f64)>, ComparisonOp, f64);
+
+/// Mixed integer linear program over bounded variables
+struct Model {
+    problem: Problem,
+    /// Upper bound of each variable, all of them are non-negative
+    bounds: Vec<f64>,
+}
+
+impl Model {
+    fn add_var(&mut self, objective: f64, max: f64) -> Variable {
+        self.bounds.push(max);
+        self.problem.add_var(objective, (0.0, max))
+    }
+
+    fn add_binary_var(&mut self) -> Variable {
+        self.bounds.push(1.0);
+        self.problem.add_binary_var(0.0)
+    }
+
+    /// Range of the values of `coeffs * x` within the bounds of the variables
+    fn range(&self, coeffs: &[(Variable, f64)]) -> (f64, f64) {
+        coeffs.iter().fold((0.0, 0.0), |(min, max), (var, coeff)| {
+            let value = coeff * self.bounds[var.idx()];
+            (min + value.min(0.0), max + value.max(0.0))
+        })
+    }
+
+    /// Adds the constraint, only enforced if the binary `indicator` is `enforced_at`.
+    ///
+    /// The constraint is relaxed by the smallest constant that makes it hold for all values within the bounds,
+    /// i.e. the usual big-M encoding with a tight M.
+    fn add_if(&mut self, (coeffs, cmp, rhs): &Constraint, indicator: Variable, enforced_at: bool) {
+        let (min, max) = self.range(coeffs);
+        /* (sign, slack) of `sign * coeffs * x <= sign * rhs + slack` */
+        let sides = match cmp {
+            ComparisonOp::Le => vec![(1.0, max - rhs)],
+            ComparisonOp::Ge => vec![(-1.0, rhs - min)],
+            ComparisonOp::Eq => vec![(1.0, max - rhs), (-1.0, rhs - min)],
+        };
+        for (sign, slack) in sides {
+            let slack = f64::max(slack, 0.0);
+            let mut relaxed = coeffs
+                .iter()
+                .map(|(var, coeff)| (*var, sign * coeff))
+                .collect::<Vec<_>>();
+            /* slack * indicator if enforced at 0, slack * (1 - indicator) if enforced at 1 */
+            let (coeff, rhs) = if enforced_at {
+                (slack, sign * rhs + slack)
+            } else {
+                (-slack, sign * rhs)
+            };
+            relaxed.push((indicator, coeff));
+            self.problem.add_constraint(relaxed, ComparisonOp::Le, rhs);
+        }
+    }
+
+    /// Returns whether some of the `values` can differ, or `None` if the solver failed.
+    ///
+    /// A binary variable selects the pair of values whose difference is maximized.
+    fn can_differ(mut self, values: &[Variable]) -> Option<bool> {
+        let Some((first, others)) = values.split_first() else {
+            return Some(false);
+        };
+        let pairs = others
+            .iter()
+            .flat_map(|v| [(*v, *first), (*first, *v)])
+            .collect::<Vec<_>>();
+        if pairs.is_empty() {
+            return Some(false);
+        }
+        let max = values
+            .iter()
+            .map(|v| self.bounds[v.idx()])
+            .fold(0.0, f64::max);
+        let difference = self.add_var(1.0, max);
+        let selected = pairs
+            .iter()
+            .map(|_| self.add_binary_var())
+            .collect::<Vec<_>>();
+        self.problem.add_constraint(
+            selected.iter().map(|z| (*z, 1.0)).collect::<Vec<_>>(),
+            ComparisonOp::Eq,
+            1.0,
+        );
+        for ((a, b), z) in pairs.into_iter().zip(selected) {
+            let constraint = (
+                vec![(difference, 1.0), (a, -1.0), (b, 1.0)],
+                ComparisonOp::Le,
+                0.0,
+            );
+            self.add_if(&constraint, z, true);
+        }
+        let solution = self.problem.solve().ok()?;
+        Some(solution.objective() > EPSILON)
+    }
+}
+
+/// Encodes the graph as a mixed integer linear program with a variable per edge,
+/// returning it together with the variables of the inputs and outputs.
+///
+/// As in the z3 model, the inputs and outputs with the same id, e.g. both sides of a splitter, share their throughput.
+fn encode(
+    graph: &FlowGraph,
+) -> (
+    Model,
+    HashMap<EntityId, Variable>,
+    HashMap<EntityId, Variable>,
+) {
+    let mut model = Model {
+        problem: Problem::new(OptimizationDirection::Maximize),
+        bounds: vec![],
+    };
+    let capacity = |e: EdgeIndex| {
+        let c = graph[e].capacity;
+        *c.numer().unwrap() as f64 / *c.denom().unwrap() as f64
+    };
+    let edges = graph
+        .edge_indices()
+        .map(|e| model.add_var(0.0, capacity(e)))
+        .collect::<Vec<_>>();
+    let var = |e: EdgeIndex| edges[e.index()];
+
+    let mut inputs = HashMap::new();
+    let mut outputs = HashMap::new();
+    let tie = |model: &mut Model, map: &mut HashMap<EntityId, Variable>, id, var: Variable| {
+        if let Some(other) = map.insert(id, var) {
+            model
+                .problem
+                .add_constraint([(var, 1.0), (other, -1.0)], ComparisonOp::Eq, 0.0);
+        }
+    };
+    for idx in graph.node_indices() {
+        let in_edges = graph.in_edge_idx(idx);
+        let out_edges = graph.out_edge_idx(idx);
+        match &graph[idx] {
+            Node::Input(i) => tie(&mut model, &mut inputs, i.id, var(out_edges[0])),
+            Node::Output(o) => tie(&mut model, &mut outputs, o.id, var(in_edges[0])),
+            node => {
+                /* kirchhoff's law */
+                let coeffs = in_edges
+                    .iter()
+                    .map(|e| (var(*e), 1.0))
+                    .chain(out_edges.iter().map(|e| (var(*e), -1.0)))
+                    .collect::<Vec<_>>();
+                model.problem.add_constraint(coeffs, ComparisonOp::Eq, 0.0);
+                if let Node::Splitter(s) = node {
+                    let case = model.add_binary_var();
+                    let [even, overflow] =
+                        splitter_cases(graph, idx, s.output_priority, &var, &capacity);
+                    for constraint in &even {
+                        model.add_if(constraint, case, false);
+                    }
+                    for constraint in &overflow {
+                        model.add_if(constraint, case, true);
+                    }
+                }
+            }
+        }
+    }
+    (model, inputs, outputs)
+}
+
+/// Returns the two cases of the condition of a splitter, see `Splitter::get_splitter_cond`.
+fn splitter_cases(
+    graph: &FlowGraph,
+    idx: NodeIndex,
+    priority: Side,
+    var: &impl Fn(EdgeIndex) -> Variable,
+    capacity: &impl Fn(EdgeIndex) -> f64,
+) -> [Vec<Constraint>; 2] {
+    let input = var(graph.in_edge_idx(idx)[0]);
+    if priority.is_none() {
+        let out_edges = graph.out_edge_idx(idx);
+        let (a, b) = (out_edges[0], out_edges[1]);
+        let (min, max) = if graph[a].capacity <= graph[b].capacity {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let min_cap = capacity(min);
+        let (min, max) = (var(min), var(max));
+        [
+            /* split evenly until the slower side is full */
+            vec![
+                (vec![(input, 1.0)], ComparisonOp::Le, 2.0 * min_cap),
+                (vec![(min, 1.0), (max, -1.0)], ComparisonOp::Eq, 0.0),
+            ],
+            /* the overflow goes to the faster side */
+            vec![
+                (vec![(input, 1.0)], ComparisonOp::Ge, 2.0 * min_cap),
+                (vec![(min, 1.0)], ComparisonOp::Eq, min_cap),
+            ],
+        ]
+    } else {
+        let prio = graph.get_edge(idx, Outgoing, priority);
+        let other = graph.get_edge(idx, Outgoing, -priority);
+        let prio_cap = capacity(prio);
+        let (prio, other) = (var(prio), var(other));
+        [
+            vec![
+                (vec![(input, 1.0)], ComparisonOp::Le, prio_cap),
+                (vec![(other, 1.0)], ComparisonOp::Eq, 0.0),
+            ],
+            vec![
+                (vec![(input, 1.0)], ComparisonOp::Ge, prio_cap),
+                (vec![(prio, 1.0)], ComparisonOp::Eq, prio_cap),
+            ],
+        ]
+    }
+}
+
+/// The property holds iff. the values can't differ, the proof is inconclusive if the solver fails.
+fn equal_values(model: Model, values: HashMap<EntityId, Variable>) -> ProofResult {
+    let values = values.into_values().collect::<Vec<_>>();
+    match model.can_differ(&values) {
+        Some(true) => ProofResult::Unsat,
+        Some(false) => ProofResult::Sat,
+        None => ProofResult::Unknown,
+    }
+}
+
+/// Same as `belt_balancer_f`, but proven with the linear programming back-end.
+pub fn lp_belt_balancer(graph: &FlowGraph) -> ProofResult {
+    let (model, _, outputs) = encode(graph);
+    equal_values(model, outputs)
+}
+
+/// Same as `equal_drain_f`, but proven with the linear programming back-end.
+///
+/// Uses a reversed graph, like `equal_drain_f`.
+pub fn lp_equal_drain(graph: &FlowGraph) -> ProofResult {
+    let (mut model, inputs, outputs) = encode(graph);
+    let mut inputs = inputs.into_values();
+    if let Some(first) = inputs.next() {
+        for input in inputs {
+            model
+                .problem
+                .add_constraint([(input, 1.0), (first, -1.0)], ComparisonOp::Eq, 0.0);
+        }
+    }
+    equal_values(model, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, FlowGraphFun, Reversable},
+    };
+
+    use super::*;
+
+    fn simplified_graph(file: &str) -> FlowGraph {
+        let entities = file_to_entities(&format!("tests/{}", file)).unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        graph
+    }
+
+    #[test]
+    fn splitter_case() {
+        /* max x s.t. x <= 4, y <= 4, x = 3 unless z = 1, y = 1 unless z = 0 */
+        let mut model = Model {
+            problem: Problem::new(OptimizationDirection::Maximize),
+            bounds: vec![],
+        };
+        let x = model.add_var(1.0, 4.0);
+        let y = model.add_var(1.0, 4.0);
+        let z = model.add_binary_var();
+        model.add_if(&(vec![(x, 1.0)], ComparisonOp::Eq, 3.0), z, false);
+        model.add_if(&(vec![(y, 1.0)], ComparisonOp::Eq, 1.0), z, true);
+        let max = model.problem.solve().unwrap().objective();
+        assert!((max - 7.0).abs() < EPSILON, "{}", max);
+    }
+
+    /// Balancers with too many splitters to enumerate the cases of, the verdicts are the ones of z3, see `matches_z3`
+    #[test]
+    fn large_balancers() {
+        for (file, verdict) in [
+            ("6-3-ntu", ProofResult::Unsat),
+            ("6-3-tu", ProofResult::Sat),
+            ("4-4-univ", ProofResult::Unsat),
+        ] {
+            let graph = simplified_graph(file);
+            assert_eq!(lp_belt_balancer(&graph), verdict, "balancer {}", file);
+            assert_eq!(
+                lp_equal_drain(&Reversable::reverse(&graph)),
+                verdict,
+                "equal drain {}",
+                file
+            );
+        }
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn matches_z3() {
+        use crate::backends::{belt_balancer_f, equal_drain_f, model_f, ModelFlags};
+        use z3::{Config, Context};
+
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        for file in [
+            "2-2",
+            "3-2",
+            "3-2-broken",
+            "3-2-equal-drain",
+            "4-4",
+            "4-4-broken",
+            "prio_splitter",
+            "mixed_output_tiers",
+            "3-3",
+            "4-4-tu",
+            "4-4-univ",
+            "6-3-ntu",
+            "6-3-tu",
+        ] {
+            let graph = simplified_graph(file);
+            let reversed = Reversable::reverse(&graph);
+            assert_eq!(
+                lp_belt_balancer(&graph),
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty()),
+                "balancer {}",
+                file
+            );
+            assert_eq!(
+                lp_equal_drain(&reversed),
+                model_f(&reversed, &ctx, equal_drain_f, ModelFlags::empty()),
+                "equal drain {}",
+                file
+            );
+        }
+    }
+}
@@ -1,11 +1,37 @@
 //! Back-end used to convert the IR into a z3 model
+//!
+//! Only the linear programming back-end and [`ProofResult`] are available without the `z3` feature.
+#[cfg(feature = "z3")]
+mod incremental;
+mod lp;
+#[cfg(feature = "z3")]
 mod model_entities;
+#[cfg(feature = "z3")]
 mod model_graph;
+#[cfg(feature = "z3")]
 mod proofs;
+mod result;
+#[cfg(feature = "z3")]
+mod solver;
 
-pub use self::proofs::{BlueprintProofEntity, ProofResult};
+#[cfg(feature = "z3")]
+pub use self::incremental::IncrementalModel;
+pub use self::lp::{lp_belt_balancer, lp_equal_drain};
+#[cfg(feature = "z3")]
+pub use self::model_entities::{edge_var_name, input_var_name, output_var_name};
+#[cfg(feature = "z3")]
+pub use self::proofs::{BlueprintProofEntity, ProofContext, SolverUnavailable};
+pub use self::result::ProofResult;
+#[cfg(feature = "z3")]
+pub use self::solver::{SolverBackend, Z3Backend};
 
+#[cfg(feature = "z3")]
 pub use model_graph::{
-    belt_balancer_f, equal_drain_f, model_f, throughput_unlimited, universal_balancer, ModelFlags,
-    ProofPrimitives,
+    approx_balancer_f, balancer_under_equal_inputs_f, belt_balancer_f, blocked_output_balancer_f,
+    classify_throughput_unlimited, contract_f, counter_example_f, equal_drain_f, fixed_ratio_f,
+    grouped_balancer, max_throughput_unlimited_load, model_f, model_f_for_logic, model_size,
+    no_deadlock_f, no_overflow_f, pinned_balancer_f, priority_correctness_f,
+    proportional_balancer_f, saturated_flow, throughput_unlimited, universal_balancer,
+    universal_equal_drain_f, weighted_drain_f, CounterExample, ModelFlags, ModelSize,
+    ProofPrimitives, TuClassification,
 };
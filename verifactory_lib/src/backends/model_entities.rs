@@ -3,62 +3,101 @@ use petgraph::{
     prelude::{EdgeIndex, NodeIndex},
     Direction::Outgoing,
 };
-use z3::{
-    ast::{Ast, Bool, Int, Real},
-    Context,
+use z3::{ast::Real, Context};
+
+use crate::{
+    entities::EntityId,
+    ir::{Connector, Edge, FlowGraph, GraphHelper, Input, Merger, Node, Output, Splitter},
+    utils::Side,
+};
+
+use super::{
+    model_graph::{ModelFlags, Z3QuantHelper},
+    solver::SolverBackend,
 };
 
-use crate::ir::{Connector, Edge, FlowGraph, GraphHelper, Input, Merger, Node, Output, Splitter};
+/// Returns the name of the z3 variable of the throughput of the input with the given entity id.
+///
+/// The inputs of the phantoms of a splitter share the id of the splitter, and therefore their variable.
+pub fn input_var_name(id: EntityId) -> String {
+    format!("input_{}", id)
+}
 
-use super::model_graph::{ModelFlags, Z3QuantHelper};
+/// Returns the name of the z3 variable of the throughput of the output with the given entity id.
+///
+/// The outputs of the phantoms of a splitter share the id of the splitter, and therefore their variable.
+pub fn output_var_name(id: EntityId) -> String {
+    format!("output_{}", id)
+}
+
+/// Returns the name of the z3 variable of the throughput of the edge `idx`,
+/// formed by its endpoints as returned by [`Node::get_str`] and its index, e.g. `edge_i1_s3_0`.
+pub fn edge_var_name(graph: &FlowGraph, idx: EdgeIndex) -> String {
+    edge_var_name_with("edge", graph, idx)
+}
+
+/// Returns the name of a z3 variable of the edge `idx` starting with `prefix`, see [`edge_var_name`]
+fn edge_var_name_with(prefix: &str, graph: &FlowGraph, idx: EdgeIndex) -> String {
+    let (src, dst) = graph.edge_endpoints(idx).unwrap();
+    format!(
+        "{}_{}_{}_{}",
+        prefix,
+        graph[src].get_str(),
+        graph[dst].get_str(),
+        idx.index()
+    )
+}
 
 // TODO: document whole file
-trait Z3Fraction {
+pub(super) trait Z3Fraction {
     fn to_z3<'a>(&self, ctx: &'a Context) -> Real<'a>;
 }
 
 impl Z3Fraction for GenericFraction<u128> {
     fn to_z3<'a>(&self, ctx: &'a Context) -> Real<'a> {
-        let num = *self.numer().unwrap() as i32;
-        let den = *self.denom().unwrap() as i32;
-        Real::from_real(ctx, num, den)
+        /* from strings, as the numerator and denominator may not fit into an `i32` */
+        let sign = if self.is_sign_negative() { "-" } else { "" };
+        let num = format!("{}{}", sign, self.numer().unwrap());
+        let den = self.denom().unwrap().to_string();
+        Real::from_real_str(ctx, &num, &den).unwrap()
     }
 }
+
 pub trait Z3Node {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     );
 }
 
 impl Z3Node for Node {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
         match self {
-            Self::Connector(c) => c.model(graph, idx, ctx, helper, flags),
-            Self::Input(c) => c.model(graph, idx, ctx, helper, flags),
-            Self::Output(c) => c.model(graph, idx, ctx, helper, flags),
-            Self::Merger(c) => c.model(graph, idx, ctx, helper, flags),
-            Self::Splitter(c) => c.model(graph, idx, ctx, helper, flags),
+            Self::Connector(c) => c.model(graph, idx, backend, helper, flags),
+            Self::Input(c) => c.model(graph, idx, backend, helper, flags),
+            Self::Output(c) => c.model(graph, idx, backend, helper, flags),
+            Self::Merger(c) => c.model(graph, idx, backend, helper, flags),
+            Self::Splitter(c) => c.model(graph, idx, backend, helper, flags),
         }
     }
 }
 
-pub fn kirchhoff_law<'a>(
+pub fn kirchhoff_law<'a, B: SolverBackend<'a>>(
     node_idx: NodeIndex,
     graph: &FlowGraph,
-    ctx: &'a Context,
-    helper: &mut Z3QuantHelper<'a>,
+    backend: &B,
+    helper: &mut Z3QuantHelper<'a, B>,
 ) {
     let edge_map = &helper.edge_map;
     let in_consts = graph
@@ -72,23 +111,23 @@ pub fn kirchhoff_law<'a>(
         .map(|idx| edge_map.get(idx).unwrap())
         .collect::<Vec<_>>();
 
-    let in_sum = Real::add(ctx, &in_consts);
-    let out_sum = Real::add(ctx, &out_consts);
+    let in_sum = backend.sum(&in_consts);
+    let out_sum = backend.sum(&out_consts);
 
-    let ast = in_sum._eq(&out_sum);
+    let ast = backend.eq(&in_sum, &out_sum);
     helper.others.push(ast);
 }
 
 impl Z3Node for Connector {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
-        kirchhoff_law(idx, graph, ctx, helper);
+        kirchhoff_law(idx, graph, backend, helper);
 
         if flags.contains(ModelFlags::Blocked) {
             // input blocked iff. output blocked
@@ -97,32 +136,30 @@ impl Z3Node for Connector {
             let blocked_in = helper.blocked_edge_map.get(&in_idx).unwrap();
             let blocked_out = helper.blocked_edge_map.get(&out_idx).unwrap();
 
-            let ast = blocked_in.iff(blocked_out);
+            let ast = backend.iff(blocked_in, blocked_out);
             helper.blocking.push(ast);
         }
     }
 }
 
 impl Z3Node for Input {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
         /* create new input variable */
-        let input_name = format!("input_{}", self.id);
-        let input = Int::new_const(ctx, input_name);
-        let input_real = Real::from_int(&input);
-        helper.input_map.insert(idx, input);
+        let input = backend.real_var(&input_var_name(self.id));
 
         /* kirchhoff on input and out-edge */
         let out_idx = graph.out_edge_idx(idx)[0];
         let out = helper.edge_map.get(&out_idx).unwrap();
 
-        let ast = input_real._eq(out);
+        let ast = backend.eq(&input, out);
+        helper.input_map.insert(idx, input);
         helper.others.push(ast);
 
         if flags.contains(ModelFlags::Blocked) {
@@ -137,23 +174,22 @@ impl Z3Node for Input {
 }
 
 impl Z3Node for Output {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
         /* create new output variable */
-        let output_name = format!("output_{}", self.id);
-        let output = Real::new_const(ctx, output_name);
+        let output = backend.real_var(&output_var_name(self.id));
 
         /* kirchhoff on output and in-edge */
         let in_idx = graph.in_edge_idx(idx)[0];
         let inp = helper.edge_map.get(&in_idx).unwrap();
 
-        let ast = output._eq(inp);
+        let ast = backend.eq(&output, inp);
         helper.others.push(ast);
         helper.output_map.insert(idx, output);
 
@@ -169,15 +205,15 @@ impl Z3Node for Output {
 }
 
 impl Z3Node for Merger {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
-        kirchhoff_law(idx, graph, ctx, helper);
+        kirchhoff_law(idx, graph, backend, helper);
 
         if flags.contains(ModelFlags::Blocked) {
             // add `blocked` constraint to [`Merger`]
@@ -191,9 +227,10 @@ impl Z3Node for Merger {
 
             // if output is blocked, block both inputs
             // otherwise, don't block the inputs
-            let ast = blocked_out.ite(
-                &Bool::and(ctx, &[blocked_in_1, blocked_in_2]),
-                &Bool::or(ctx, &[blocked_in_1, blocked_in_2]).not(),
+            let ast = backend.ite(
+                blocked_out,
+                &backend.and(&[blocked_in_1, blocked_in_2]),
+                &backend.not(&backend.or(&[blocked_in_1, blocked_in_2])),
             );
             helper.blocking.push(ast);
         }
@@ -201,16 +238,24 @@ impl Z3Node for Merger {
 }
 
 impl Z3Node for Splitter {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
-        kirchhoff_law(idx, graph, ctx, helper);
-        let splitter_cond = self.get_splitter_cond(graph, idx, ctx, helper);
+        kirchhoff_law(idx, graph, backend, helper);
+        let mut splitter_cond = self.get_splitter_cond(graph, idx, backend, helper, flags);
+
+        if flags.contains(ModelFlags::Guarded) {
+            // the splitter condition only holds if the splitter is active
+            let active_name = format!("active_{}_{}", graph[idx].get_str(), idx.index());
+            let active = backend.bool_var(&active_name);
+            splitter_cond = backend.implies(&active, &splitter_cond);
+            helper.active_splitter_map.insert(idx, active);
+        }
 
         if flags.contains(ModelFlags::Relaxed) {
             // skip the splitter condition
@@ -225,15 +270,23 @@ impl Z3Node for Splitter {
             let blocked_out_2 = helper.blocked_edge_map.get(&out_idx_2).unwrap();
 
             // remove splitter condition if at least one of the outputs is blocked
-            let ast = Bool::or(ctx, &[blocked_out_1, blocked_out_2])
-                .not()
-                .implies(&splitter_cond);
-            helper.others.push(ast);
+            if !flags.contains(ModelFlags::Adversarial) {
+                let ast = backend.implies(
+                    &backend.not(&backend.or(&[blocked_out_1, blocked_out_2])),
+                    &splitter_cond,
+                );
+                helper.others.push(ast);
+            }
             // if both outputs are blocked, block the input
             // otherwise, don't block the input
-            let ast =
-                Bool::and(ctx, &[blocked_out_1, blocked_out_2]).ite(blocked_in, &blocked_in.not());
+            let ast = backend.ite(
+                &backend.and(&[blocked_out_1, blocked_out_2]),
+                blocked_in,
+                &backend.not(blocked_in),
+            );
             helper.blocking.push(ast);
+        } else if flags.contains(ModelFlags::Adversarial) {
+            // the splitter may distribute its input arbitrarily
         } else {
             // ModelFlags is empty (normal operation)
             helper.others.push(splitter_cond);
@@ -242,17 +295,22 @@ impl Z3Node for Splitter {
 }
 
 impl Splitter {
-    pub fn get_splitter_cond<'a>(
+    pub fn get_splitter_cond<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: NodeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
-    ) -> Bool<'a> {
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
+        flags: ModelFlags,
+    ) -> B::Bool {
         let in_idx = graph.in_edge_idx(idx)[0];
         let in_var = helper.edge_map.get(&in_idx).unwrap();
 
-        let side = self.output_priority;
+        let side = if flags.contains(ModelFlags::IgnorePriority) {
+            Side::None
+        } else {
+            self.output_priority
+        };
         if side.is_none() {
             let out_idxs = graph.out_edge_idx(idx);
             let a_idx = out_idxs[0];
@@ -270,13 +328,16 @@ impl Splitter {
             let max_var = helper.edge_map.get(&max_idx).unwrap();
 
             let min_cap = graph[min_idx].capacity;
-            let min_cap_var = min_cap.to_z3(ctx);
+            let min_cap_var = backend.real_const(min_cap);
             let out_min = min_cap * 2;
-            let out_min_var = out_min.to_z3(ctx);
-
-            in_var
-                .le(&out_min_var)
-                .ite(&min_var._eq(max_var), &min_var._eq(&min_cap_var))
+            let out_min_var = backend.real_const(out_min);
+
+            // split evenly until the slower side is full, the overflow goes to the faster side
+            backend.ite(
+                &backend.le(in_var, &out_min_var),
+                &backend.eq(min_var, max_var),
+                &backend.eq(min_var, &min_cap_var),
+            )
         } else {
             let prio_idx = graph.get_edge(idx, Outgoing, side);
             let other_idx = graph.get_edge(idx, Outgoing, -side);
@@ -285,65 +346,65 @@ impl Splitter {
             let other_var = helper.edge_map.get(&other_idx).unwrap();
 
             let prio_cap = graph[prio_idx].capacity;
-            let prio_cap_var = prio_cap.to_z3(ctx);
-            let zero = Real::from_real(ctx, 0, 1);
-
-            in_var
-                .le(&prio_cap_var)
-                .ite(&other_var._eq(&zero), &prio_var._eq(&prio_cap_var))
+            let prio_cap_var = backend.real_const(prio_cap);
+            let zero = backend.real_const(GenericFraction::from(0));
+
+            backend.ite(
+                &backend.le(in_var, &prio_cap_var),
+                &backend.eq(other_var, &zero),
+                &backend.eq(prio_var, &prio_cap_var),
+            )
         }
     }
 }
 
 pub trait Z3Edge {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: EdgeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     );
 }
 
 impl Z3Edge for Edge {
-    fn model<'a>(
+    fn model<'a, B: SolverBackend<'a>>(
         &self,
         graph: &FlowGraph,
         idx: EdgeIndex,
-        ctx: &'a Context,
-        helper: &mut Z3QuantHelper<'a>,
+        backend: &B,
+        helper: &mut Z3QuantHelper<'a, B>,
         flags: ModelFlags,
     ) {
-        let numer = *self.capacity.numer().unwrap() as i32;
-        let denom = *self.capacity.denom().unwrap() as i32;
-        let capacity = Real::from_real(ctx, numer, denom);
-
-        let (src, dst) = graph.edge_endpoints(idx).unwrap();
-        let (src_id, dst_id) = (graph[src].get_str(), graph[dst].get_str());
+        let capacity = backend.real_const(self.capacity);
 
-        let edge_name = format!("edge_{}_{}_{}", src_id, dst_id, idx.index());
-        let edge = Real::new_const(ctx, edge_name);
-        let zero = Real::from_real(ctx, 0, 1);
+        let edge = backend.real_var(&edge_var_name(graph, idx));
+        let zero = backend.real_const(GenericFraction::from(0));
 
-        let ast = edge.le(&capacity);
+        let ast = backend.le(&edge, &capacity);
         helper.others.push(ast);
-        let ast = edge.ge(&zero);
+        let ast = backend.ge(&edge, &zero);
         helper.others.push(ast);
+        if flags.contains(ModelFlags::Guarded) {
+            // a dead edge does not carry any flow
+            let dead = backend.bool_var(&edge_var_name_with("dead", graph, idx));
+            helper
+                .others
+                .push(backend.implies(&dead, &backend.eq(&edge, &zero)));
+            helper.dead_edge_map.insert(idx, dead);
+        }
         helper.edge_map.insert(idx, edge);
 
         // check if blocked
         if flags.contains(ModelFlags::Blocked) {
             // add `blocked` constraint to each edge in the model
             let edge = helper.edge_map.get(&idx).unwrap();
-            let zero = Real::from_real(ctx, 0, 1);
-
-            let (src, dst) = graph.edge_endpoints(idx).unwrap();
-            let (src_id, dst_id) = (graph[src].get_str(), graph[dst].get_str());
+            let zero = backend.real_const(GenericFraction::from(0));
 
-            let blocked_name = format!("blocked_{}_{}_{}", src_id, dst_id, idx.index());
-            let blocked = Bool::new_const(ctx, blocked_name);
-            let blocked_capacity = blocked.implies(&edge._eq(&zero));
+            let blocked = backend.bool_var(&edge_var_name_with("blocked", graph, idx));
+            let blocked_capacity = backend.implies(&blocked, &backend.eq(edge, &zero));
 
             helper.blocked_edge_map.insert(idx, blocked);
 
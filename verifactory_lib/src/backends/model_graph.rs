@@ -1,28 +1,63 @@
 use bitflags::bitflags;
-use petgraph::prelude::{EdgeIndex, NodeIndex};
-use std::{collections::HashMap, mem};
+use fraction::GenericFraction;
+use petgraph::{
+    prelude::{EdgeIndex, NodeIndex},
+    visit::Dfs,
+    Direction::Outgoing,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    mem,
+};
 use z3::{
     ast::{exists_const, forall_const, Ast, Bool, Int, Real},
-    Context, Solver,
+    Context, Optimize, SatResult,
 };
 
-use crate::{entities::FBEntity, ir::FlowGraph};
+use crate::{
+    entities::{exact_throughput, EntityId, FBEntity},
+    ir::{CoalesceStrength, FlowGraph, FlowGraphFun, GraphHelper, Node},
+    utils::{Position, Side},
+};
 
-use super::proofs::ProofResult;
+use super::ProofResult;
 
-use super::model_entities::{Z3Edge, Z3Node};
+use super::model_entities::{Z3Edge, Z3Fraction, Z3Node};
+use super::solver::{SolverBackend, Z3Backend};
 
-#[derive(Default)]
-pub struct Z3QuantHelper<'a> {
-    pub edge_map: HashMap<EdgeIndex, Real<'a>>,
-    pub input_map: HashMap<NodeIndex, Int<'a>>,
-    pub output_map: HashMap<NodeIndex, Real<'a>>,
-    pub input_const: Vec<Bool<'a>>,
-    pub others: Vec<Bool<'a>>,
-    pub blocked_edge_map: HashMap<EdgeIndex, Bool<'a>>,
-    pub blocked_input_map: HashMap<NodeIndex, Bool<'a>>,
-    pub blocked_output_map: HashMap<NodeIndex, Bool<'a>>,
-    pub blocking: Vec<Bool<'a>>,
+/// Variables and constraints of the encoding of a graph in the solver backend `B`
+pub struct Z3QuantHelper<'a, B: SolverBackend<'a> = Z3Backend<'a>> {
+    pub edge_map: HashMap<EdgeIndex, B::Real>,
+    pub input_map: HashMap<NodeIndex, B::Real>,
+    pub output_map: HashMap<NodeIndex, B::Real>,
+    pub input_const: Vec<B::Bool>,
+    pub others: Vec<B::Bool>,
+    pub blocked_edge_map: HashMap<EdgeIndex, B::Bool>,
+    pub blocked_input_map: HashMap<NodeIndex, B::Bool>,
+    pub blocked_output_map: HashMap<NodeIndex, B::Bool>,
+    pub blocking: Vec<B::Bool>,
+    pub dead_edge_map: HashMap<EdgeIndex, B::Bool>,
+    pub active_splitter_map: HashMap<NodeIndex, B::Bool>,
+}
+
+impl<'a, B: SolverBackend<'a>> Default for Z3QuantHelper<'a, B> {
+    fn default() -> Self {
+        Self {
+            edge_map: HashMap::new(),
+            input_map: HashMap::new(),
+            output_map: HashMap::new(),
+            input_const: vec![],
+            others: vec![],
+            blocked_edge_map: HashMap::new(),
+            blocked_input_map: HashMap::new(),
+            blocked_output_map: HashMap::new(),
+            blocking: vec![],
+            dead_edge_map: HashMap::new(),
+            active_splitter_map: HashMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -32,9 +67,9 @@ pub struct ProofPrimitives<'a> {
     /// Flowgraph associated with the proof
     pub graph: &'a FlowGraph,
     /// `Vec` of all the input throughput variables in z3
-    pub input_bounds: Vec<Int<'a>>,
+    pub input_bounds: Vec<Real<'a>>,
     /// Map from `NodeIndex` to the associated throughput variable in z3
-    pub input_map: HashMap<NodeIndex, Int<'a>>,
+    pub input_map: HashMap<NodeIndex, Real<'a>>,
     /// `Vec` of all the output throughput variables in z3
     pub output_bounds: Vec<Real<'a>>,
     /// Map from `NodeIndex` to the associated throughput variable in z3
@@ -45,6 +80,8 @@ pub struct ProofPrimitives<'a> {
     pub blocked_output_map: HashMap<NodeIndex, Bool<'a>>,
     /// min. and max. throughput of an edge constraint
     pub edge_bounds: Vec<Real<'a>>,
+    /// Map from `EdgeIndex` to the associated throughput variable in z3
+    pub edge_map: HashMap<EdgeIndex, Real<'a>>,
     /// constraints like kirchhoffs law or implementation of splitters
     pub model_constraint: Bool<'a>,
     /// blocking constraints
@@ -52,37 +89,79 @@ pub struct ProofPrimitives<'a> {
 }
 
 bitflags! {
-    #[derive(Clone, Copy)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(transparent)]
     pub struct ModelFlags: u8 {
+        /// Drops the splitter conditions, only sound for proving throughput unlimitedness.
         const Relaxed = 1;
+        /// Allows outputs to be blocked.
         const Blocked = 1 << 1;
+        /// Guards edges and splitter conditions with literals that can be toggled via assumptions.
+        /// Used by [`IncrementalModel`](super::IncrementalModel) to remove inputs and outputs without re-encoding.
+        const Guarded = 1 << 2;
+        /// Models all splitters as if they had no output priority.
+        const IgnorePriority = 1 << 3;
+        /// Drops the splitter conditions, so that splitters may distribute their input arbitrarily
+        /// among their outputs within the capacities.
+        ///
+        /// A property proven with this flag holds no matter how the splitters behave, which is more conservative
+        /// than the deterministic model: e.g. no design that relies on a splitter to balance is a balancer.
+        /// Unlike [`ModelFlags::Relaxed`] it can be combined with [`ModelFlags::Blocked`], keeping the blocking constraints.
+        const Adversarial = 1 << 4;
     }
 }
 
-pub fn model_f<'a, F>(
-    graph: &'a FlowGraph,
-    ctx: &'a Context,
-    f: F,
-    flags: ModelFlags,
-) -> ProofResult
-where
-    F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
-{
-    let solver = Solver::new(ctx);
+impl ModelFlags {
+    /// Returns a human readable note for each active flag, to be reported along with a proof result.
+    ///
+    /// Results obtained with modified models can otherwise be misread as results of the normal model.
+    pub fn notes(&self) -> Vec<&'static str> {
+        self.iter()
+            .filter_map(|flag| match flag {
+                Self::Relaxed => Some("splitter conditions relaxed (sound for TU)"),
+                Self::Blocked => Some("outputs may be blocked"),
+                Self::IgnorePriority => Some("splitter priorities ignored"),
+                Self::Adversarial => Some("splitters may split arbitrarily"),
+                _ => None,
+            })
+            .collect()
+    }
+}
 
+#[cfg(test)]
+thread_local! {
+    /// Number of graphs encoded on this thread, to test that an encoding is reused
+    pub(super) static ENCODINGS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Encodes the edges and nodes of the `graph` as constraints of the solver `backend`
+pub(super) fn encode_graph<'a, B: SolverBackend<'a>>(
+    graph: &FlowGraph,
+    backend: &B,
+    flags: ModelFlags,
+) -> Z3QuantHelper<'a, B> {
+    #[cfg(test)]
+    ENCODINGS.with(|n| n.set(n.get() + 1));
     let mut helper = Z3QuantHelper::default();
     // encode edges as variables in z3
     for edge_idx in graph.edge_indices() {
         let edge = graph[edge_idx];
-        edge.model(graph, edge_idx, ctx, &mut helper, flags);
+        edge.model(graph, edge_idx, backend, &mut helper, flags);
     }
     // encode nodes as equations
     for node_idx in graph.node_indices() {
         let node = &graph[node_idx];
-        node.model(graph, node_idx, ctx, &mut helper, flags);
+        node.model(graph, node_idx, backend, &mut helper, flags);
     }
+    helper
+}
 
-    // add stuff to solver
+/// Collects the encoded constraints of the `helper` into [`ProofPrimitives`]
+pub(super) fn to_primitives<'a>(
+    graph: &'a FlowGraph,
+    ctx: &'a Context,
+    helper: &mut Z3QuantHelper<'a>,
+) -> ProofPrimitives<'a> {
     let input_map = mem::take(&mut helper.input_map);
     let input_bounds = input_map.values().cloned().collect::<Vec<_>>();
 
@@ -92,14 +171,14 @@ where
     let blocked_input_map = mem::take(&mut helper.blocked_input_map);
     let blocked_output_map = mem::take(&mut helper.blocked_output_map);
 
-    let edge_map = mem::take(&mut helper.edge_map);
-    let edge_bounds = edge_map.values().cloned().collect::<Vec<_>>();
+    let edge_bounds = helper.edge_map.values().cloned().collect::<Vec<_>>();
+    let edge_map = helper.edge_map.clone();
 
     let model_constraint = vec_and(ctx, &helper.others);
 
-    let blocking_constraint = helper.blocking;
+    let blocking_constraint = mem::take(&mut helper.blocking);
 
-    let primitives = ProofPrimitives {
+    ProofPrimitives {
         ctx,
         graph,
         input_bounds,
@@ -109,16 +188,96 @@ where
         blocked_input_map,
         blocked_output_map,
         edge_bounds,
+        edge_map,
         model_constraint,
         blocking_constraint,
-    };
+    }
+}
+
+/// Size of the z3 encoding of a graph, to compare the solver workload of different designs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelSize {
+    /// Number of nodes of the encoded graph
+    pub nodes: usize,
+    /// Number of edges of the encoded graph
+    pub edges: usize,
+    /// Number of z3 variables: the throughputs of the edges, inputs and outputs,
+    /// as well as the literals added by [`ModelFlags::Blocked`] and [`ModelFlags::Guarded`]
+    pub variables: usize,
+    /// Number of z3 constraints of the model, before adding the property to prove
+    pub constraints: usize,
+}
+
+impl Display for ModelSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} edges: {} variables, {} constraints",
+            self.nodes, self.edges, self.variables, self.constraints
+        )
+    }
+}
+
+/// Returns the size of the z3 model of the `graph` with the given `flags`, without solving it.
+pub fn model_size(graph: &FlowGraph, ctx: &Context, flags: ModelFlags) -> ModelSize {
+    let backend = Z3Backend::new(ctx, None);
+    let helper = encode_graph(graph, &backend, flags);
+    ModelSize {
+        nodes: graph.node_count(),
+        edges: graph.edge_count(),
+        variables: helper.edge_map.len()
+            + helper.input_map.len()
+            + helper.output_map.len()
+            + helper.blocked_edge_map.len()
+            + helper.dead_edge_map.len()
+            + helper.active_splitter_map.len(),
+        constraints: helper.input_const.len() + helper.others.len() + helper.blocking.len(),
+    }
+}
+
+pub fn model_f<'a, F>(
+    graph: &'a FlowGraph,
+    ctx: &'a Context,
+    f: F,
+    flags: ModelFlags,
+) -> ProofResult
+where
+    F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
+{
+    model_f_for_logic(graph, ctx, f, flags, None)
+}
+
+/// Same as [`model_f`] but uses a solver specialized for the given SMT-LIB `logic`, e.g. `"QF_LRA"`.
+///
+/// Falls back to the default solver if `logic` is `None` or not supported by z3.
+///
+/// Which logic suits which proof:
+/// - [`belt_balancer_f`](super::belt_balancer_f) and [`equal_drain_f`](super::equal_drain_f) are
+///   quantifier-free linear arithmetic and run fastest with `"QF_LRA"`.
+/// - [`throughput_unlimited`](super::throughput_unlimited) and
+///   [`universal_balancer`](super::universal_balancer) quantify over the inputs and outputs and need a
+///   quantified logic such as `"LRA"`, or the default solver.
+pub fn model_f_for_logic<'a, F>(
+    graph: &'a FlowGraph,
+    ctx: &'a Context,
+    f: F,
+    flags: ModelFlags,
+    logic: Option<&str>,
+) -> ProofResult
+where
+    F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
+{
+    let backend = Z3Backend::new(ctx, logic);
+
+    let mut helper = encode_graph(graph, &backend, flags);
+    let primitives = to_primitives(graph, ctx, &mut helper);
 
-    solver.assert(&f(primitives.clone()));
-    let res: ProofResult = solver.check().into();
+    backend.assert(&f(primitives.clone()));
+    let res = backend.check();
     // TODO: move to tracing
     // println!("Solver:\n{:?}", solver);
     // println!("Model:\n{:?}", solver.get_model());
-    if let Some(model) = solver.get_model() {
+    if let Some(model) = backend.solver().get_model() {
         for input in primitives.input_bounds {
             let a = model.eval(&input, true);
             println!("{:?}: {:?}", &input, a);
@@ -127,6 +286,172 @@ where
     res.not()
 }
 
+/// Throughputs of the inputs and outputs in a counter-example to a property
+#[derive(Debug, Clone, PartialEq)]
+pub struct CounterExample {
+    /// Throughput of each input, identified by the id of its entity
+    pub inputs: Vec<(EntityId, f64)>,
+    /// Throughput of each output, identified by the id of its entity
+    pub outputs: Vec<(EntityId, f64)>,
+    /// Position of the entities of the inputs and outputs, empty unless set with [`CounterExample::with_positions`]
+    pub positions: HashMap<EntityId, Position<i32>>,
+}
+
+impl CounterExample {
+    /// Looks up the positions of the inputs and outputs, e.g. in [`Compiler::entity_positions`](crate::frontend::Compiler::entity_positions),
+    /// so that they can be located on the grid.
+    pub fn with_positions(mut self, positions: &HashMap<EntityId, Position<i32>>) -> Self {
+        self.positions = self
+            .inputs
+            .iter()
+            .map(|(id, _)| *id)
+            .chain(self.outputs.iter().map(|(id, _)| *id))
+            .filter_map(|id| positions.get(&id).map(|pos| (id, *pos)))
+            .collect();
+        self
+    }
+
+    /// Returns the difference `a - b` between the throughputs of each pair of outputs `a` and `b`,
+    /// where the id of `a` is lower than the id of `b`.
+    pub fn output_differences(&self) -> Vec<(EntityId, EntityId, f64)> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (a, a_value))| {
+                self.outputs[i + 1..]
+                    .iter()
+                    .map(move |(b, b_value)| (*a, *b, a_value - b_value))
+            })
+            .collect()
+    }
+
+    /// Returns the largest difference between the throughputs of two outputs
+    pub fn max_imbalance(&self) -> f64 {
+        self.output_differences()
+            .iter()
+            .map(|(_, _, diff)| diff.abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+impl Display for CounterExample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |id: &EntityId| match self.positions.get(id) {
+            Some(pos) => format!("{} at {}", id, pos),
+            None => id.to_string(),
+        };
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(id, v)| format!("{}: {}/s", name(id), v))
+            .collect::<Vec<_>>();
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|(id, v)| format!("{}: {}/s", name(id), v))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "Counter-example with inputs {} and outputs {}",
+            inputs.join(", "),
+            outputs.join(", ")
+        )
+    }
+}
+
+/// Same as [`model_f_for_logic`] but returns the counter-example found by the solver, if the property does not hold.
+///
+/// Only meaningful for properties without quantifiers over the inputs, like [`belt_balancer_f`].
+/// The inputs and outputs are sorted by their entity id.
+pub fn counter_example_f<'a, F>(
+    graph: &'a FlowGraph,
+    ctx: &'a Context,
+    f: F,
+    flags: ModelFlags,
+    logic: Option<&str>,
+) -> Option<CounterExample>
+where
+    F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
+{
+    let backend = Z3Backend::new(ctx, logic);
+
+    let mut helper = encode_graph(graph, &backend, flags);
+    let primitives = to_primitives(graph, ctx, &mut helper);
+
+    backend.assert(&f(primitives.clone()));
+    if backend.check() != ProofResult::Sat {
+        return None;
+    }
+    let model = backend.solver().get_model()?;
+    let mut inputs = primitives
+        .input_map
+        .iter()
+        .map(|(idx, v)| {
+            let value = model
+                .eval(v, true)
+                .and_then(|v| v.as_real())
+                .map_or(0.0, |(num, den)| num as f64 / den as f64);
+            (graph[*idx].get_id(), value)
+        })
+        .collect::<Vec<_>>();
+    inputs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut outputs = primitives
+        .output_map
+        .iter()
+        .map(|(idx, v)| {
+            let value = model
+                .eval(v, true)
+                .and_then(|v| v.as_real())
+                .map_or(0.0, |(num, den)| num as f64 / den as f64);
+            (graph[*idx].get_id(), value)
+        })
+        .collect::<Vec<_>>();
+    outputs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Some(CounterExample {
+        inputs,
+        outputs,
+        positions: HashMap::new(),
+    })
+}
+
+/// Returns a flow of maximum total throughput, i.e. the flow through the saturated design, with the items/s carried by each edge.
+///
+/// Unlike [`FlowGraphAnalysis::min_cut_value`](crate::ir::FlowGraphAnalysis::min_cut_value), the flow honors the balancing
+/// and the priorities of the splitters as modelled with the given `flags`. If there are several such flows, any of them is returned.
+/// Returns `None` if the solver does not find one.
+pub fn saturated_flow(
+    graph: &FlowGraph,
+    ctx: &Context,
+    flags: ModelFlags,
+) -> Option<HashMap<EdgeIndex, f64>> {
+    let backend = Z3Backend::new(ctx, None);
+    let mut helper = encode_graph(graph, &backend, flags);
+    let primitives = to_primitives(graph, ctx, &mut helper);
+
+    let optimize = Optimize::new(ctx);
+    optimize.assert(&primitives.model_constraint);
+    let outputs = primitives.output_bounds.iter().collect::<Vec<_>>();
+    if !outputs.is_empty() {
+        optimize.maximize(&Real::add(ctx, &outputs));
+    }
+    if optimize.check(&[]) != SatResult::Sat {
+        return None;
+    }
+    let model = optimize.get_model()?;
+    let flow = primitives
+        .edge_map
+        .iter()
+        .map(|(idx, v)| {
+            let value = model
+                .eval(v, true)
+                .and_then(|v| v.as_real())
+                .map_or(0.0, |(num, den)| num as f64 / den as f64);
+            (*idx, value)
+        })
+        .collect();
+    Some(flow)
+}
+
 /// Conjunction of a slice of `Bool`s.
 pub fn vec_and<'a>(ctx: &'a Context, vec: &[Bool<'a>]) -> Bool<'a> {
     let slice = vec.iter().collect::<Vec<_>>();
@@ -160,147 +485,551 @@ pub fn belt_balancer_f(p: ProofPrimitives<'_>) -> Bool<'_> {
     Bool::and(p.ctx, &[&balancer_condition.not(), &p.model_constraint])
 }
 
-/// Function to prove if a given z3 model is an equal drain belt balancer
-///
-/// # Definiton
+/// Function that generates a function to prove if a given z3 model balances its outputs up to a tolerance of `epsilon` items/s
 ///
-/// Equal drain: When operating all the inputs are consumed equally, not resulting in any imbalances.
-/// E.g. [this](https://fbe.teoxoy.com/?source=0eJyd0ttqwzAMBuB30bVTkjSliy/3GqUUJ1WLwFGMrYyFkHefnYxRGPR0aVv/JyN7gsYO6DyxgJ6A2p4D6MMEga5sbNqT0SFoIMEOFLDp0upigmTiDQfXe8katAKzAuIzfoMu5qMCZCEhXLnbWHCWRNBHzfUh1vSc+sRcrmAEneWbXbTO5LFdD/NfbTzx0DUxGTuo6d5d/tEJXfXisV6+qr+Cb9/FnxhL9fZYnsB36VWXb6Bvfo2CL/RhiZQfRbWvy31V1vW2LhRYE7vG6s+/6nn+AWY2ztQ=) is a 2-2 equal drain belt balancer;
-/// [this](https://fbe.teoxoy.com/?source=0eJyVktFqwzAMRf9Fz/ZI0pQuftxvlFGcVh0GRza2MhZC/n1yOkbHYGuejC3dc7myZuj9iDE5YjAzuHOgDOY4Q3ZvZH154ykiGHCMAyggO5Tb1WbWnCzlGBLrHj3DosDRBT/A1MurAiR27PCG+0OmIIYsnYGKm6h19bRXMIGRU5gXl/B8K1df1OlE49BjKk7qJzxH75il9AtbrUz9ALQpQeLIJ5lLSFIRuMdrSbgtyYYguzv2wwPS9f/gdgN4C3df/nhdCnO3QwreMeVV0jzX7aFrDm3TdbuuVuCtuEr3y3f3snwCz/TTyA==) is only a 2-2 belt balancer.
+/// # Definition
 ///
-/// # Precondition
+/// Approximate balancer: Blueprint that taking every possible combination of inputs produces outputs
+/// that differ by at most `epsilon` from each other. With an `epsilon` of zero this is [`belt_balancer_f`],
+/// a negative `epsilon` can never be met.
 ///
-/// Assumes that the model is a valid belt balancer.
-/// Uses a reversed graph.
+/// Useful for designs that are intentionally near-balanced, e.g. "is it balanced within 1 item/s?".
 ///
-/// The `model_condition` states that the z3 model is modelled correctly and that equality of inputs does NOT imply equality of outputs.
+/// The `model_condition` states that the z3 model is modelled correctly and two outputs differ by more than `epsilon`.
 /// This is used to find a counter-example.
-pub fn equal_drain_f(p: ProofPrimitives<'_>) -> Bool<'_> {
-    let input_eq = equality(p.ctx, &p.input_bounds);
-    let output_eq = equality(p.ctx, &p.output_bounds);
-    // Correct model and equality of inputs does NOT imply equality of outputs
-    Bool::and(
-        p.ctx,
-        &[&p.model_constraint, &input_eq.implies(&output_eq).not()],
-    )
+pub fn approx_balancer_f<'a>(
+    epsilon: GenericFraction<u128>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let epsilon = epsilon.to_z3(p.ctx);
+        let outputs = &p.output_bounds;
+        let exceeds = outputs
+            .iter()
+            .flat_map(|a| outputs.iter().map(move |b| (a, b)))
+            .map(|(a, b)| a.gt(&Real::add(p.ctx, &[b, &epsilon])))
+            .collect::<Vec<_>>();
+        let imbalanced = Bool::or(p.ctx, &exceeds.iter().collect::<Vec<_>>());
+        // Correct model and two outputs differing by more than epsilon
+        Bool::and(p.ctx, &[&imbalanced, &p.model_constraint])
+    }
 }
 
-// TODO: figure out lifetimes and fix code duplication
-fn capacity_bound<'a, 'b>(
-    p: &'a ProofPrimitives<'a>,
-    entities: &[FBEntity<i32>],
-    iter: impl Iterator<Item = (&'b NodeIndex, &'a Real<'a>)>,
-) -> Bool<'a> {
-    let zero = Real::from_real(p.ctx, 0, 1);
-    let conditions = iter
-        .map(|(idx, v)| {
-            let lower = v.ge(&zero);
-
-            let entity_id = p.graph[*idx].get_id();
-            let capacity = entities
-                .iter()
-                .find(|e| e.get_base().id == entity_id)
-                .unwrap()
-                .get_base()
-                .throughput as i64;
-            let upper_const = Real::from_int(&Int::from_i64(p.ctx, capacity));
-            let upper = v.le(&upper_const);
-            Bool::and(p.ctx, &[&lower, &upper])
-        })
-        .collect::<Vec<_>>();
-    vec_and(p.ctx, &conditions)
+/// Function that generates a function to prove if a given z3 model balances the outputs fed by the free inputs,
+/// when the `pinned` inputs carry a fixed amount of items
+///
+/// # Definition
+///
+/// Pinned balancer: Blueprint that produces equal outputs for every combination of the inputs that are not pinned,
+/// while each pinned input, identified by its entity id, carries the given value, e.g. because it is always full.
+///
+/// Only the outputs that can be reached from a free input take part in the balancer condition,
+/// the outputs fed by pinned inputs only are constant. The values are converted like the throughputs
+/// of the entities, see [`exact_throughput`], so pinning an input to the throughput of its belt saturates it.
+///
+/// The `model_condition` states that the z3 model is modelled correctly, the pinned inputs carry their values
+/// and the free outputs are NOT equal. This is used to find a counter-example.
+pub fn pinned_balancer_f<'a>(
+    pinned: HashMap<EntityId, f64>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let mut pinned_conditions = vec![];
+        let mut reached = vec![false; p.graph.node_count()];
+        for (idx, input) in &p.input_map {
+            match pinned.get(&p.graph[*idx].get_id()) {
+                Some(value) => {
+                    let value = exact_capacity(*value).to_z3(p.ctx);
+                    pinned_conditions.push(input._eq(&value));
+                }
+                None => {
+                    let mut dfs = Dfs::new(p.graph, *idx);
+                    while let Some(node) = dfs.next(p.graph) {
+                        reached[node.index()] = true;
+                    }
+                }
+            }
+        }
+        let free_outputs = p
+            .output_map
+            .iter()
+            .filter(|(idx, _)| reached[idx.index()])
+            .map(|(_, v)| v.clone())
+            .collect::<Vec<_>>();
+        let balancer_condition = equality(p.ctx, &free_outputs);
+        // Correct model, pinned inputs and NOT equality of the free outputs
+        Bool::and(
+            p.ctx,
+            &[
+                &vec_and(p.ctx, &pinned_conditions),
+                &balancer_condition.not(),
+                &p.model_constraint,
+            ],
+        )
+    }
 }
 
-/// Function that generates a function to prove if a given z3 model is a throughput unlimited belt balancer
+/// Function that generates a function to prove if a given z3 model routes its saturated inputs to the outputs in the given `ratios`
 ///
 /// # Definition
 ///
-/// Throughput unlimited:
+/// Fixed ratio router: Blueprint that, when every input carries the full throughput of its belt,
+/// produces outputs in a fixed ratio, i.e. `output_i / ratio_i` is the same for all outputs in `ratios`.
+/// The ratios are keyed by the entity id of the outputs, the outputs without a ratio are not part of the condition.
+/// An output with a ratio of 0 receives no items, if all the ratios are 0 none of the outputs do.
 ///
-/// # Precondition
+/// Unlike a balancer, a router relies on the priorities and capacities of its splitters,
+/// e.g. a priority splitter whose priority output is a slower belt, so the ratio only holds under saturation.
+/// The ratio is checked exactly on the rational throughputs of the model.
 ///
-/// Assumes that the model is a valid belt balancer.
+/// The `model_condition` states that the z3 model is modelled correctly, all the inputs are saturated
+/// and the outputs are NOT in the given ratio. This is used to find a counter-example.
+pub fn fixed_ratio_f<'a>(
+    ratios: HashMap<EntityId, u32>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let saturated = p
+            .input_map
+            .iter()
+            .map(|(idx, input)| {
+                let capacity = p.graph.out_edges(*idx)[0].capacity.to_z3(p.ctx);
+                input._eq(&capacity)
+            })
+            .collect::<Vec<_>>();
+        let routed = p
+            .output_map
+            .iter()
+            .filter_map(|(idx, output)| {
+                let ratio = ratios.get(&p.graph[*idx].get_id())?;
+                Some((output, *ratio))
+            })
+            .collect::<Vec<_>>();
+        /* cross-multiply with an output of non-zero ratio: `output / ratio == reference / ref_ratio` */
+        let ratio = |r: u32| Int::from_u64(p.ctx, r.into()).to_real();
+        let zero = Real::from_real(p.ctx, 0, 1);
+        let in_ratio = routed
+            .iter()
+            .map(|(output, r)| match routed.iter().find(|(_, r)| *r != 0) {
+                Some((reference, ref_ratio)) => {
+                    (*output * ratio(*ref_ratio))._eq(&(*reference * ratio(*r)))
+                }
+                None => output._eq(&zero),
+            })
+            .collect::<Vec<_>>();
+        let ratio_condition = vec_and(p.ctx, &in_ratio);
+        // Correct model, saturated inputs and NOT outputs in the given ratio
+        Bool::and(
+            p.ctx,
+            &[
+                &vec_and(p.ctx, &saturated),
+                &ratio_condition.not(),
+                &p.model_constraint,
+            ],
+        )
+    }
+}
+
+/// Returns the input, output and edge variables, to quantify over all the flows of a model.
+///
+/// The phantoms of an entity share its input or output variable, so they are only returned once.
+fn flow_variables<'a, 'b>(p: &'b ProofPrimitives<'a>) -> Vec<&'b dyn Ast<'a>> {
+    let inputs = p.input_bounds.iter().collect::<HashSet<_>>();
+    let outputs = p.output_bounds.iter().collect::<HashSet<_>>();
+    inputs
+        .into_iter()
+        .map(|i| i as &dyn Ast)
+        .chain(outputs.into_iter().map(|o| o as &dyn Ast))
+        .chain(p.edge_bounds.iter().map(|e| e as &dyn Ast))
+        .collect()
+}
+
+/// Function that generates a function to prove if a given z3 model honors a contract on its external interface
+///
+/// # Definition
+///
+/// Contract: When each input in `supplies`, identified by its entity id, carries the given amount of items
+/// and all other inputs are idle, the outputs in `demands` can carry at least the given amount of items.
+/// Outputs that are not part of the contract are unconstrained.
+///
+/// This is a feasibility proof, not a balance proof: it allows verifying a fragment of a bigger design
+/// against the rates of the belts it is connected to. The rates are converted like the throughputs of the entities,
+/// see [`exact_throughput`], so a boundary demand of exactly the throughput of a belt is compared with its capacity.
 ///
 /// To prove:
 /// ```text
-/// forall inputs, outputs. in_out_eq -> exist edges. model holds
+/// exist inputs, outputs, edges. model holds and supplies and demands
 /// ```
 /// Find a counterexample:
 /// ```text
-/// not forall inputs, outputs. in_out_eq -> exist edges. model holds
-/// not forall inputs, outputs. not in_out_eq or exist edges. model holds
-/// exist inputs, outputs. in_out_eq and not exist edges. model holds
-/// inputs, outputs. in_out_eq and forall edges. model does NOT hold
+/// forall inputs, outputs, edges. not (model holds and supplies and demands)
 /// ```
-pub fn throughput_unlimited<'a>(
-    entities: Vec<FBEntity<i32>>,
+pub fn contract_f<'a>(
+    supplies: Vec<(EntityId, f64)>,
+    demands: Vec<(EntityId, f64)>,
 ) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
-    let i = move |p: ProofPrimitives<'a>| {
-        let zero = Int::from_i64(p.ctx, 0);
-        // `input_condition` adds the following constraint to all inputs (0 <= input <= capacity)
-        let input_constraints = p
+    move |p: ProofPrimitives<'a>| {
+        let supply_conditions = p
             .input_map
             .iter()
-            .map(|(idx, v)| {
-                let lower = v.ge(&zero);
-
-                let entity_id = p.graph[*idx].get_id();
-                let capacity = entities
+            .map(|(idx, input)| {
+                let id = p.graph[*idx].get_id();
+                let supply = supplies
                     .iter()
-                    .find(|e| e.get_base().id == entity_id)
-                    .unwrap()
-                    .get_base()
-                    .throughput as i64;
-                let upper_const = Int::from_i64(p.ctx, capacity);
-                let upper = v.le(&upper_const);
-                Bool::and(p.ctx, &[&lower, &upper])
+                    .find(|(supplied, _)| *supplied == id)
+                    .map_or(0.0, |(_, supply)| *supply);
+                input._eq(&exact_capacity(supply).to_z3(p.ctx))
             })
             .collect::<Vec<_>>();
-        let input_condition = vec_and(p.ctx, &input_constraints);
-
-        let zero = Real::from_int(&zero);
-        // `output_condition` adds the following constraint to all outputs (0 <= output <= capacity)
-        let output_constraints = p
+        let demand_conditions = p
             .output_map
             .iter()
-            .map(|(idx, v)| {
-                let lower = v.ge(&zero);
-
-                let entity_id = p.graph[*idx].get_id();
-                let capacity = entities
-                    .iter()
-                    .find(|e| e.get_base().id == entity_id)
-                    .unwrap()
-                    .get_base()
-                    .throughput as i64;
-                let upper_const = Real::from_int(&Int::from_i64(p.ctx, capacity));
-                let upper = v.le(&upper_const);
-                Bool::and(p.ctx, &[&lower, &upper])
+            .filter_map(|(idx, output)| {
+                let id = p.graph[*idx].get_id();
+                let (_, demand) = demands.iter().find(|(demanded, _)| *demanded == id)?;
+                Some(output.ge(&exact_capacity(*demand).to_z3(p.ctx)))
             })
             .collect::<Vec<_>>();
-        let output_condition = vec_and(p.ctx, &output_constraints);
-
-        let outputs = p.output_map.values().collect::<Vec<_>>();
-        let output_sum = if !outputs.is_empty() {
-            Real::add(p.ctx, &outputs)
-        } else {
-            zero.clone()
-        };
+        let contract = Bool::and(
+            p.ctx,
+            &[
+                &p.model_constraint,
+                &vec_and(p.ctx, &supply_conditions),
+                &vec_and(p.ctx, &demand_conditions),
+            ],
+        );
 
-        let inputs = p.input_map.values().collect::<Vec<_>>();
-        let input_sum = if !inputs.is_empty() {
-            Real::from_int(&Int::add(p.ctx, &inputs))
-        } else {
-            zero
-        };
+        forall_const(p.ctx, &flow_variables(&p), &[], &contract.not())
+    }
+}
 
-        let in_out_eq = input_sum._eq(&output_sum);
+/// Function to prove if a given z3 model balances its outputs when all inputs are fed equally
+///
+/// # Definition
+///
+/// Balancer under equal inputs: Blueprint that produces equal outputs whenever all the inputs carry the same amount of items.
+///
+/// This is weaker than a [`belt_balancer_f`], which has to produce equal outputs for every combination of inputs:
+/// e.g. belts running straight from each input to an output pass this check, but they don't balance at all
+/// if a single input is fed. It matches designs that are only ever fed equally and is cheaper to solve.
+///
+/// The `model_condition` states that the z3 model is modelled correctly, the inputs are equal and the outputs are NOT equal.
+/// This is used to find a counter-example.
+pub fn balancer_under_equal_inputs_f(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let input_condition = equality(p.ctx, &p.input_bounds);
+    let balancer_condition = equality(p.ctx, &p.output_bounds);
+    // Correct model, equal inputs and NOT output equality
+    Bool::and(
+        p.ctx,
+        &[
+            &input_condition,
+            &balancer_condition.not(),
+            &p.model_constraint,
+        ],
+    )
+}
 
-        // Model edge throughput as existentially quantified variables
-        let cast_edge_bounds = p
-            .edge_bounds
+/// Function that generates a function to prove if a given z3 model balances each group of outputs
+///
+/// # Definition
+///
+/// Grouped balancer: The outputs within each group carry the same amount of items.
+/// Outputs of different groups, e.g. of two separate buses, may differ.
+/// Outputs that are not part of any group are unconstrained.
+///
+/// For a single group containing all the outputs this is the same as [`belt_balancer_f`].
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that the outputs of at least one group are NOT equal.
+/// This is used to find a counter-example.
+pub fn grouped_balancer<'a>(
+    groups: Vec<Vec<EntityId>>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let group_conditions = groups
             .iter()
-            .map(|r| r as &dyn Ast)
+            .map(|group| {
+                let outputs = p
+                    .output_map
+                    .iter()
+                    .filter(|(idx, _)| group.contains(&p.graph[**idx].get_id()))
+                    .map(|(_, v)| v.clone())
+                    .collect::<Vec<_>>();
+                equality(p.ctx, &outputs)
+            })
+            .collect::<Vec<_>>();
+        let grouped_condition = vec_and(p.ctx, &group_conditions);
+        // Correct model and NOT equality within all the groups
+        Bool::and(p.ctx, &[&grouped_condition.not(), &p.model_constraint])
+    }
+}
+
+/// Function to prove if a given z3 model is an equal drain belt balancer
+///
+/// # Definiton
+///
+/// Equal drain: When operating all the inputs are consumed equally, not resulting in any imbalances.
+/// E.g. [this](https://fbe.teoxoy.com/?source=0eJyd0ttqwzAMBuB30bVTkjSliy/3GqUUJ1WLwFGMrYyFkHefnYxRGPR0aVv/JyN7gsYO6DyxgJ6A2p4D6MMEga5sbNqT0SFoIMEOFLDp0upigmTiDQfXe8katAKzAuIzfoMu5qMCZCEhXLnbWHCWRNBHzfUh1vSc+sRcrmAEneWbXbTO5LFdD/NfbTzx0DUxGTuo6d5d/tEJXfXisV6+qr+Cb9/FnxhL9fZYnsB36VWXb6Bvfo2CL/RhiZQfRbWvy31V1vW2LhRYE7vG6s+/6nn+AWY2ztQ=) is a 2-2 equal drain belt balancer;
+/// [this](https://fbe.teoxoy.com/?source=0eJyVktFqwzAMRf9Fz/ZI0pQuftxvlFGcVh0GRza2MhZC/n1yOkbHYGuejC3dc7myZuj9iDE5YjAzuHOgDOY4Q3ZvZH154ykiGHCMAyggO5Tb1WbWnCzlGBLrHj3DosDRBT/A1MurAiR27PCG+0OmIIYsnYGKm6h19bRXMIGRU5gXl/B8K1df1OlE49BjKk7qJzxH75il9AtbrUz9ALQpQeLIJ5lLSFIRuMdrSbgtyYYguzv2wwPS9f/gdgN4C3df/nhdCnO3QwreMeVV0jzX7aFrDm3TdbuuVuCtuEr3y3f3snwCz/TTyA==) is only a 2-2 belt balancer.
+///
+/// # Precondition
+///
+/// Assumes that the model is a valid belt balancer.
+/// Uses a reversed graph.
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that equality of inputs does NOT imply equality of outputs.
+/// This is used to find a counter-example.
+pub fn equal_drain_f(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let input_eq = equality(p.ctx, &p.input_bounds);
+    let output_eq = equality(p.ctx, &p.output_bounds);
+    // Correct model and equality of inputs does NOT imply equality of outputs
+    Bool::and(
+        p.ctx,
+        &[&p.model_constraint, &input_eq.implies(&output_eq).not()],
+    )
+}
+
+/// Function that generates a function to prove if a given z3 model drains its inputs in the ratio of `weights`
+///
+/// # Definition
+///
+/// Weighted drain: When operating the inputs are consumed in a fixed ratio, i.e. `input_i / weight_i` is the same for all inputs.
+/// The weights are keyed by the entity id of the inputs, inputs without a weight have a weight of 1.
+/// An input with a weight of 0 is never consumed, if all the weights are 0 none of the inputs are.
+/// With all weights equal this is the same as [`equal_drain_f`], other ratios model drains using priorities or uneven splits.
+///
+/// # Precondition
+///
+/// Assumes that the model is a valid belt balancer.
+/// Uses a reversed graph, so the weighted inputs of the blueprint are the outputs of the model.
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that equality of inputs does NOT imply outputs in the ratio of the weights.
+/// This is used to find a counter-example.
+pub fn weighted_drain_f<'a>(
+    weights: HashMap<EntityId, u32>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let input_eq = equality(p.ctx, &p.input_bounds);
+        let weighted = p
+            .output_map
+            .iter()
+            .map(|(idx, v)| {
+                let weight = weights.get(&p.graph[*idx].get_id()).copied().unwrap_or(1);
+                (v, weight)
+            })
+            .collect::<Vec<_>>();
+        /* cross-multiply with an output of non-zero weight: `v / weight == reference / ref_weight` */
+        let weight = |w: u32| Int::from_u64(p.ctx, w.into()).to_real();
+        let zero = Real::from_real(p.ctx, 0, 1);
+        let weighted_eq = weighted
+            .iter()
+            .map(|(v, w)| match weighted.iter().find(|(_, w)| *w != 0) {
+                Some((reference, ref_weight)) => {
+                    (*v * weight(*ref_weight))._eq(&(*reference * weight(*w)))
+                }
+                None => v._eq(&zero),
+            })
+            .collect::<Vec<_>>();
+        let weighted_eq = Bool::and(p.ctx, &weighted_eq.iter().collect::<Vec<_>>());
+        // Correct model and equality of inputs does NOT imply weighted outputs
+        Bool::and(
+            p.ctx,
+            &[&p.model_constraint, &input_eq.implies(&weighted_eq).not()],
+        )
+    }
+}
+
+/// Function that generates a function to prove if a given z3 model is a proportional belt balancer
+///
+/// # Definition
+///
+/// Proportional balancer: The flow of every output is proportional to its capacity,
+/// i.e. `output_i / capacity_i` is the same for all outputs.
+/// The capacity of an output is the throughput of its belt, even if a slower belt upstream limits the flow to it.
+///
+/// For outputs of the same tier this is the same as a belt balancer,
+/// for mixed-tier outputs it is the right notion of balance as faster belts should receive more items.
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that the outputs are NOT proportional to their capacities.
+/// This is used to find a counter-example.
+pub fn proportional_balancer_f<'a>(
+    entities: Vec<FBEntity<i32>>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let ratios = p
+            .output_map
+            .iter()
+            .map(|(idx, v)| {
+                let throughput = entity_throughput(&entities, p.graph[*idx].get_id());
+                v.div(&exact_capacity(throughput).to_z3(p.ctx))
+            })
+            .collect::<Vec<_>>();
+        let proportional_condition = equality(p.ctx, &ratios);
+        // Correct model and NOT proportional outputs
+        Bool::and(p.ctx, &[&proportional_condition.not(), &p.model_constraint])
+    }
+}
+
+/// Function to check that the priority splitters of a z3 model behave correctly
+///
+/// # Definition
+///
+/// A priority splitter sends all its input to the priority output until that output is saturated,
+/// only the overflow goes to the other output:
+/// - if `input < prio capacity` then `prio output == input` and `other output == 0`
+/// - otherwise `prio output == prio capacity`
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that at least one priority splitter does NOT behave as defined.
+/// This is used to find a counter-example, i.e. a bug in the modelling of priority splitters.
+pub fn priority_correctness_f(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let zero = Real::from_real(p.ctx, 0, 1);
+    let conditions = p
+        .graph
+        .node_indices()
+        .filter_map(|idx| match &p.graph[idx] {
+            Node::Splitter(s) if !s.output_priority.is_none() => Some((idx, s.output_priority)),
+            _ => None,
+        })
+        .map(|(idx, side)| {
+            let in_var = &p.edge_map[&p.graph.in_edge_idx(idx)[0]];
+            let prio_idx = p.graph.get_edge(idx, Outgoing, side);
+            let prio_var = &p.edge_map[&prio_idx];
+            let other_var = &p.edge_map[&p.graph.get_edge(idx, Outgoing, -side)];
+            let prio_cap = p.graph[prio_idx].capacity.to_z3(p.ctx);
+
+            let unsaturated = Bool::and(p.ctx, &[&prio_var._eq(in_var), &other_var._eq(&zero)]);
+            in_var
+                .lt(&prio_cap)
+                .ite(&unsaturated, &prio_var._eq(&prio_cap))
+        })
+        .collect::<Vec<_>>();
+    // Correct model and NOT all priority splitters behave correctly
+    Bool::and(
+        p.ctx,
+        &[&vec_and(p.ctx, &conditions).not(), &p.model_constraint],
+    )
+}
+
+/// Returns the throughput of the entity with the given `id`.
+///
+/// The nodes of a reversed graph keep their ids, so an input of the reversed graph is bounded
+/// by the throughput of the physical belt it was an output of.
+fn entity_throughput(entities: &[FBEntity<i32>], id: EntityId) -> f64 {
+    entities
+        .iter()
+        .find(|e| e.get_base().id == id)
+        .unwrap()
+        .get_base()
+        .throughput
+}
+
+/// Returns `throughput` as an exact rational, see [`exact_throughput`]
+fn exact_capacity(throughput: f64) -> GenericFraction<u128> {
+    exact_throughput(throughput).unwrap_or_else(|| GenericFraction::from(throughput))
+}
+
+/// Constrains each of the variables to be between zero and the throughput of the entity of its node
+fn capacity_bound<'a, 'b>(
+    p: &ProofPrimitives<'a>,
+    entities: &[FBEntity<i32>],
+    iter: impl Iterator<Item = (&'b NodeIndex, Real<'a>)>,
+) -> Bool<'a> {
+    let zero = Real::from_real(p.ctx, 0, 1);
+    let conditions = iter
+        .map(|(idx, v)| {
+            let lower = v.ge(&zero);
+
+            let throughput = entity_throughput(entities, p.graph[*idx].get_id());
+            let upper = v.le(&exact_capacity(throughput).to_z3(p.ctx));
+            Bool::and(p.ctx, &[&lower, &upper])
+        })
+        .collect::<Vec<_>>();
+    vec_and(p.ctx, &conditions)
+}
+
+/// Function that generates a function to prove that no output of a given z3 model exceeds the throughput of its belt
+///
+/// # Definition
+///
+/// No overflow: For all inputs within the throughput of their belts, each output carries between zero and the throughput of its belt.
+///
+/// The capacities of the edges are derived from the throughputs of the entities when compiling and simplifying the graph,
+/// so this holds for every correct model. A counter-example points to a bug in the propagation of the capacities.
+///
+/// The `model_condition` states that the z3 model is modelled correctly, the inputs are valid and at least one output is NOT within its throughput.
+/// This is used to find a counter-example.
+pub fn no_overflow_f<'a>(entities: Vec<FBEntity<i32>>) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let inputs = p.input_map.iter().map(|(idx, v)| (idx, v.clone()));
+        let input_condition = capacity_bound(&p, &entities, inputs);
+        let outputs = p.output_map.iter().map(|(idx, v)| (idx, v.clone()));
+        let output_condition = capacity_bound(&p, &entities, outputs);
+        // Correct model, valid inputs and NOT all outputs within their throughput
+        Bool::and(
+            p.ctx,
+            &[
+                &p.model_constraint,
+                &input_condition,
+                &output_condition.not(),
+            ],
+        )
+    }
+}
+
+/// Function that generates a function to prove if a given z3 model is a throughput unlimited belt balancer
+///
+/// # Definition
+///
+/// Throughput unlimited:
+///
+/// # Precondition
+///
+/// Assumes that the model is a valid belt balancer.
+///
+/// The inputs and outputs are bounded by the throughputs of their `entities`, which the graph must be compiled from.
+/// To assume other belts for the outputs, compile the graph from [`with_output_caps`](crate::analyze::with_output_caps).
+///
+/// To prove:
+/// ```text
+/// forall inputs, outputs. in_out_eq -> exist edges. model holds
+/// ```
+/// Find a counterexample:
+/// ```text
+/// not forall inputs, outputs. in_out_eq -> exist edges. model holds
+/// not forall inputs, outputs. not in_out_eq or exist edges. model holds
+/// exist inputs, outputs. in_out_eq and not exist edges. model holds
+/// inputs, outputs. in_out_eq and forall edges. model does NOT hold
+/// ```
+pub fn throughput_unlimited<'a>(
+    entities: Vec<FBEntity<i32>>,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    let i = move |p: ProofPrimitives<'a>| {
+        let zero = Real::from_real(p.ctx, 0, 1);
+        // `input_condition` adds the following constraint to all inputs (0 <= input <= capacity)
+        let inputs = p.input_map.iter().map(|(idx, v)| (idx, v.clone()));
+        let input_condition = capacity_bound(&p, &entities, inputs);
+
+        // `output_condition` adds the following constraint to all outputs (0 <= output <= capacity)
+        let outputs = p.output_map.iter().map(|(idx, v)| (idx, v.clone()));
+        let output_condition = capacity_bound(&p, &entities, outputs);
+
+        let outputs = p.output_map.values().collect::<Vec<_>>();
+        let output_sum = if !outputs.is_empty() {
+            Real::add(p.ctx, &outputs)
+        } else {
+            zero.clone()
+        };
+
+        let inputs = p.input_map.values().collect::<Vec<_>>();
+        let input_sum = if !inputs.is_empty() {
+            Real::add(p.ctx, &inputs)
+        } else {
+            zero
+        };
+
+        let in_out_eq = input_sum._eq(&output_sum);
+
+        // Model edge throughput as existentially quantified variables
+        let cast_edge_bounds = p
+            .edge_bounds
+            .iter()
+            .map(|r| r as &dyn Ast)
             .collect::<Vec<_>>();
 
         let no_model = forall_const(p.ctx, &cast_edge_bounds, &[], &p.model_constraint.not());
@@ -313,8 +1042,8 @@ pub fn throughput_unlimited<'a>(
     i
 }
 
-/// input, output, blocked. BLOCKING, MODEL and not OUT_EQ
-pub fn universal_balancer(p: ProofPrimitives<'_>) -> Bool<'_> {
+/// Equality of all the outputs that are not blocked
+fn unblocked_outputs_eq<'a>(p: &ProofPrimitives<'a>) -> Bool<'a> {
     let eq_value = Real::new_const(p.ctx, "output_value");
     let outputs_eq_value = p
         .output_map
@@ -325,7 +1054,165 @@ pub fn universal_balancer(p: ProofPrimitives<'_>) -> Bool<'_> {
         })
         .collect::<Vec<_>>();
     let out_eq = vec_and(p.ctx, &outputs_eq_value);
-    let out_eq_condition = exists_const(p.ctx, &[&eq_value], &[], &out_eq);
+    exists_const(p.ctx, &[&eq_value], &[], &out_eq)
+}
+
+/// Classification of a balancer by whether it is throughput unlimited with and without priorities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuClassification {
+    /// Throughput unlimited even if all priorities are cleared
+    WithoutPriorities,
+    /// Throughput unlimited only with the configured priorities
+    WithPriorities,
+    /// Not throughput unlimited
+    NotTu,
+}
+
+/// Classifies the graph by running the [`throughput_unlimited`] proof with and without priorities.
+///
+/// The throughput unlimited proof uses the [`ModelFlags::Relaxed`] model, which drops the splitter conditions,
+/// so the priorities only influence the result through the capacities computed when simplifying the graph:
+/// e.g. a priority splitter whose priority output can take its whole input never uses its other output.
+/// The `graph` must therefore not be simplified yet, it is simplified here once with its priorities
+/// and once with all of them cleared, removing the inputs and outputs in `exclude_list`.
+pub fn classify_throughput_unlimited(
+    graph: &FlowGraph,
+    ctx: &Context,
+    entities: Vec<FBEntity<i32>>,
+    exclude_list: &[EntityId],
+) -> TuClassification {
+    let mut with_graph = graph.clone();
+    with_graph.simplify(exclude_list, CoalesceStrength::Aggressive);
+    let with_priorities = model_f(
+        &with_graph,
+        ctx,
+        throughput_unlimited(entities.clone()),
+        ModelFlags::Relaxed,
+    );
+
+    let mut without_graph = graph.clone();
+    for node in without_graph.node_weights_mut() {
+        match node {
+            Node::Splitter(s) => s.output_priority = Side::None,
+            Node::Merger(m) => m.input_priority = Side::None,
+            _ => (),
+        }
+    }
+    without_graph.simplify(exclude_list, CoalesceStrength::Aggressive);
+    let without_priorities = model_f(
+        &without_graph,
+        ctx,
+        throughput_unlimited(entities),
+        ModelFlags::Relaxed | ModelFlags::IgnorePriority,
+    );
+    match (with_priorities, without_priorities) {
+        (_, ProofResult::Sat) => TuClassification::WithoutPriorities,
+        (ProofResult::Sat, _) => TuClassification::WithPriorities,
+        _ => TuClassification::NotTu,
+    }
+}
+
+/// Returns the maximum load, as a fraction of the throughput of the inputs and outputs,
+/// up to which the graph is throughput unlimited.
+///
+/// Proving [`throughput_unlimited`] with inputs and outputs limited to `load` times their throughput
+/// is the same as proving it with all edge capacities divided by `load`, which is what is done here.
+/// The load is found by a binary search over `[0, 1]` with the given number of `steps`,
+/// so the result is a lower bound that is exact up to `1 / 2^steps`.
+pub fn max_throughput_unlimited_load(
+    graph: &FlowGraph,
+    ctx: &Context,
+    entities: Vec<FBEntity<i32>>,
+    steps: u32,
+) -> GenericFraction<u128> {
+    let is_tu = |load: GenericFraction<u128>| {
+        let mut scaled = graph.clone();
+        for edge in scaled.edge_weights_mut() {
+            edge.capacity /= load;
+        }
+        let f = throughput_unlimited(entities.clone());
+        model_f(&scaled, ctx, f, ModelFlags::Relaxed) == ProofResult::Sat
+    };
+    let (mut low, mut high) = (GenericFraction::from(0), GenericFraction::from(1));
+    if is_tu(high) {
+        return high;
+    }
+    for _ in 0..steps {
+        let mid = (low + high) / 2;
+        if is_tu(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Function that generates a function to prove if a given z3 model still balances when the output `blocked` backs up
+///
+/// # Definition
+///
+/// Balancer with a blocked output: For every combination of inputs, when the output with the entity id `blocked`
+/// is backed up and all the other outputs consume freely, the other outputs carry the same amount of items.
+///
+/// This answers "if output N backs up, do the others still balance?" for a single output,
+/// a focused and cheaper subset of [`universal_balancer`], which considers every subset of the outputs backing up.
+///
+/// # Precondition
+///
+/// Uses a graph modelled with [`ModelFlags::Blocked`].
+///
+/// The `model_condition` states that the z3 model is modelled correctly, including the blocking of the belts,
+/// that only the output `blocked` is blocked and the other outputs are NOT equal. This is used to find a counter-example.
+pub fn blocked_output_balancer_f<'a>(
+    blocked: EntityId,
+) -> impl Fn(ProofPrimitives<'a>) -> Bool<'a> {
+    move |p: ProofPrimitives<'a>| {
+        let mut pinned = vec![];
+        let mut free_outputs = vec![];
+        for (idx, output) in &p.output_map {
+            let is_blocked = p.blocked_output_map.get(idx).unwrap();
+            if p.graph[*idx].get_id() == blocked {
+                pinned.push(is_blocked.clone());
+            } else {
+                pinned.push(is_blocked.not());
+                free_outputs.push(output.clone());
+            }
+        }
+        let balancer_condition = equality(p.ctx, &free_outputs);
+        let blocking_p = vec_and(p.ctx, &p.blocking_constraint);
+        // Correct model, only the given output blocked and NOT equality of the other outputs
+        Bool::and(
+            p.ctx,
+            &[
+                &blocking_p,
+                &vec_and(p.ctx, &pinned),
+                &p.model_constraint,
+                &balancer_condition.not(),
+            ],
+        )
+    }
+}
+
+/// Function to prove if a given z3 model is a universal belt balancer, i.e. a belt balancer when its outputs may back up
+///
+/// # Definition
+///
+/// Balancer under backpressure: For every combination of inputs and every subset of outputs that is backed up,
+/// the outputs that are not backed up carry the same amount of items.
+///
+/// This is closer to a balancer in a running factory than [`belt_balancer_f`], where every output consumes freely.
+/// A backed up output blocks the belts leading to it, which then carry zero items, and splitters send all their items
+/// to the side that is not blocked. A design balancing in free flow might not balance anymore once some of its outputs back up.
+///
+/// # Precondition
+///
+/// Uses a graph modelled with [`ModelFlags::Blocked`].
+///
+/// The `model_condition` states that the z3 model is modelled correctly, including the blocking of the belts,
+/// and the outputs that are not blocked are NOT equal. This is used to find a counter-example.
+pub fn universal_balancer(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let out_eq_condition = unblocked_outputs_eq(&p);
     let blocking_p = vec_and(p.ctx, &p.blocking_constraint);
     Bool::and(
         p.ctx,
@@ -333,12 +1220,91 @@ pub fn universal_balancer(p: ProofPrimitives<'_>) -> Bool<'_> {
     )
 }
 
+/// Function to prove if a given z3 model is a universal equal drain belt balancer
+///
+/// # Definition
+///
+/// Universal equal drain: The inputs that still carry items are consumed equally,
+/// for any subset of inputs running dry.
+///
+/// # Precondition
+///
+/// Uses a reversed graph modelled with [`ModelFlags::Blocked`].
+/// In the reversed graph a blocked output is an input of the original graph that ran dry.
+///
+/// The `model_condition` states that the z3 model is modelled correctly and that equality of inputs does NOT imply equality of the outputs that are not blocked.
+/// This is used to find a counter-example.
+pub fn universal_equal_drain_f(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let input_eq = equality(p.ctx, &p.input_bounds);
+    let out_eq_condition = unblocked_outputs_eq(&p);
+    let blocking_p = vec_and(p.ctx, &p.blocking_constraint);
+    Bool::and(
+        p.ctx,
+        &[
+            &blocking_p,
+            &p.model_constraint,
+            &input_eq.implies(&out_eq_condition).not(),
+        ],
+    )
+}
+
+/// Function to prove that a given z3 model can not deadlock
+///
+/// # Definition
+///
+/// No deadlock: As long as at least one input is supplied and at least one output is not blocked,
+/// the design delivers a nonzero throughput.
+/// A design that deadlocks, e.g. a loop with priority on itself that fills up and locks out the inputs,
+/// can not move any items even though nothing is backed up.
+///
+/// # Precondition
+///
+/// Uses a graph modelled with [`ModelFlags::Blocked`].
+/// An input that is not blocked is supplied with items.
+///
+/// The `model_condition` states that the blocking is modelled correctly, that an input and an output are not blocked,
+/// and that no flow satisfying the model reaches the outputs. This is used to find a deadlock as counter-example:
+/// ```text
+/// exist blocked. blocking and (exist input. not blocked) and (exist output. not blocked)
+///     and forall inputs, outputs, edges. not (model and output total > 0)
+/// ```
+pub fn no_deadlock_f(p: ProofPrimitives<'_>) -> Bool<'_> {
+    let blocking_p = vec_and(p.ctx, &p.blocking_constraint);
+    let supplied = p
+        .blocked_input_map
+        .values()
+        .map(|blocked| blocked.not())
+        .collect::<Vec<_>>();
+    let unblocked = p
+        .blocked_output_map
+        .values()
+        .map(|blocked| blocked.not())
+        .collect::<Vec<_>>();
+    let supplied = Bool::or(p.ctx, &supplied.iter().collect::<Vec<_>>());
+    let unblocked = Bool::or(p.ctx, &unblocked.iter().collect::<Vec<_>>());
+
+    let zero = Real::from_real(p.ctx, 0, 1);
+    let outputs = p.output_bounds.iter().collect::<Vec<_>>();
+    let output_total = if outputs.is_empty() {
+        zero.clone()
+    } else {
+        Real::add(p.ctx, &outputs)
+    };
+    let delivers = Bool::and(p.ctx, &[&p.model_constraint, &output_total.gt(&zero)]);
+
+    let no_flow = forall_const(p.ctx, &flow_variables(&p), &[], &delivers.not());
+
+    Bool::and(p.ctx, &[&blocking_p, &supplied, &unblocked, &no_flow])
+}
+
 #[cfg(test)]
 mod tests {
     use z3::Config;
 
     use super::*;
-    use crate::ir::CoalesceStrength;
+    use crate::analyze::{classify, BalancerClass};
+    use crate::backends::{edge_var_name, input_var_name, output_var_name};
+    use crate::ir::{CoalesceStrength, FlowGraphAnalysis, GraphHelper, Node, Reversable};
     use crate::{frontend::Compiler, import::file_to_entities, ir::FlowGraphFun};
 
     // TODO: figure out lifetimes and fix code duplication
@@ -461,15 +1427,158 @@ mod tests {
         assert!(matches!(res, ProofResult::Unsat));
     }
 
+    /// The 4-4 balancer balances in free flow, but not once some outputs back up.
+    /// A single splitter sends everything to the other output if one backs up, so it still balances.
     #[test]
-    fn empty_belt_balancer() {
-        let entities = vec![];
-        let mut graph = Compiler::new(entities).create_graph();
-        graph.simplify(&[], CoalesceStrength::Aggressive);
+    fn balancer_under_backpressure() {
         let cfg = Config::new();
         let ctx = Context::new(&cfg);
-        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
-        assert!(matches!(res, ProofResult::Sat));
+        let prove = |file: &str| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            (
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty()),
+                model_f(&graph, &ctx, universal_balancer, ModelFlags::Blocked),
+            )
+        };
+        assert_eq!(
+            prove("tests/4-4-tu"),
+            (ProofResult::Sat, ProofResult::Unsat)
+        );
+        assert_eq!(prove("tests/2-2"), (ProofResult::Sat, ProofResult::Sat));
+    }
+
+    /// The 3-3 balancer is not universal, but keeps balancing if only its left output backs up
+    #[test]
+    fn blocked_output_balancer() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let entities = file_to_entities("tests/3-3").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let prove = |blocked| {
+            model_f(
+                &graph,
+                &ctx,
+                blocked_output_balancer_f(blocked),
+                ModelFlags::Blocked,
+            )
+        };
+        assert_eq!(
+            model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty()),
+            ProofResult::Sat
+        );
+        assert_eq!(
+            model_f(&graph, &ctx, universal_balancer, ModelFlags::Blocked),
+            ProofResult::Unsat
+        );
+        assert_eq!(prove(24), ProofResult::Sat);
+        assert_eq!(prove(25), ProofResult::Unsat);
+        assert_eq!(prove(26), ProofResult::Unsat);
+    }
+
+    /// The belt side-loading into the loop can't enter once the loop, which has priority on itself, is full.
+    /// The empty input of the splitter (with the id of the splitter) is not supplied.
+    #[test]
+    fn deadlock() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |file: &str, exclude: &[EntityId]| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(exclude, CoalesceStrength::Aggressive);
+            model_f(&graph, &ctx, no_deadlock_f, ModelFlags::Blocked)
+        };
+        assert_eq!(prove("tests/deadlock", &[1]), ProofResult::Unsat);
+        /* supplying the splitter directly gets the items out */
+        assert_eq!(prove("tests/deadlock", &[]), ProofResult::Sat);
+        assert_eq!(prove("tests/2-2", &[]), ProofResult::Sat);
+        assert_eq!(prove("tests/4-4-tu", &[]), ProofResult::Sat);
+    }
+
+    /// The splitter gives priority to the left output, so the right output only gets the overflow.
+    #[test]
+    fn contract() {
+        let entities = file_to_entities("tests/contract").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |supplies: Vec<(EntityId, f64)>, demands: Vec<(EntityId, f64)>| {
+            model_f(
+                &graph,
+                &ctx,
+                contract_f(supplies, demands),
+                ModelFlags::empty(),
+            )
+        };
+        assert_eq!(prove(vec![(2, 15.0)], vec![(3, 15.0)]), ProofResult::Sat);
+        assert_eq!(
+            prove(vec![(2, 15.0)], vec![(3, 10.0), (4, 5.0)]),
+            ProofResult::Unsat
+        );
+        /* the splitter takes up to two belts, the overflow reaches the right output */
+        assert_eq!(
+            prove(vec![(1, 15.0), (2, 15.0)], vec![(3, 15.0), (4, 15.0)]),
+            ProofResult::Sat
+        );
+        assert_eq!(prove(vec![(2, 15.0)], vec![(4, 0.5)]), ProofResult::Unsat);
+
+        // the full throughput of 2.31/s belts passes at the boundary, but not any more
+        let entities = file_to_entities("tests/contract")
+            .unwrap()
+            .into_iter()
+            .map(|mut e| {
+                e.get_base_mut().throughput = 2.31;
+                e
+            })
+            .collect();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let prove = |supply: f64, demand: f64| {
+            let contract = contract_f(vec![(2, supply)], vec![(3, demand)]);
+            model_f(&graph, &ctx, contract, ModelFlags::empty())
+        };
+        assert_eq!(prove(2.31, 2.31), ProofResult::Sat);
+        assert_eq!(prove(2.31, 2.32), ProofResult::Unsat);
+        assert_eq!(prove(2.32, 2.31), ProofResult::Unsat);
+    }
+
+    #[test]
+    fn model_size_scales() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let size = |file: &str, flags: ModelFlags| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let (inputs, outputs) = graph.io_dimensions();
+            (model_size(&graph, &ctx, flags), inputs + outputs)
+        };
+
+        let (small, small_io) = size("tests/3-2", ModelFlags::empty());
+        let (large, large_io) = size("tests/6-3-tu", ModelFlags::empty());
+        assert_eq!(small.variables, small.edges + small_io);
+        assert_eq!(large.variables, large.edges + large_io);
+        assert!(large.edges > small.edges);
+        assert!(large.constraints > small.constraints);
+
+        // every edge gets a blocked literal
+        let (blocked, _) = size("tests/3-2", ModelFlags::Blocked);
+        assert_eq!(blocked.variables, small.variables + small.edges);
+        assert!(blocked.constraints > small.constraints);
+    }
+
+    #[test]
+    fn empty_belt_balancer() {
+        let entities = vec![];
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+        assert!(matches!(res, ProofResult::Sat));
     }
 
     #[test]
@@ -483,6 +1592,83 @@ mod tests {
         assert!(matches!(res, ProofResult::Sat));
     }
 
+    #[test]
+    fn weighted_drain() {
+        // inputs 1 and 2 are merged by a splitter, which is merged with input 6 by another splitter
+        let entities = file_to_entities("tests/weighted_drain").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        // exclude the free sides of the splitters
+        graph.simplify(&[3, 5], CoalesceStrength::Aggressive);
+        let graph = graph.reverse();
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let weights = HashMap::from([(1, 1), (2, 1), (6, 2)]);
+        let res = model_f(&graph, &ctx, weighted_drain_f(weights), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Sat);
+
+        let res = model_f(&graph, &ctx, equal_drain_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+        let weights = HashMap::from([(1, 2), (2, 1), (6, 1)]);
+        let res = model_f(&graph, &ctx, weighted_drain_f(weights), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+
+        // weights above i32::MAX don't wrap
+        let weights = HashMap::from([(1, 1_500_000_000), (2, 1_500_000_000), (6, 3_000_000_000)]);
+        let res = model_f(&graph, &ctx, weighted_drain_f(weights), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Sat);
+
+        // an input with a weight of 0 is never consumed, but all of them are
+        for weights in [
+            HashMap::from([(1, 0), (2, 1), (6, 2)]),
+            HashMap::from([(1, 0), (2, 0), (6, 0)]),
+        ] {
+            let res = model_f(&graph, &ctx, weighted_drain_f(weights), ModelFlags::empty());
+            assert_eq!(res, ProofResult::Unsat);
+        }
+    }
+
+    #[test]
+    fn fixed_ratio() {
+        // a turbo input with priority on an express output, the overflow goes to a yellow output
+        let entities = file_to_entities("tests/ratio_router").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        // exclude the free input side of the splitter
+        graph.simplify(&[1], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let ratios = HashMap::from([(3, 3), (4, 1)]);
+        let res = model_f(&graph, &ctx, fixed_ratio_f(ratios), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Sat);
+
+        let ratios = HashMap::from([(3, 2), (4, 1)]);
+        let res = model_f(&graph, &ctx, fixed_ratio_f(ratios), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+        // not a balancer, the ratio only holds under saturation
+        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+
+        // the input can also be saturated with a non-integer throughput
+        let entities = entities
+            .into_iter()
+            .map(|mut e| {
+                e.get_base_mut().throughput *= 1.01;
+                e
+            })
+            .collect();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[1], CoalesceStrength::Aggressive);
+        let ratios = HashMap::from([(3, 2), (4, 1)]);
+        let res = model_f(&graph, &ctx, fixed_ratio_f(ratios), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+
+        // an output with a ratio of 0 receives items
+        let ratios = HashMap::from([(3, 1), (4, 0)]);
+        let res = model_f(&graph, &ctx, fixed_ratio_f(ratios), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
     #[test]
     fn empty_throughput_unlimited() {
         let entities = vec![];
@@ -509,4 +1695,688 @@ mod tests {
         let res = model_f(&graph, &ctx, equal_drain_f, ModelFlags::Blocked);
         assert!(matches!(res, ProofResult::Sat));
     }
+
+    #[test]
+    fn output_tier_caps_output() {
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let throughput = |id| {
+            let entity = entities.iter().find(|e| e.get_base().id == id).unwrap();
+            entity.get_base().throughput as i64
+        };
+
+        // the edge feeding each output is capped by the tier of the output belt
+        for idx in graph.node_indices() {
+            if let Node::Output(o) = &graph[idx] {
+                let capacity = graph.in_edges(idx)[0].capacity;
+                assert_eq!(capacity, throughput(o.id).into());
+            }
+        }
+
+        // no output can carry more than the throughput of its belt
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let res = model_f(
+            &graph,
+            &ctx,
+            no_overflow_f(entities.clone()),
+            ModelFlags::empty(),
+        );
+        assert!(matches!(res, ProofResult::Sat));
+
+        let res = model_f(
+            &graph,
+            &ctx,
+            throughput_unlimited(entities.clone()),
+            ModelFlags::Relaxed,
+        );
+        assert!(matches!(res, ProofResult::Sat));
+    }
+
+    #[test]
+    fn no_overflow() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        for file in ["tests/4-4", "tests/belt_merge", "tests/6-3-tu"] {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let res = model_f(&graph, &ctx, no_overflow_f(entities), ModelFlags::empty());
+            assert!(matches!(res, ProofResult::Sat), "{}", file);
+        }
+
+        // two yellow belts side-loading a yellow belt can only deliver 15 items/s,
+        // dropping the capacity of the merged belt lets its output overflow
+        let entities = file_to_entities("tests/belt_merge").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        for edge in graph.edge_weights_mut() {
+            edge.capacity = 100.into();
+        }
+        let res = model_f(&graph, &ctx, no_overflow_f(entities), ModelFlags::empty());
+        assert!(matches!(res, ProofResult::Unsat));
+    }
+
+    /// Reversing swaps the inputs and outputs but keeps the ids, so the capacity bounds of a
+    /// reversed graph must still be the throughputs of the physical belts
+    #[test]
+    fn reversed_capacity_bounds() {
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let reversed = graph.reverse();
+
+        for idx in reversed.node_indices() {
+            let edges = match reversed[idx] {
+                Node::Input(_) => reversed.out_edge_idx(idx),
+                Node::Output(_) => reversed.in_edge_idx(idx),
+                _ => continue,
+            };
+            let throughput = entity_throughput(&entities, reversed[idx].get_id());
+            assert_eq!(
+                GenericFraction::from(throughput),
+                reversed[edges[0]].capacity,
+                "{:?}",
+                reversed[idx]
+            );
+        }
+
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let res = model_f(
+            &reversed,
+            &ctx,
+            no_overflow_f(entities),
+            ModelFlags::empty(),
+        );
+        assert!(matches!(res, ProofResult::Sat));
+    }
+
+    /// A splitter feeding a slow and a fast belt splits evenly until the slow side is full,
+    /// the overflow then goes to the fast side. This must also hold for unblocked outputs in the blocked model.
+    #[test]
+    fn asymmetric_downstream_backpressure() {
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        // counter-example: with `total` input and no output blocked, the outputs differ from `expected`
+        let prove = |total: i64, expected: [i64; 2], flags: ModelFlags| {
+            model_f(
+                &graph,
+                &ctx,
+                |p| {
+                    let int = |v| Real::from_int(&Int::from_i64(p.ctx, v));
+                    let inputs = p.input_bounds.iter().collect::<Vec<_>>();
+                    let input_total = Real::add(p.ctx, &inputs)._eq(&int(total));
+                    let mut outputs = p.output_map.iter().collect::<Vec<_>>();
+                    // the slow output has the smaller capacity
+                    outputs.sort_by_key(|(idx, _)| p.graph.in_edges(**idx)[0].capacity);
+                    let expected_outputs = outputs
+                        .iter()
+                        .zip(expected)
+                        .map(|((_, v), e)| v._eq(&int(e)))
+                        .collect::<Vec<_>>();
+                    let unblocked = p
+                        .blocked_output_map
+                        .values()
+                        .map(|b| b.not())
+                        .collect::<Vec<_>>();
+                    let blocking = vec_and(p.ctx, &p.blocking_constraint);
+                    Bool::and(
+                        p.ctx,
+                        &[
+                            &p.model_constraint,
+                            &blocking,
+                            &vec_and(p.ctx, &unblocked),
+                            &input_total,
+                            &vec_and(p.ctx, &expected_outputs).not(),
+                        ],
+                    )
+                },
+                flags,
+            )
+        };
+
+        for flags in [ModelFlags::empty(), ModelFlags::Blocked] {
+            assert_eq!(prove(20, [10, 10], flags), ProofResult::Sat);
+            assert_eq!(prove(30, [15, 15], flags), ProofResult::Sat);
+            assert_eq!(prove(50, [15, 35], flags), ProofResult::Sat);
+            assert_eq!(prove(50, [25, 25], flags), ProofResult::Unsat);
+            assert_eq!(prove(60, [15, 45], flags), ProofResult::Sat);
+        }
+
+        // the overflow to the fast output unbalances the outputs, so the design is no balancer at all
+        let (class, analysis) = classify(&graph, entities);
+        assert_eq!(class, BalancerClass::NotBalancer);
+        assert_eq!(analysis.balancer, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn priority_correctness() {
+        let entities = file_to_entities("tests/prio_splitter").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert!(graph
+            .node_weights()
+            .any(|n| matches!(n, Node::Splitter(s) if !s.output_priority.is_none())));
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let res = model_f(&graph, &ctx, priority_correctness_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Sat);
+        // without the splitter conditions the priority is not enforced
+        let res = model_f(&graph, &ctx, priority_correctness_f, ModelFlags::Relaxed);
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn max_tu_load() {
+        let max_load = |file| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let cfg = Config::new();
+            let ctx = Context::new(&cfg);
+            max_throughput_unlimited_load(&graph, &ctx, entities, 8)
+        };
+        assert_eq!(max_load("tests/4-4-tu"), 1.into());
+        // the two inner outputs are only fed by the middle splitter, which receives a single belt
+        // from each input splitter. Two full inputs of the same input splitter can't reach them,
+        // so the balancer is only throughput unlimited up to half load
+        assert_eq!(
+            max_load("tests/4-4-ntu"),
+            GenericFraction::new(1u128, 2u128)
+        );
+    }
+
+    #[test]
+    fn counter_example_3_2_broken() {
+        let entities = file_to_entities("tests/3-2-broken").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let example =
+            counter_example_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty(), None).unwrap();
+        let count = |f: fn(&Node) -> bool| graph.node_weights().filter(|n| f(n)).count();
+        assert_eq!(example.inputs.len(), count(|n| matches!(n, Node::Input(_))));
+        assert_eq!(
+            example.outputs.len(),
+            count(|n| matches!(n, Node::Output(_)))
+        );
+        // the outputs are not balanced
+        assert!(example.outputs.iter().any(|o| o.1 != example.outputs[0].1));
+        assert!(example
+            .to_string()
+            .starts_with("Counter-example with inputs "));
+
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let example = counter_example_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty(), None);
+        assert_eq!(example, None);
+    }
+
+    /// The 1-3 design sends half of its 45/s input to one output and a quarter to each of the others,
+    /// so its outputs differ by up to 45/4 items/s
+    #[test]
+    fn approx_balancer() {
+        let entities = file_to_entities("tests/proportional_1-3").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        // the unused input sides of the splitters (ids 2 and 5) are removed
+        graph.simplify(&[2, 5], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |numer: u128, denom: u128| {
+            let epsilon = GenericFraction::new(numer, denom);
+            model_f(
+                &graph,
+                &ctx,
+                approx_balancer_f(epsilon),
+                ModelFlags::empty(),
+            )
+        };
+        assert_eq!(prove(0, 1), ProofResult::Unsat);
+        assert_eq!(prove(11, 1), ProofResult::Unsat);
+        assert_eq!(prove(45, 4), ProofResult::Sat);
+        assert_eq!(prove(12, 1), ProofResult::Sat);
+        // numerators and denominators beyond `i32`
+        assert_eq!(prove(44_999_999_999, 4_000_000_000), ProofResult::Unsat);
+        assert_eq!(prove(45_000_000_000, 4_000_000_000), ProofResult::Sat);
+
+        // an exact balancer is balanced within any tolerance
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let res = model_f(
+            &graph,
+            &ctx,
+            approx_balancer_f(GenericFraction::from(0)),
+            ModelFlags::empty(),
+        );
+        assert_eq!(res, ProofResult::Sat);
+        // but not within a negative one
+        let res = model_f(
+            &graph,
+            &ctx,
+            approx_balancer_f(-GenericFraction::from(1)),
+            ModelFlags::empty(),
+        );
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    /// The names of the variables in a dump of the model can be traced back to the entities
+    #[test]
+    fn var_names() {
+        let entities = file_to_entities("tests/3-2-broken").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let backend = Z3Backend::new(&ctx, None);
+        let helper = encode_graph(&graph, &backend, ModelFlags::empty());
+
+        for (idx, input) in &helper.input_map {
+            assert_eq!(input.to_string(), input_var_name(graph[*idx].get_id()));
+        }
+        for (idx, output) in &helper.output_map {
+            assert_eq!(output.to_string(), output_var_name(graph[*idx].get_id()));
+        }
+        for (idx, edge) in &helper.edge_map {
+            assert_eq!(edge.to_string(), edge_var_name(&graph, *idx));
+        }
+        let (idx, _) = helper.edge_map.iter().next().unwrap();
+        assert!(edge_var_name(&graph, *idx).ends_with(&format!("_{}", idx.index())));
+    }
+
+    #[test]
+    fn counter_example_positions() {
+        let entities = file_to_entities("tests/3-2-broken").unwrap();
+        let compiler = Compiler::new(entities);
+        let mut graph = compiler.create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let example = counter_example_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty(), None)
+            .unwrap()
+            .with_positions(&compiler.entity_positions());
+
+        let (input, _) = example.inputs[0];
+        let pos = example.positions[&input];
+        assert_eq!(compiler.pos_to_id(&pos), Some(input));
+        assert!(example
+            .to_string()
+            .contains(&format!("{} at ({}, {})", input, pos.x, pos.y)));
+    }
+
+    #[test]
+    fn counter_example_output_distribution() {
+        let entities = file_to_entities("tests/3-2-broken").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let example =
+            counter_example_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty(), None).unwrap();
+
+        let outputs = graph
+            .node_weights()
+            .filter(|n| matches!(n, Node::Output(_)))
+            .count();
+        assert_eq!(example.outputs.len(), outputs);
+        assert!(example.outputs.windows(2).all(|w| w[0].0 < w[1].0));
+        // every item entering the balancer leaves it
+        let input_total = example.inputs.iter().map(|(_, v)| v).sum::<f64>();
+        let output_total = example.outputs.iter().map(|(_, v)| v).sum::<f64>();
+        assert!((input_total - output_total).abs() < 1e-9);
+
+        let differences = example.output_differences();
+        assert_eq!(differences.len(), outputs * (outputs - 1) / 2);
+        assert!(example.max_imbalance() > 0.0);
+    }
+
+    #[test]
+    fn equal_inputs() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |file, f: fn(ProofPrimitives<'_>) -> Bool<'_>| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            model_f(&graph, &ctx, f, ModelFlags::empty())
+        };
+
+        // two belts next to each other only produce equal outputs if they are fed equally
+        let res = prove("tests/parallel_belts", balancer_under_equal_inputs_f);
+        assert_eq!(res, ProofResult::Sat);
+        let res = prove("tests/parallel_belts", belt_balancer_f);
+        assert_eq!(res, ProofResult::Unsat);
+
+        let res = prove("tests/4-4", balancer_under_equal_inputs_f);
+        assert_eq!(res, ProofResult::Sat);
+        let res = prove("tests/4-4-broken", balancer_under_equal_inputs_f);
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn pinned_inputs() {
+        let entities = file_to_entities("tests/splitter_and_belt").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |pinned: &[(EntityId, f64)]| {
+            let pinned = pinned.iter().copied().collect();
+            model_f(&graph, &ctx, pinned_balancer_f(pinned), ModelFlags::empty())
+        };
+
+        // the separate belt (6 -> 8) is not balanced with the splitter outputs (4 and 5)
+        assert_eq!(prove(&[]), ProofResult::Unsat);
+        // when the separate belt is always full, only the splitter outputs vary and they balance
+        assert_eq!(prove(&[(6, 15.0)]), ProofResult::Sat);
+        // pinning an input of the splitter leaves the separate belt free
+        assert_eq!(prove(&[(1, 15.0)]), ProofResult::Unsat);
+        // with all the inputs pinned no output varies, so there is nothing to balance
+        assert_eq!(prove(&[(1, 15.0), (2, 15.0), (6, 15.0)]), ProofResult::Sat);
+        assert_eq!(prove(&[(1, 15.0), (2, 15.0), (6, 5.0)]), ProofResult::Sat);
+
+        // pinned to the throughput of its 2.31/s belt, the input carries exactly the capacity of its edge
+        let entities = file_to_entities("tests/splitter_and_belt")
+            .unwrap()
+            .into_iter()
+            .map(|mut e| {
+                e.get_base_mut().throughput = 2.31;
+                e
+            })
+            .collect();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let pinned = HashMap::from([(1, 2.31)]);
+        let res = model_f(&graph, &ctx, pinned_balancer_f(pinned), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn grouped_outputs() {
+        let entities = file_to_entities("tests/two_buses").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let groups = vec![vec![4, 5], vec![9, 10]];
+        let res = model_f(&graph, &ctx, grouped_balancer(groups), ModelFlags::empty());
+        assert_eq!(res, ProofResult::Sat);
+
+        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+        let res = model_f(
+            &graph,
+            &ctx,
+            grouped_balancer(vec![vec![4, 9]]),
+            ModelFlags::empty(),
+        );
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    #[test]
+    fn proportional_balancer() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |file, removed: &[crate::entities::EntityId], proportional: bool| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(removed, CoalesceStrength::Aggressive);
+            if proportional {
+                model_f(
+                    &graph,
+                    &ctx,
+                    proportional_balancer_f(entities),
+                    ModelFlags::empty(),
+                )
+            } else {
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty())
+            }
+        };
+
+        // the unused input sides of the splitters (ids 2 and 5) are removed
+        let res = prove("tests/proportional_1-3", &[2, 5], true);
+        assert_eq!(res, ProofResult::Sat);
+        let res = prove("tests/proportional_1-3", &[2, 5], false);
+        assert_eq!(res, ProofResult::Unsat);
+
+        let res = prove("tests/mixed_output_tiers", &[], true);
+        assert_eq!(res, ProofResult::Unsat);
+        let res = prove("tests/4-4", &[], true);
+        assert_eq!(res, ProofResult::Sat);
+    }
+
+    /// The express output gets half of the input, like the fast output of `proportional_1-3`:
+    /// the flows are proportional to the capacities of the edges, but not to the throughputs of the output belts.
+    #[test]
+    fn proportional_to_belt_throughput() {
+        let entities = file_to_entities("tests/proportional_bottleneck").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[2, 5], CoalesceStrength::Aggressive);
+        let output = graph
+            .node_indices()
+            .find(|idx| matches!(&graph[*idx], Node::Output(o) if o.id == 9))
+            .unwrap();
+        assert_eq!(
+            graph.in_edges(output)[0].capacity,
+            GenericFraction::new(45u128, 2u128)
+        );
+        assert_eq!(entity_throughput(&entities, 9), 45.0);
+
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let res = model_f(
+            &graph,
+            &ctx,
+            proportional_balancer_f(entities),
+            ModelFlags::empty(),
+        );
+        assert_eq!(res, ProofResult::Unsat);
+    }
+
+    /// The blocked model of a reversed graph must match the one of the physically reversed blueprint
+    #[test]
+    fn blocked_reversal() {
+        let compile = |file: &str| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            graph
+        };
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        for fixture in ["3-2", "3-2-equal-drain"] {
+            let auto_reversed = compile(&format!("tests/{}", fixture)).reverse();
+            let manually_reversed = compile(&format!("tests/{}-reversed", fixture));
+            assert_eq!(auto_reversed.node_count(), manually_reversed.node_count());
+            assert_eq!(auto_reversed.edge_count(), manually_reversed.edge_count());
+
+            let prove = |graph, f: fn(ProofPrimitives<'_>) -> Bool<'_>| {
+                model_f(graph, &ctx, f, ModelFlags::Blocked)
+            };
+            assert_eq!(
+                prove(&auto_reversed, universal_balancer),
+                prove(&manually_reversed, universal_balancer)
+            );
+            assert_eq!(
+                prove(&auto_reversed, universal_equal_drain_f),
+                prove(&manually_reversed, universal_equal_drain_f)
+            );
+        }
+
+        let graph = compile("tests/simple_belt").reverse();
+        let res = model_f(&graph, &ctx, universal_equal_drain_f, ModelFlags::Blocked);
+        assert_eq!(res, ProofResult::Sat);
+    }
+
+    #[test]
+    fn adversarial_splitters() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let prove = |file: &str, flags: ModelFlags| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            model_f(&graph, &ctx, belt_balancer_f, flags)
+        };
+        // the splitters of a balancer have to split evenly, otherwise everything may end up on one side
+        let file = "tests/4-4";
+        assert_eq!(prove(file, ModelFlags::empty()), ProofResult::Sat);
+        assert_eq!(prove(file, ModelFlags::Adversarial), ProofResult::Unsat);
+        // a plain belt does not depend on any splitter
+        let file = "tests/simple_belt";
+        assert_eq!(prove(file, ModelFlags::empty()), ProofResult::Sat);
+        assert_eq!(prove(file, ModelFlags::Adversarial), ProofResult::Sat);
+    }
+
+    #[test]
+    fn ignore_priority() {
+        let entities = file_to_entities("tests/prio_splitter").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+
+        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsat);
+        let res = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::IgnorePriority);
+        assert_eq!(res, ProofResult::Sat);
+    }
+
+    #[test]
+    fn tu_classification() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let classify = |file: &str, exclude_list: &[EntityId]| {
+            let entities = file_to_entities(file).unwrap();
+            let graph = Compiler::new(entities.clone()).create_graph();
+            classify_throughput_unlimited(&graph, &ctx, entities, exclude_list)
+        };
+        // the priority keeps the splitter from sending half of the input around the loop
+        assert_eq!(
+            classify("tests/prio_loop", &[1]),
+            TuClassification::WithPriorities
+        );
+        assert_eq!(
+            classify("tests/6-3-tu", &[24, 36, 44]),
+            TuClassification::WithoutPriorities
+        );
+        assert_eq!(
+            classify("tests/prio_splitter", &[]),
+            TuClassification::NotTu
+        );
+    }
+
+    /// The solver specialized for `QF_LRA` reaches the same verdicts as the default solver.
+    #[test]
+    fn balancer_for_logic() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        for file in [
+            "3-2-broken",
+            "3-3",
+            "4-4",
+            "4-4-broken",
+            "6-3-tu",
+            "prio_splitter",
+        ] {
+            let entities = file_to_entities(&format!("tests/{}", file)).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let default = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+            let logic = model_f_for_logic(
+                &graph,
+                &ctx,
+                belt_balancer_f,
+                ModelFlags::empty(),
+                Some("QF_LRA"),
+            );
+            assert!(default.is_conclusive(), "{}", file);
+            assert_eq!(default, logic, "{}", file);
+        }
+    }
+
+    /// Verdicts of the fixtures recorded while the inputs were integers in the model.
+    /// Quantifying over real input rates must not change any of them.
+    #[test]
+    fn real_input_verdicts() {
+        use ProofResult::{Sat, Unsat};
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        // belt balancer, equal drain, throughput unlimited and universal balancer
+        for (file, expected) in [
+            ("2-2", [Sat, Sat, Sat, Sat]),
+            ("3-2", [Unsat, Sat, Unsat, Unsat]),
+            ("3-2-broken", [Unsat, Sat, Unsat, Unsat]),
+            ("3-2-equal-drain", [Sat, Sat, Unsat, Unsat]),
+            ("3-3", [Sat, Sat, Unsat, Unsat]),
+            ("3-3-broken", [Unsat, Unsat, Unsat, Unsat]),
+            ("4-4", [Sat, Sat, Sat, Sat]),
+            ("4-4-broken", [Unsat, Unsat, Sat, Unsat]),
+            ("4-4-ntu", [Sat, Sat, Unsat, Unsat]),
+            ("4-4-tu", [Sat, Sat, Sat, Unsat]),
+            ("4-4-tu-express-io", [Sat, Sat, Unsat, Unsat]),
+            ("6-3-ntu", [Unsat, Unsat, Unsat, Unsat]),
+            ("prio_splitter", [Unsat, Unsat, Unsat, Unsat]),
+            ("ratio_router", [Unsat, Unsat, Sat, Unsat]),
+            ("weighted_drain", [Unsat, Sat, Unsat, Unsat]),
+            ("mixed_output_tiers", [Unsat, Unsat, Sat, Unsat]),
+        ] {
+            let entities = file_to_entities(&format!("tests/{}", file)).unwrap();
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            let verdicts = [
+                model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty()),
+                model_f(&graph, &ctx, equal_drain_f, ModelFlags::empty()),
+                model_f(
+                    &graph,
+                    &ctx,
+                    throughput_unlimited(entities),
+                    ModelFlags::Relaxed,
+                ),
+                model_f(&graph, &ctx, universal_balancer, ModelFlags::Blocked),
+            ];
+            assert_eq!(verdicts, expected, "{}", file);
+        }
+    }
+
+    /// Proves the belt balancer property on both the lossless- and the aggressively-simplified
+    /// graph of `entities` and asserts that the simplification did not change the result.
+    fn assert_simplify_preserves(entities: Vec<FBEntity<i32>>) {
+        let results = [CoalesceStrength::Lossless, CoalesceStrength::Aggressive].map(|strength| {
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(&[], strength);
+            let cfg = Config::new();
+            let ctx = Context::new(&cfg);
+            model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty())
+        });
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn simplify_preserves_balancer() {
+        // every blueprint directly in `tests/`, the files that are not blueprints fail to import
+        let mut fixtures = std::fs::read_dir("tests")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        fixtures.sort();
+        for fixture in fixtures {
+            let Ok(entities) = file_to_entities(fixture.to_str().unwrap()) else {
+                continue;
+            };
+            assert_simplify_preserves(entities);
+        }
+    }
 }
@@ -1,27 +1,22 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
 
 use z3::{ast::Bool, Config, Context, SatResult};
 
-use crate::ir::FlowGraph;
+use crate::{
+    entities::EntityId,
+    ir::{FlowGraph, Reversable},
+};
 
-use super::{model_f, ModelFlags, ProofPrimitives};
-
-#[derive(Debug, Clone, Copy)]
-pub enum ProofResult {
-    Unknown,
-    Sat,
-    Unsat,
-}
-
-impl ProofResult {
-    pub fn not(&self) -> Self {
-        match self {
-            ProofResult::Sat => ProofResult::Unsat,
-            ProofResult::Unsat => ProofResult::Sat,
-            ProofResult::Unknown => ProofResult::Unknown,
-        }
-    }
-}
+use super::{
+    counter_example_f, model_f_for_logic,
+    model_graph::{encode_graph, to_primitives},
+    CounterExample, IncrementalModel, ModelFlags, ProofPrimitives, ProofResult, SolverBackend,
+    Z3Backend,
+};
 
 impl From<SatResult> for ProofResult {
     fn from(value: SatResult) -> Self {
@@ -33,48 +28,228 @@ impl From<SatResult> for ProofResult {
     }
 }
 
-impl Display for ProofResult {
+/// Error of a [`BlueprintProofEntity`] whose z3 solver could not be initialized,
+/// i.e. the native library panicked while creating the context or failed to check an empty formula.
+///
+/// A native abort, or a z3 shared library that cannot be loaded, still terminates the process
+/// as it cannot be caught: link z3 statically with the `build_z3` feature to rule out the latter.
+///
+/// The proofs of such an entity return [`ProofResult::Unsupported`],
+/// the structural analyses of the graph, e.g. [`FlowGraphAnalysis`](crate::ir::FlowGraphAnalysis), don't need a solver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolverUnavailable {
+    /// Why the solver could not be initialized
+    pub reason: String,
+}
+
+impl Display for SolverUnavailable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            Self::Sat => "Yes",
-            Self::Unsat => "No",
-            Self::Unknown => "Unknown",
-        };
-        write!(f, "{}", s)
+        write!(f, "z3 solver unavailable: {}", self.reason)
+    }
+}
+
+impl std::error::Error for SolverUnavailable {}
+
+#[cfg(test)]
+thread_local! {
+    /// Makes the creation of the solvers on this thread fail, to test the handling of an unavailable solver
+    static SIMULATE_UNAVAILABLE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Creates a z3 configuration, set up by `configure`, and a context, then checks that its solver works.
+///
+/// A panic of the native library in any of these steps is caught, an abort is not, see [`SolverUnavailable`].
+fn create_context(
+    configure: impl FnOnce(&mut Config),
+) -> Result<(Config, Context), SolverUnavailable> {
+    #[cfg(test)]
+    if SIMULATE_UNAVAILABLE.with(|s| s.get()) {
+        return Err(SolverUnavailable {
+            reason: "simulated failure".to_owned(),
+        });
+    }
+    let created = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut cfg = Config::new();
+        configure(&mut cfg);
+        let ctx = Context::new(&cfg);
+        /* a short timeout may already cut off the check of the empty formula */
+        let res = Z3Backend::new(&ctx, None).check();
+        let works = matches!(res, ProofResult::Sat | ProofResult::Timeout);
+        (cfg, ctx, works)
+    }));
+    match created {
+        Ok((cfg, ctx, true)) => Ok((cfg, ctx)),
+        Ok((_, _, false)) => Err(SolverUnavailable {
+            reason: "the solver failed to check an empty formula".to_owned(),
+        }),
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "the native library panicked".to_owned());
+            Err(SolverUnavailable { reason })
+        }
     }
 }
 
 pub struct BlueprintProofEntity {
-    _cfg: Config,
-    ctx: Context,
+    _cfg: Option<Config>,
+    ctx: Result<Context, SolverUnavailable>,
     graph: FlowGraph,
+    logic: Option<String>,
     result: Option<ProofResult>,
+    flags: Option<ModelFlags>,
 }
 
 impl BlueprintProofEntity {
-    pub fn new(graph: FlowGraph) -> Self {
-        let _cfg = Config::new();
-        let ctx = Context::new(&_cfg);
+    /// Creates a new proof entity for `graph`.
+    ///
+    /// `logic` optionally selects the SMT-LIB logic the solver is specialized for,
+    /// see [`model_f_for_logic`] for which logic suits which proof.
+    pub fn new(graph: FlowGraph, logic: Option<&str>) -> Self {
+        Self::with_config(graph, logic, |_| {})
+    }
+
+    /// Creates a new proof entity for `graph` whose proofs return [`ProofResult::Timeout`]
+    /// if the solver does not finish within `timeout`.
+    pub fn with_timeout(graph: FlowGraph, logic: Option<&str>, timeout: Duration) -> Self {
+        let timeout = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        Self::with_config(graph, logic, |cfg| cfg.set_timeout_msec(timeout))
+    }
+
+    fn with_config(
+        graph: FlowGraph,
+        logic: Option<&str>,
+        configure: impl FnOnce(&mut Config),
+    ) -> Self {
+        let (_cfg, ctx) = match create_context(configure) {
+            Ok((cfg, ctx)) => (Some(cfg), Ok(ctx)),
+            Err(e) => (None, Err(e)),
+        };
         Self {
             _cfg,
             ctx,
             graph,
+            logic: logic.map(str::to_owned),
             result: None,
+            flags: None,
         }
     }
 
+    /// Returns why the solver could not be initialized, if it is unavailable.
+    pub fn solver_error(&self) -> Option<&SolverUnavailable> {
+        self.ctx.as_ref().err()
+    }
+
+    /// Proves the property `f`, returning [`ProofResult::Unsupported`] if the solver is unavailable.
     pub fn model<'a, F>(&'a mut self, f: F, flags: ModelFlags) -> ProofResult
     where
         F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
     {
-        let res = model_f(&self.graph, &self.ctx, f, flags).into();
+        let res = match &self.ctx {
+            Ok(ctx) => model_f_for_logic(&self.graph, ctx, f, flags, self.logic.as_deref()),
+            Err(_) => ProofResult::Unsupported,
+        };
         self.result = Some(res);
+        self.flags = Some(flags);
         res
     }
 
+    /// Returns a counter-example to the property `f`, see [`counter_example_f`].
+    pub fn counter_example<'a, F>(&'a self, f: F, flags: ModelFlags) -> Option<CounterExample>
+    where
+        F: FnOnce(ProofPrimitives<'a>) -> Bool<'a>,
+    {
+        let ctx = self.ctx.as_ref().ok()?;
+        counter_example_f(&self.graph, ctx, f, flags, self.logic.as_deref())
+    }
+
+    /// Proves the property `f` for each selection of inputs and outputs, given by the inputs and outputs that are removed.
+    ///
+    /// The graph is encoded only once using an [`IncrementalModel`], so it should contain all the inputs and outputs.
+    pub fn model_selections<'a, F>(
+        &'a mut self,
+        f: F,
+        flags: ModelFlags,
+        selections: &[Vec<EntityId>],
+    ) -> Vec<ProofResult>
+    where
+        F: Fn(ProofPrimitives<'a>) -> Bool<'a>,
+    {
+        let results = match &self.ctx {
+            Ok(ctx) => {
+                let model = IncrementalModel::new(&self.graph, ctx, flags);
+                selections
+                    .iter()
+                    .map(|removed| model.check(&f, removed))
+                    .collect::<Vec<_>>()
+            }
+            Err(_) => vec![ProofResult::Unsupported; selections.len()],
+        };
+        self.result = results.last().copied();
+        self.flags = Some(flags);
+        results
+    }
+
     pub fn result(&self) -> Option<ProofResult> {
         self.result
     }
+
+    /// Returns the [`ModelFlags`] the last result was proven with, see [`ModelFlags::notes`].
+    pub fn flags(&self) -> Option<ModelFlags> {
+        self.flags
+    }
+}
+
+/// Owner of the z3 [`Context`] used to build [`ProofPrimitives`] in a given orientation.
+///
+/// Proofs about the inputs of a blueprint, like [`equal_drain_f`](super::equal_drain_f),
+/// are done on the reversed graph. Building the primitives through [`ProofContext::reversed`]
+/// makes the orientation explicit and keeps the reversed graph alive as long as the primitives.
+pub struct ProofContext {
+    _cfg: Config,
+    ctx: Context,
+    reversed: Option<FlowGraph>,
+}
+
+impl Default for ProofContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProofContext {
+    pub fn new() -> Self {
+        let _cfg = Config::new();
+        let ctx = Context::new(&_cfg);
+        Self {
+            _cfg,
+            ctx,
+            reversed: None,
+        }
+    }
+
+    /// Returns the z3 context
+    pub fn ctx(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Encodes `graph` as is and returns the resulting primitives.
+    pub fn forward<'a>(&'a self, graph: &'a FlowGraph, flags: ModelFlags) -> ProofPrimitives<'a> {
+        let backend = Z3Backend::new(&self.ctx, None);
+        let mut helper = encode_graph(graph, &backend, flags);
+        to_primitives(graph, &self.ctx, &mut helper)
+    }
+
+    /// Reverses `graph`, encodes the reversed graph and returns the resulting primitives.
+    ///
+    /// The inputs of the primitives are the outputs of `graph` and vice versa.
+    pub fn reversed<'a>(&'a mut self, graph: &FlowGraph, flags: ModelFlags) -> ProofPrimitives<'a> {
+        self.reversed = Some(Reversable::reverse(graph));
+        let this: &'a Self = self;
+        this.forward(this.reversed.as_ref().unwrap(), flags)
+    }
 }
 
 // TODO: decide what to do with these tests
@@ -118,3 +293,124 @@ impl BlueprintProofEntity {
 //         assert!(matches!(is_balancer, SatResult::Unsat));
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        backends::belt_balancer_f,
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, FlowGraphAnalysis, FlowGraphFun},
+    };
+    use z3::ast::{Ast, Int};
+
+    use super::*;
+
+    #[test]
+    fn inconclusive_results() {
+        let inconclusive = [
+            (ProofResult::Unknown, "Unknown"),
+            (
+                ProofResult::Invalid,
+                "Not proven, a precondition does not hold",
+            ),
+            (ProofResult::Timeout, "Timed out"),
+            (ProofResult::Unsupported, "Not supported"),
+        ];
+        for (res, display) in inconclusive {
+            assert_eq!(res.to_string(), display);
+            assert_eq!(res.not(), res);
+            assert!(!res.is_conclusive());
+        }
+        assert_eq!(ProofResult::Sat.not(), ProofResult::Unsat);
+        assert_eq!(ProofResult::Unsat.not(), ProofResult::Sat);
+        assert!(ProofResult::Sat.is_conclusive() && ProofResult::Unsat.is_conclusive());
+    }
+
+    #[test]
+    fn timeout() {
+        let entities = file_to_entities("tests/6-3-tu").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let mut proof = BlueprintProofEntity::with_timeout(graph, None, Duration::from_millis(100));
+        // no positive integers solve x^3 + y^3 = z^3 and z3 can't prove it, so it never finishes
+        let res = proof.model(
+            |p| {
+                let var = |name| Int::new_const(p.ctx, name);
+                let (x, y, z) = (var("x"), var("y"), var("z"));
+                let one = Int::from_i64(p.ctx, 1);
+                let cube = |v| Int::mul(p.ctx, &[v, v, v]);
+                let fermat = Int::add(p.ctx, &[&cube(&x), &cube(&y)])._eq(&cube(&z));
+                Bool::and(
+                    p.ctx,
+                    &[
+                        &p.model_constraint,
+                        &x.ge(&one),
+                        &y.ge(&one),
+                        &z.ge(&one),
+                        &fermat,
+                    ],
+                )
+            },
+            ModelFlags::empty(),
+        );
+        assert_eq!(res, ProofResult::Timeout);
+    }
+
+    #[test]
+    fn reversed_primitives() {
+        let entities = file_to_entities("tests/3-2").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[4, 5, 6], CoalesceStrength::Aggressive);
+
+        let ids = |p: &ProofPrimitives<'_>, inputs: bool| {
+            let nodes = if inputs {
+                p.input_map.keys().copied().collect::<Vec<_>>()
+            } else {
+                p.output_map.keys().copied().collect()
+            };
+            nodes
+                .into_iter()
+                .map(|idx| p.graph[idx].get_id())
+                .collect::<BTreeSet<_>>()
+        };
+
+        let forward_ctx = ProofContext::new();
+        let forward = forward_ctx.forward(&graph, ModelFlags::empty());
+        let (inputs, outputs) = (ids(&forward, true), ids(&forward, false));
+        assert!(!inputs.is_empty() && !outputs.is_empty());
+        assert_ne!(inputs, outputs);
+
+        let mut reversed_ctx = ProofContext::new();
+        let reversed = reversed_ctx.reversed(&graph, ModelFlags::empty());
+        assert_eq!(ids(&reversed, true), outputs);
+        assert_eq!(ids(&reversed, false), inputs);
+    }
+
+    #[test]
+    fn solver_unavailable() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+
+        assert_eq!(
+            BlueprintProofEntity::new(graph.clone(), None).solver_error(),
+            None
+        );
+
+        SIMULATE_UNAVAILABLE.with(|s| s.set(true));
+        let mut proof = BlueprintProofEntity::new(graph.clone(), Some("QF_LRA"));
+        SIMULATE_UNAVAILABLE.with(|s| s.set(false));
+        assert!(proof.solver_error().is_some());
+        let res = proof.model(belt_balancer_f, ModelFlags::empty());
+        assert_eq!(res, ProofResult::Unsupported);
+        assert_eq!(
+            proof.counter_example(belt_balancer_f, ModelFlags::empty()),
+            None
+        );
+        // the structural analyses still work
+        assert!(graph.is_fully_connected_io());
+    }
+}
@@ -0,0 +1,55 @@
+//! Outcome of the proofs, shared by the z3 and the linear programming back-ends
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Outcome of a proof
+///
+/// Only [`ProofResult::Sat`] and [`ProofResult::Unsat`] tell whether the property holds,
+/// the other variants tell why the proof is inconclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofResult {
+    /// The solver could not decide the property
+    Unknown,
+    /// The property holds
+    Sat,
+    /// The property does not hold
+    Unsat,
+    /// The property was not proven, as a precondition of its proof does not hold,
+    /// e.g. the throughput unlimited proof of a design that is not a balancer
+    Invalid,
+    /// The solver did not finish within the timeout, see [`BlueprintProofEntity::with_timeout`](super::BlueprintProofEntity::with_timeout)
+    Timeout,
+    /// The proof is not supported, e.g. because the z3 solver is unavailable, see `SolverUnavailable`
+    Unsupported,
+}
+
+impl ProofResult {
+    /// Negates a conclusive result, the inconclusive results stay the same.
+    pub fn not(&self) -> Self {
+        match self {
+            ProofResult::Sat => ProofResult::Unsat,
+            ProofResult::Unsat => ProofResult::Sat,
+            other => *other,
+        }
+    }
+
+    /// Returns true if the result tells whether the property holds, i.e. it is [`ProofResult::Sat`] or [`ProofResult::Unsat`].
+    pub fn is_conclusive(&self) -> bool {
+        matches!(self, ProofResult::Sat | ProofResult::Unsat)
+    }
+}
+
+impl Display for ProofResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Sat => "Yes",
+            Self::Unsat => "No",
+            Self::Unknown => "Unknown",
+            Self::Invalid => "Not proven, a precondition does not hold",
+            Self::Timeout => "Timed out",
+            Self::Unsupported => "Not supported",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,400 @@
+//! Abstraction over the solver used to prove properties of a [`FlowGraph`](crate::ir::FlowGraph)
+
+use fraction::GenericFraction;
+use z3::{
+    ast::{Ast, Bool, Real},
+    Context, SatResult, Solver,
+};
+
+use super::{model_entities::Z3Fraction, ProofResult};
+
+/// Solver able to decide linear arithmetic constraints over real and boolean variables.
+///
+/// The variables and constraints are built and checked through this trait,
+/// so the encoding of the graph in `model_entities.rs` and `model_graph.rs` works with any solver implementing it.
+/// The properties to prove are still z3 formulas, as some of them quantify over the flows.
+pub trait SolverBackend<'ctx> {
+    /// Boolean term of the solver
+    type Bool: Clone;
+    /// Real term of the solver
+    type Real: Clone;
+
+    /// Creates a new boolean variable
+    fn bool_var(&self, name: &str) -> Self::Bool;
+    /// Creates a new real variable
+    fn real_var(&self, name: &str) -> Self::Real;
+    /// Creates the constant of the rational `value`, which must be exact whatever the size of its numerator and denominator
+    fn real_const(&self, value: GenericFraction<u128>) -> Self::Real;
+
+    /// Returns the sum of the `terms`, zero if there are none
+    fn sum(&self, terms: &[&Self::Real]) -> Self::Real;
+    /// Returns `a = b`
+    fn eq(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    /// Returns `a <= b`
+    fn le(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    /// Returns `a >= b`
+    fn ge(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    /// Returns the conjunction of the `terms`, true if there are none
+    fn and(&self, terms: &[&Self::Bool]) -> Self::Bool;
+    /// Returns the disjunction of the `terms`, false if there are none
+    fn or(&self, terms: &[&Self::Bool]) -> Self::Bool;
+    /// Returns `not a`
+    fn not(&self, a: &Self::Bool) -> Self::Bool;
+    /// Returns `a => b`
+    fn implies(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool;
+    /// Returns `a <=> b`
+    fn iff(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool;
+    /// Returns `if cond then a else b`
+    fn ite(&self, cond: &Self::Bool, a: &Self::Bool, b: &Self::Bool) -> Self::Bool;
+
+    /// Asserts the constraint in the solver
+    fn assert(&self, constraint: &Self::Bool);
+    /// Checks the satisfiability of the asserted constraints
+    fn check(&self) -> ProofResult;
+    /// Checks the satisfiability of the asserted constraints together with the `assumptions`
+    fn check_assumptions(&self, assumptions: &[Self::Bool]) -> ProofResult;
+    /// Creates a backtracking point
+    fn push(&self);
+    /// Removes all the constraints asserted since the last backtracking point
+    fn pop(&self);
+}
+
+/// Default [`SolverBackend`] using z3
+pub struct Z3Backend<'ctx> {
+    ctx: &'ctx Context,
+    solver: Solver<'ctx>,
+}
+
+impl<'ctx> Z3Backend<'ctx> {
+    /// Creates a backend with a solver specialized for the given SMT-LIB `logic`.
+    ///
+    /// Falls back to the default solver if `logic` is `None` or not supported by z3.
+    pub fn new(ctx: &'ctx Context, logic: Option<&str>) -> Self {
+        let solver = logic
+            .and_then(|l| Solver::new_for_logic(ctx, l))
+            .unwrap_or_else(|| Solver::new(ctx));
+        Self { ctx, solver }
+    }
+
+    /// Returns the z3 context of the backend
+    pub fn ctx(&self) -> &'ctx Context {
+        self.ctx
+    }
+
+    /// Returns the underlying z3 solver
+    pub fn solver(&self) -> &Solver<'ctx> {
+        &self.solver
+    }
+
+    /// Converts the result of a check, telling a timeout apart from other reasons of z3 giving up
+    fn result(&self, res: SatResult) -> ProofResult {
+        let reason = self.solver.get_reason_unknown();
+        match (res, reason.as_deref()) {
+            (SatResult::Unknown, Some("timeout" | "canceled")) => ProofResult::Timeout,
+            (res, _) => res.into(),
+        }
+    }
+}
+
+impl<'ctx> SolverBackend<'ctx> for Z3Backend<'ctx> {
+    type Bool = Bool<'ctx>;
+    type Real = Real<'ctx>;
+
+    fn bool_var(&self, name: &str) -> Self::Bool {
+        Bool::new_const(self.ctx, name)
+    }
+
+    fn real_var(&self, name: &str) -> Self::Real {
+        Real::new_const(self.ctx, name)
+    }
+
+    fn real_const(&self, value: GenericFraction<u128>) -> Self::Real {
+        value.to_z3(self.ctx)
+    }
+
+    fn sum(&self, terms: &[&Self::Real]) -> Self::Real {
+        if terms.is_empty() {
+            return self.real_const(GenericFraction::from(0));
+        }
+        Real::add(self.ctx, terms)
+    }
+
+    fn eq(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a._eq(b)
+    }
+
+    fn le(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a.le(b)
+    }
+
+    fn ge(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a.ge(b)
+    }
+
+    fn and(&self, terms: &[&Self::Bool]) -> Self::Bool {
+        Bool::and(self.ctx, terms)
+    }
+
+    fn or(&self, terms: &[&Self::Bool]) -> Self::Bool {
+        Bool::or(self.ctx, terms)
+    }
+
+    fn not(&self, a: &Self::Bool) -> Self::Bool {
+        a.not()
+    }
+
+    fn implies(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool {
+        a.implies(b)
+    }
+
+    fn iff(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool {
+        a.iff(b)
+    }
+
+    fn ite(&self, cond: &Self::Bool, a: &Self::Bool, b: &Self::Bool) -> Self::Bool {
+        cond.ite(a, b)
+    }
+
+    fn assert(&self, constraint: &Self::Bool) {
+        self.solver.assert(constraint);
+    }
+
+    fn check(&self) -> ProofResult {
+        self.result(self.solver.check())
+    }
+
+    fn check_assumptions(&self, assumptions: &[Self::Bool]) -> ProofResult {
+        self.result(self.solver.check_assumptions(assumptions))
+    }
+
+    fn push(&self) {
+        self.solver.push();
+    }
+
+    fn pop(&self) {
+        self.solver.pop(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::{BTreeSet, HashSet},
+    };
+
+    use z3::Config;
+
+    use crate::{
+        backends::{belt_balancer_f, model_f, model_graph::encode_graph, ModelFlags},
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, FlowGraphFun},
+    };
+
+    use super::*;
+
+    /// Backend writing the constraints as an SMT-LIB script, which is only handed to z3 when checking it.
+    ///
+    /// Its terms are plain strings, so the encoding can not rely on any z3 type.
+    struct SmtLibBackend<'ctx> {
+        ctx: &'ctx Context,
+        declarations: RefCell<BTreeSet<String>>,
+        assertions: RefCell<Vec<String>>,
+    }
+
+    impl<'ctx> SmtLibBackend<'ctx> {
+        fn new(ctx: &'ctx Context) -> Self {
+            Self {
+                ctx,
+                declarations: RefCell::default(),
+                assertions: RefCell::default(),
+            }
+        }
+
+        fn declare(&self, name: &str, sort: &str) -> String {
+            self.declarations
+                .borrow_mut()
+                .insert(format!("(declare-const {} {})", name, sort));
+            name.to_owned()
+        }
+
+        fn apply(op: &str, terms: &[&String], neutral: &str) -> String {
+            match terms {
+                [] => neutral.to_owned(),
+                [term] => term.to_string(),
+                terms => {
+                    let terms = terms.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+                    format!("({} {})", op, terms.join(" "))
+                }
+            }
+        }
+    }
+
+    impl<'ctx> SolverBackend<'ctx> for SmtLibBackend<'ctx> {
+        type Bool = String;
+        type Real = String;
+
+        fn bool_var(&self, name: &str) -> String {
+            self.declare(name, "Bool")
+        }
+
+        fn real_var(&self, name: &str) -> String {
+            self.declare(name, "Real")
+        }
+
+        fn real_const(&self, value: GenericFraction<u128>) -> String {
+            let fraction = format!(
+                "(/ {}.0 {}.0)",
+                value.numer().unwrap(),
+                value.denom().unwrap()
+            );
+            if value.is_sign_negative() {
+                format!("(- {})", fraction)
+            } else {
+                fraction
+            }
+        }
+
+        fn sum(&self, terms: &[&String]) -> String {
+            Self::apply("+", terms, "0.0")
+        }
+
+        fn eq(&self, a: &String, b: &String) -> String {
+            format!("(= {} {})", a, b)
+        }
+
+        fn le(&self, a: &String, b: &String) -> String {
+            format!("(<= {} {})", a, b)
+        }
+
+        fn ge(&self, a: &String, b: &String) -> String {
+            format!("(>= {} {})", a, b)
+        }
+
+        fn and(&self, terms: &[&String]) -> String {
+            Self::apply("and", terms, "true")
+        }
+
+        fn or(&self, terms: &[&String]) -> String {
+            Self::apply("or", terms, "false")
+        }
+
+        fn not(&self, a: &String) -> String {
+            format!("(not {})", a)
+        }
+
+        fn implies(&self, a: &String, b: &String) -> String {
+            format!("(=> {} {})", a, b)
+        }
+
+        fn iff(&self, a: &String, b: &String) -> String {
+            format!("(= {} {})", a, b)
+        }
+
+        fn ite(&self, cond: &String, a: &String, b: &String) -> String {
+            format!("(ite {} {} {})", cond, a, b)
+        }
+
+        fn assert(&self, constraint: &String) {
+            self.assertions
+                .borrow_mut()
+                .push(format!("(assert {})", constraint));
+        }
+
+        fn check(&self) -> ProofResult {
+            self.check_assumptions(&[])
+        }
+
+        fn check_assumptions(&self, assumptions: &[String]) -> ProofResult {
+            let solver = Solver::new(self.ctx);
+            let declarations = self.declarations.borrow();
+            let assertions = self.assertions.borrow();
+            let script = declarations
+                .iter()
+                .chain(assertions.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n");
+            solver.from_string(script);
+            let assumptions = assumptions
+                .iter()
+                .map(|a| Bool::new_const(self.ctx, a.as_str()))
+                .collect::<Vec<_>>();
+            solver.check_assumptions(&assumptions).into()
+        }
+
+        fn push(&self) {
+            self.assertions.borrow_mut().push("(push 1)".to_owned());
+        }
+
+        fn pop(&self) {
+            let mut assertions = self.assertions.borrow_mut();
+            let push = assertions.iter().rposition(|a| a == "(push 1)").unwrap();
+            assertions.truncate(push);
+        }
+    }
+
+    /// The graph encoded through another backend gives the same verdicts as the z3 model
+    #[test]
+    fn encode_with_other_backend() {
+        for fixture in ["3-2", "3-2-broken", "4-4", "4-4-broken", "prio_splitter"] {
+            let entities = file_to_entities(&format!("tests/{}", fixture)).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+
+            let cfg = Config::new();
+            let ctx = Context::new(&cfg);
+            let backend = SmtLibBackend::new(&ctx);
+            let helper = encode_graph(&graph, &backend, ModelFlags::empty());
+            for constraint in &helper.others {
+                backend.assert(constraint);
+            }
+            // the phantoms of an entity share its output variable
+            let outputs = helper.output_map.values().collect::<HashSet<_>>();
+            let outputs = outputs.into_iter().collect::<Vec<_>>();
+            let equal = outputs
+                .windows(2)
+                .map(|w| backend.eq(w[0], w[1]))
+                .collect::<Vec<_>>();
+            backend.assert(&backend.not(&backend.and(&equal.iter().collect::<Vec<_>>())));
+
+            let expected = model_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty());
+            assert!(expected.is_conclusive());
+            assert_eq!(backend.check().not(), expected, "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn z3_backend_push_pop() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let backend = Z3Backend::new(&ctx, Some("QF_LRA"));
+        let x = backend.real_var("x");
+        backend.assert(&x.le(&backend.real_const(GenericFraction::new(1u128, 2u128))));
+        assert_eq!(backend.check(), ProofResult::Sat);
+
+        backend.push();
+        backend.assert(&x.gt(&backend.real_const(GenericFraction::new(1u128, 2u128))));
+        assert_eq!(backend.check(), ProofResult::Unsat);
+        backend.pop();
+        assert_eq!(backend.check(), ProofResult::Sat);
+
+        let b = backend.bool_var("b");
+        backend.assert(&b.implies(&x._eq(&backend.real_const(GenericFraction::from(1)))));
+        assert_eq!(backend.check_assumptions(&[b]), ProofResult::Unsat);
+    }
+
+    #[test]
+    fn z3_backend_exact_constants() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let backend = Z3Backend::new(&ctx, Some("QF_LRA"));
+        // neither the numerator nor the value fit into an `i32`
+        let large = GenericFraction::new(u128::from(u32::MAX) * 3, 2u128);
+        let x = backend.real_var("x");
+        backend.assert(&x._eq(&backend.real_const(large)));
+        backend.assert(&x.le(&backend.real_const(GenericFraction::from(1u128 << 31))));
+        assert_eq!(backend.check(), ProofResult::Unsat);
+    }
+}
@@ -1,8 +1,12 @@
 //! Definitions of entities that are part of a Factorio blueprint
 //!
-use crate::utils::{Direction, Position, Rotation};
+use crate::utils::{bounding_box, Direction, Position, Rotation};
+use fraction::GenericFraction;
 use serde::Deserialize;
-use std::ops::{Add, Sub};
+use std::{
+    collections::HashSet,
+    ops::{Add, Sub},
+};
 
 pub type EntityId = i32;
 
@@ -13,6 +17,58 @@ pub struct FBBaseEntity<T> {
     pub position: Position<T>,
     pub direction: Direction,
     pub throughput: f64,
+    /// Quality of the entity in Factorio 2.0, `None` if the blueprint does not specify it
+    pub quality: Option<Quality>,
+}
+
+impl<T> FBBaseEntity<T> {
+    /// Creates a base entity. The `throughput` is in items/s, e.g. 15 for a yellow belt.
+    pub fn new(id: EntityId, position: Position<T>, direction: Direction, throughput: f64) -> Self {
+        Self {
+            id,
+            position,
+            direction,
+            throughput,
+            quality: None,
+        }
+    }
+}
+
+/// Quality of an entity, introduced in Factorio 2.0
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    Normal,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Quality {
+    /// Returns the name used for the quality in blueprints
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Uncommon => "uncommon",
+            Self::Rare => "rare",
+            Self::Epic => "epic",
+            Self::Legendary => "legendary",
+        }
+    }
+
+    /// Returns the factor by which the quality increases the crafting speed of assembling machines.
+    ///
+    /// Quality does not change the speed of belts, underground belts, splitters or inserters.
+    pub fn crafting_speed_multiplier(&self) -> f64 {
+        match self {
+            Self::Normal => 1.0,
+            Self::Uncommon => 1.3,
+            Self::Rare => 1.6,
+            Self::Epic => 1.9,
+            Self::Legendary => 2.5,
+        }
+    }
 }
 
 impl<T> FBBaseEntity<T>
@@ -55,6 +111,173 @@ impl<T> FBEntity<T> {
             Self::AssemblerPhantom(b) => &b.base,
         }
     }
+
+    /// Get a mutable reference to the base entity of a `FBEntity<T>`.
+    pub fn get_base_mut(&mut self) -> &mut FBBaseEntity<T> {
+        match self {
+            Self::Belt(b) => &mut b.base,
+            Self::Underground(b) => &mut b.base,
+            Self::Splitter(b) => &mut b.base,
+            Self::SplitterPhantom(b) => &mut b.base,
+            Self::Inserter(b) => &mut b.base,
+            Self::LongInserter(b) => &mut b.base,
+            Self::Assembler(b) => &mut b.base,
+            Self::AssemblerPhantom(b) => &mut b.base,
+        }
+    }
+}
+
+/// Adds the phantoms of all splitters and assemblers to `entities`.
+///
+/// The phantoms are required by the [`Compiler`](crate::frontend::Compiler) to know which cells
+/// are covered by entities bigger than 1x1. They are added automatically when importing a
+/// blueprint string, but have to be added when building the entities by hand.
+pub fn add_phantoms(entities: &mut Vec<FBEntity<i32>>) {
+    let phantoms = entities
+        .iter()
+        .flat_map(|e| match e {
+            FBEntity::Splitter(s) => vec![FBEntity::SplitterPhantom(s.get_phantom())],
+            FBEntity::Assembler(a) => a
+                .get_phantoms()
+                .into_iter()
+                .map(FBEntity::AssemblerPhantom)
+                .collect(),
+            _ => vec![],
+        })
+        .collect::<Vec<_>>();
+    entities.extend(phantoms);
+}
+
+/// Merges the entities of two blueprints, placing `b` shifted by `offset` next to `a`.
+///
+/// The ids of `b` are renumbered to follow the highest id of `a`, so that no two entities share an id.
+/// Phantoms keep sharing the id of the entity they are part of.
+pub fn merge_entities(
+    a: Vec<FBEntity<i32>>,
+    b: Vec<FBEntity<i32>>,
+    offset: Position<i32>,
+) -> Vec<FBEntity<i32>> {
+    let id_offset = a.iter().map(|e| e.get_base().id).max().unwrap_or(0);
+    let shifted = b.into_iter().map(|mut e| {
+        let base = e.get_base_mut();
+        base.id += id_offset;
+        base.position = base.position + offset;
+        e
+    });
+    a.into_iter().chain(shifted).collect()
+}
+
+/// Returns the entities lying entirely within the rectangle spanned by the corners `a` and `b`, in any order.
+///
+/// An entity bigger than 1x1, like a splitter, is only kept if all of its tiles, i.e. its phantoms, are within,
+/// as a part of it can not be compiled. This allows analyzing a region of a bigger blueprint as a sub-blueprint.
+pub fn entities_within(
+    entities: &[FBEntity<i32>],
+    a: Position<i32>,
+    b: Position<i32>,
+) -> Vec<FBEntity<i32>> {
+    let (min, max) = bounding_box([a, b]).unwrap();
+    let is_within = |p: Position<i32>| min.x <= p.x && p.x <= max.x && min.y <= p.y && p.y <= max.y;
+    let cut = entities
+        .iter()
+        .map(|e| e.get_base())
+        .filter(|base| !is_within(base.position))
+        .map(|base| base.id)
+        .collect::<HashSet<_>>();
+    entities
+        .iter()
+        .filter(|e| !cut.contains(&e.get_base().id))
+        .copied()
+        .collect()
+}
+
+/// Lookup between the original ids of remapped entities and their new ids, see [`remap_ids`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdMap {
+    /// Original id of each new id, i.e. the new id is the index
+    originals: Vec<EntityId>,
+}
+
+impl IdMap {
+    /// Returns the original id of the entity with the new `id`
+    pub fn original(&self, id: EntityId) -> Option<EntityId> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|idx| self.originals.get(idx).copied())
+    }
+
+    /// Returns the new id of the entity with the `original` id
+    pub fn remapped(&self, original: EntityId) -> Option<EntityId> {
+        self.originals
+            .binary_search(&original)
+            .ok()
+            .map(|idx| idx as EntityId)
+    }
+
+    /// Sets the ids of remapped entities back to the original ones.
+    ///
+    /// Ids that are not part of the map are kept.
+    pub fn restore(&self, entities: &mut [FBEntity<i32>]) {
+        for entity in entities {
+            let base = entity.get_base_mut();
+            base.id = self.original(base.id).unwrap_or(base.id);
+        }
+    }
+}
+
+/// Remaps the ids of the entities to the contiguous range `0..n`, keeping their order.
+///
+/// Blueprint entity numbers can be large and sparse, small ids make the graph, the variables of the z3 model
+/// and counter-examples easier to read. Phantoms keep sharing the id of the entity they are part of.
+/// The returned [`IdMap`] maps the new ids back to the original ones.
+pub fn remap_ids(entities: &mut [FBEntity<i32>]) -> IdMap {
+    let mut originals = entities.iter().map(|e| e.get_base().id).collect::<Vec<_>>();
+    originals.sort_unstable();
+    originals.dedup();
+    let map = IdMap { originals };
+    for entity in entities {
+        let base = entity.get_base_mut();
+        base.id = map.remapped(base.id).unwrap();
+    }
+    map
+}
+
+/// Name of the map editor's infinity chest, which is imported as a belt acting as an ideal source or sink.
+///
+/// The chest stands in for the loader moving items between it and the belt in front of or behind it,
+/// so it is placed in line with that belt and takes the direction of the blueprint, north if it has none.
+pub const INFINITY_CHEST: &str = "infinity-chest";
+
+/// Throughput of an [`INFINITY_CHEST`].
+///
+/// It is a large bound rather than infinity, so that it can be used as a capacity in the model.
+/// Only the belts attached to such an entity limit the flow through it.
+pub const UNBOUNDED_THROUGHPUT: f64 = 1_000_000.0;
+
+/// Non-integral throughputs of the known entities, as `(throughput, numerator, denominator)`
+const EXACT_THROUGHPUTS: [(f64, u128, u128); 7] = [
+    (0.5, 1, 2),
+    (0.6, 3, 5),
+    (0.75, 3, 4),
+    (0.83, 83, 100),
+    (1.2, 6, 5),
+    (1.25, 5, 4),
+    (2.31, 231, 100),
+];
+
+/// Returns the exact rational of a known throughput, e.g. 83/100 for the 0.83 items/s of an inserter.
+///
+/// Integral throughputs, like the belt tiers, are always exact. The known non-integral throughputs are
+/// mapped by value instead of converting the `f64`, which is only the closest binary approximation.
+/// Returns `None` for any other throughput, which can only be approximated.
+pub fn exact_throughput(throughput: f64) -> Option<GenericFraction<u128>> {
+    if throughput >= 0.0 && throughput.fract() == 0.0 {
+        return Some(GenericFraction::new(throughput as u128, 1u128));
+    }
+    EXACT_THROUGHPUTS
+        .iter()
+        .find(|(t, _, _)| *t == throughput)
+        .map(|(_, num, den)| GenericFraction::new(*num, *den))
 }
 
 /// Belt entity
@@ -63,6 +286,12 @@ pub struct FBBelt<T> {
     pub base: FBBaseEntity<T>,
 }
 
+impl<T> FBBelt<T> {
+    pub fn new(base: FBBaseEntity<T>) -> Self {
+        Self { base }
+    }
+}
+
 /// Type of the underground belt. Either going into the ground, `Input`, or exiting, `Output`
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -78,6 +307,47 @@ pub struct FBUnderground<T> {
     pub belt_type: BeltType,
 }
 
+impl<T> FBUnderground<T> {
+    pub fn new(base: FBBaseEntity<T>, belt_type: BeltType) -> Self {
+        Self { base, belt_type }
+    }
+}
+
+/// Maximum distance between an input underground belt and its output for each belt tier.
+///
+/// The tiers are identified by their throughput.
+/// The default table contains the reach of the vanilla tiers, other tiers (e.g. from mods) can be
+/// added using [`UndergroundReach::with_tier`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndergroundReach {
+    reach: Vec<(f64, i32)>,
+}
+
+impl Default for UndergroundReach {
+    fn default() -> Self {
+        Self {
+            reach: vec![(15.0, 5), (30.0, 7), (45.0, 9), (60.0, 11)],
+        }
+    }
+}
+
+impl UndergroundReach {
+    /// Sets the maximum distance of the tier with the given `throughput`, replacing the previous one.
+    pub fn with_tier(mut self, throughput: f64, max_distance: i32) -> Self {
+        self.reach.retain(|(t, _)| *t != throughput);
+        self.reach.push((throughput, max_distance));
+        self
+    }
+
+    /// Returns the maximum distance of the tier with the given `throughput`, if the tier is known.
+    pub fn max_distance(&self, throughput: f64) -> Option<i32> {
+        self.reach
+            .iter()
+            .find(|(t, _)| *t == throughput)
+            .map(|(_, d)| *d)
+    }
+}
+
 /// Side priority for input or output of splitters
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -95,6 +365,31 @@ pub struct FBSplitter<T> {
     pub output_prio: Priority,
 }
 
+impl<T> FBSplitter<T> {
+    /// Creates a splitter. Its `base` is the right half of the splitter when facing its direction,
+    /// the left half is the phantom, see [`add_phantoms`].
+    pub fn new(base: FBBaseEntity<T>, input_prio: Priority, output_prio: Priority) -> Self {
+        Self {
+            base,
+            input_prio,
+            output_prio,
+        }
+    }
+
+    /// Returns a copy of the splitter with the given output priority
+    pub fn with_output_priority(self, output_prio: Priority) -> Self {
+        Self {
+            output_prio,
+            ..self
+        }
+    }
+
+    /// Returns a copy of the splitter with the given input priority
+    pub fn with_input_priority(self, input_prio: Priority) -> Self {
+        Self { input_prio, ..self }
+    }
+}
+
 impl FBSplitter<i32> {
     /// Get the phantom associated with a splitter entity.
     /// This is the left side of the splitter.
@@ -120,11 +415,20 @@ pub trait InserterTrait {
 }
 
 /// Inserter entity
+///
+/// The direction of an inserter is the direction it moves items in, from its source to its destination.
+/// This is the opposite of the direction stored in a Factorio blueprint, which points to the source.
 #[derive(Debug, Clone, Copy)]
 pub struct FBInserter<T> {
     pub base: FBBaseEntity<T>,
 }
 
+impl<T> FBInserter<T> {
+    pub fn new(base: FBBaseEntity<T>) -> Self {
+        Self { base }
+    }
+}
+
 impl InserterTrait for FBInserter<i32> {
     fn get_source(&self) -> Position<i32> {
         self.base.position.shift(self.base.direction, -1)
@@ -136,11 +440,19 @@ impl InserterTrait for FBInserter<i32> {
 }
 
 /// Long inserter entity
+///
+/// Uses the same direction convention as [`FBInserter`].
 #[derive(Debug, Clone, Copy)]
 pub struct FBLongInserter<T> {
     pub base: FBBaseEntity<T>,
 }
 
+impl<T> FBLongInserter<T> {
+    pub fn new(base: FBBaseEntity<T>) -> Self {
+        Self { base }
+    }
+}
+
 impl InserterTrait for FBLongInserter<i32> {
     fn get_source(&self) -> Position<i32> {
         self.base.position.shift(self.base.direction, -2)
@@ -157,6 +469,12 @@ pub struct FBAssembler<T> {
     pub base: FBBaseEntity<T>,
 }
 
+impl<T> FBAssembler<T> {
+    pub fn new(base: FBBaseEntity<T>) -> Self {
+        Self { base }
+    }
+}
+
 impl FBAssembler<i32> {
     /// Get all the phantoms associated with the assembler entity.
     /// These are all the cells around the assembler entity as it's size is 3x3.
@@ -185,3 +503,80 @@ impl FBAssembler<i32> {
 pub struct FBAssemblerPhantom<T> {
     pub base: FBBaseEntity<T>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities_within() {
+        let base = |id, x, y| FBBaseEntity::new(id, Position { x, y }, Direction::North, 15.0);
+        let mut entities = (0..3)
+            .map(|y| FBEntity::Belt(FBBelt::new(base(y + 1, 0, y))))
+            .collect::<Vec<_>>();
+        entities.push(FBEntity::Splitter(FBSplitter::new(
+            base(4, 1, 3),
+            Priority::None,
+            Priority::None,
+        )));
+        add_phantoms(&mut entities);
+        let ids = |a, b| {
+            let mut ids = super::entities_within(&entities, a, b)
+                .iter()
+                .map(|e| e.get_base().id)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        // the phantom of the splitter is within, but the splitter itself is not
+        let column = (Position { x: 0, y: 0 }, Position { x: 0, y: 3 });
+        assert_eq!(ids(column.0, column.1), vec![1, 2, 3]);
+        assert_eq!(ids(column.1, column.0), vec![1, 2, 3]);
+        assert_eq!(
+            ids(Position { x: 1, y: 3 }, Position { x: 0, y: 1 }),
+            vec![2, 3, 4, 4]
+        );
+        assert!(ids(Position { x: 2, y: 0 }, Position { x: 3, y: 3 }).is_empty());
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn remap_ids_roundtrip() {
+        use crate::{
+            backends::{belt_balancer_f, BlueprintProofEntity, ModelFlags, ProofResult},
+            frontend::Compiler,
+            import::file_to_entities,
+            ir::{CoalesceStrength, FlowGraphFun},
+        };
+
+        let original = file_to_entities("tests/3-2-broken").unwrap();
+        let mut entities = original.clone();
+        // make the ids sparse
+        for entity in &mut entities {
+            entity.get_base_mut().id = entity.get_base().id * 7 + 100;
+        }
+        let sparse = entities.clone();
+
+        let map = remap_ids(&mut entities);
+        let mut ids = entities.iter().map(|e| e.get_base().id).collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids, (0..ids.len() as EntityId).collect::<Vec<_>>());
+        assert_eq!(map.original(0), Some(107));
+        assert_eq!(map.remapped(107), Some(0));
+
+        let prove = |entities: Vec<FBEntity<i32>>| {
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            BlueprintProofEntity::new(graph, None).model(belt_balancer_f, ModelFlags::empty())
+        };
+        assert_eq!(prove(entities.clone()), ProofResult::Unsat);
+        assert_eq!(prove(sparse.clone()), ProofResult::Unsat);
+
+        map.restore(&mut entities);
+        let restored = entities.iter().map(|e| e.get_base().id).collect::<Vec<_>>();
+        let expected = sparse.iter().map(|e| e.get_base().id).collect::<Vec<_>>();
+        assert_eq!(restored, expected);
+    }
+}
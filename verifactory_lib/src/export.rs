@@ -0,0 +1,219 @@
+//! Utility functions to convert a list of `FBEntity`s back into a Factorio blueprint string.
+//!
+//! This is the inverse of the [`import`](crate::import) module and allows reproducing a design,
+//! e.g. together with the input rates of a counter-example, in game.
+
+use std::io::Write;
+
+use anyhow::Result;
+use base64::engine::{general_purpose, Engine as _};
+use flate2::{write::ZlibEncoder, Compression};
+use serde_json::{json, Map, Value};
+
+use crate::entities::{BeltType, FBEntity, Priority, INFINITY_CHEST, UNBOUNDED_THROUGHPUT};
+
+/// Version of the blueprint format using 16 directions, as in the imported blueprints
+const BLUEPRINT_VERSION: u64 = 562949954404356;
+
+/// Returns the prefix of the belt entity names for the tier with the given `throughput`
+fn belt_prefix(throughput: f64) -> &'static str {
+    match throughput as i32 {
+        30 => "fast-",
+        45 => "express-",
+        60 => "turbo-",
+        _ => "",
+    }
+}
+
+fn priority_name(priority: Priority) -> Option<&'static str> {
+    match priority {
+        Priority::None => None,
+        Priority::Left => Some("left"),
+        Priority::Right => Some("right"),
+    }
+}
+
+/// Converts an entity into its JSON representation, returns `None` for phantoms.
+fn entity_to_json(entity: &FBEntity<i32>) -> Option<Value> {
+    let base = entity.get_base();
+    let prefix = belt_prefix(base.throughput);
+    /* in Factorio blueprints the y-axis is inverted and entities are placed at the tile centers */
+    let (mut x, mut y) = (base.position.x as f64 + 0.5, 0.5 - base.position.y as f64);
+    let mut direction = base.direction;
+    let mut fields = Map::new();
+
+    let name = match entity {
+        /* imported from an infinity chest, see `INFINITY_CHEST` */
+        FBEntity::Belt(_) if base.throughput == UNBOUNDED_THROUGHPUT => INFINITY_CHEST.to_string(),
+        FBEntity::Belt(_) => format!("{}transport-belt", prefix),
+        FBEntity::Underground(u) => {
+            let belt_type = match u.belt_type {
+                BeltType::Input => "input",
+                BeltType::Output => "output",
+            };
+            fields.insert("type".into(), belt_type.into());
+            format!("{}underground-belt", prefix)
+        }
+        FBEntity::Splitter(s) => {
+            /* a splitter is placed in between the tiles of its two halves */
+            let phantom = s.get_phantom().base.position;
+            x = (base.position.x + phantom.x) as f64 / 2.0 + 0.5;
+            y = 0.5 - (base.position.y + phantom.y) as f64 / 2.0;
+            if let Some(p) = priority_name(s.input_prio) {
+                fields.insert("input_priority".into(), p.into());
+            }
+            if let Some(p) = priority_name(s.output_prio) {
+                fields.insert("output_priority".into(), p.into());
+            }
+            format!("{}splitter", prefix)
+        }
+        /* Factorio stores the direction of the pickup, see `FBInserter` */
+        FBEntity::Inserter(_) => {
+            direction = direction.flip();
+            let name = if base.throughput == 0.6 {
+                "burner-inserter"
+            } else if base.throughput == 0.83 {
+                "inserter"
+            } else {
+                "fast-inserter"
+            };
+            name.to_string()
+        }
+        FBEntity::LongInserter(_) => {
+            direction = direction.flip();
+            "long-handed-inserter".to_string()
+        }
+        FBEntity::Assembler(_) => {
+            let multiplier = base.quality.map_or(1.0, |q| q.crafting_speed_multiplier());
+            let speed = base.throughput / multiplier;
+            let tier = if (speed - 0.5).abs() < 1e-6 {
+                1
+            } else if (speed - 0.75).abs() < 1e-6 {
+                2
+            } else {
+                3
+            };
+            format!("assembling-machine-{}", tier)
+        }
+        FBEntity::SplitterPhantom(_) | FBEntity::AssemblerPhantom(_) => return None,
+    };
+
+    fields.insert("entity_number".into(), base.id.into());
+    fields.insert("name".into(), name.into());
+    fields.insert("position".into(), json!({ "x": x, "y": y }));
+    fields.insert("direction".into(), (direction as u8).into());
+    if let Some(quality) = base.quality {
+        fields.insert("quality".into(), quality.name().into());
+    }
+    Some(Value::Object(fields))
+}
+
+/// Converts the entities into a blueprint string that can be imported in Factorio.
+///
+/// The `description` is shown in game when hovering the blueprint, e.g. to describe the input
+/// rates of a counter-example. Phantoms are skipped, as they are part of the entities they belong to.
+pub fn entities_to_string(entities: &[FBEntity<i32>], description: Option<&str>) -> Result<String> {
+    let entities = entities
+        .iter()
+        .filter_map(entity_to_json)
+        .collect::<Vec<_>>();
+    let mut blueprint = json!({
+        "item": "blueprint",
+        "label": "VeriFactory export",
+        "entities": entities,
+        "version": BLUEPRINT_VERSION,
+    });
+    if let Some(description) = description {
+        blueprint["description"] = description.into();
+    }
+    let json = serde_json::to_vec(&json!({ "blueprint": blueprint }))?;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&json)?;
+    let compressed = encoder.finish()?;
+    /* the first byte is the version of the blueprint string format */
+    Ok(format!("0{}", general_purpose::STANDARD.encode(compressed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        import::{decompress_string, file_to_entities, string_to_entities},
+        utils::Position,
+    };
+
+    use super::*;
+
+    /// Returns the entities as comparable tuples, relative to the position of the first one
+    fn normalized(entities: &[FBEntity<i32>]) -> Vec<String> {
+        let origin = entities[0].get_base().position;
+        let mut normalized = entities
+            .iter()
+            .map(|e| {
+                let mut e = *e;
+                let base = e.get_base_mut();
+                base.position = Position {
+                    x: base.position.x - origin.x,
+                    y: base.position.y - origin.y,
+                };
+                format!("{:?}", e)
+            })
+            .collect::<Vec<_>>();
+        normalized.sort();
+        normalized
+    }
+
+    #[test]
+    fn roundtrip() {
+        for file in [
+            "tests/4-4",
+            "tests/underground_test",
+            "tests/inserter_directions",
+            "tests/infinity_io",
+        ] {
+            let entities = file_to_entities(file).unwrap();
+            let exported = entities_to_string(&entities, None).unwrap();
+            let imported = string_to_entities(&exported).unwrap();
+            assert_eq!(normalized(&imported), normalized(&entities), "{}", file);
+        }
+    }
+
+    #[test]
+    fn infinity_chest_name() {
+        let entities = file_to_entities("tests/infinity_io").unwrap();
+        let exported = entities_to_string(&entities, None).unwrap();
+        let json = decompress_string(&exported).unwrap();
+        let names = json["blueprint"]["entities"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| e["name"] == INFINITY_CHEST)
+            .count();
+        assert_eq!(names, 2);
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn counter_example_description() {
+        use crate::{
+            backends::{belt_balancer_f, counter_example_f, ModelFlags},
+            frontend::Compiler,
+            ir::{CoalesceStrength, FlowGraphFun},
+        };
+        use z3::{Config, Context};
+
+        let entities = file_to_entities("tests/3-2-broken").unwrap();
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let example =
+            counter_example_f(&graph, &ctx, belt_balancer_f, ModelFlags::empty(), None).unwrap();
+
+        let description = example.to_string();
+        let exported = entities_to_string(&entities, Some(&description)).unwrap();
+        let json = decompress_string(&exported).unwrap();
+        assert_eq!(json["blueprint"]["description"], description.as_str());
+        assert_eq!(string_to_entities(&exported).unwrap().len(), entities.len());
+    }
+}
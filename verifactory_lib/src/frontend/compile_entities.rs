@@ -1,13 +1,30 @@
 use fraction::GenericFraction;
 use petgraph::prelude::NodeIndex;
 use std::collections::HashMap;
+use tracing::warn;
 
 use crate::{
-    entities::{FBBelt, FBEntity, FBSplitter, FBUnderground},
+    entities::{
+        exact_throughput, FBAssembler, FBBelt, FBEntity, FBSplitter, FBUnderground,
+        UNBOUNDED_THROUGHPUT,
+    },
     ir::{self, Connector, Edge, FlowGraph, Node},
     utils::{Position, Side},
 };
 
+/// Returns the capacity of an edge carrying `throughput`, exact for all the known throughputs.
+///
+/// Unknown throughputs fall back to converting the `f64`, see [`Compiler::check_exact_throughputs`](super::Compiler::check_exact_throughputs).
+pub(super) fn capacity(throughput: f64) -> GenericFraction<u128> {
+    exact_throughput(throughput).unwrap_or_else(|| {
+        warn!(
+            "No exact value for throughput {}, approximating it",
+            throughput
+        );
+        throughput.into()
+    })
+}
+
 fn add_belt_to_graph(
     belt: &FBEntity<i32>,
     graph: &mut FlowGraph,
@@ -15,7 +32,7 @@ fn add_belt_to_graph(
 ) {
     let base = belt.get_base();
     let id = base.id;
-    let capacity = base.throughput.into();
+    let capacity = capacity(base.throughput);
 
     /* add the nodes to the graph */
     let input = Node::Connector(Connector { id });
@@ -80,7 +97,7 @@ impl AddToGraph for FBSplitter<i32> {
             output_priority: self.output_prio.into(),
             id,
         };
-        let capacity = self.base.throughput.into();
+        let capacity = capacity(self.base.throughput);
 
         /* add the nodes to the graph */
         let splitter_idx = graph.add_node(Node::Splitter(ir_splitter));
@@ -125,3 +142,27 @@ impl AddToGraph for FBSplitter<i32> {
         graph.add_edge(merger_idx, splitter_idx, merger_splitter_edge);
     }
 }
+
+/// An assembler is compiled into an input port, collecting the items of the inserters dropping into it,
+/// and an output port, feeding the inserters picking up from it, both at its center.
+///
+/// The rate of an assembler depends on its recipe, see [`assembler_supply`](crate::analyze::assembler_supply),
+/// so only the inserters limit the flow through it.
+impl AddToGraph for FBAssembler<i32> {
+    fn add_to_graph(
+        &self,
+        graph: &mut FlowGraph,
+        pos_to_connector: &mut HashMap<Position<i32>, (NodeIndex, NodeIndex)>,
+    ) {
+        let id = self.base.id;
+        let in_idx = graph.add_node(Node::Connector(Connector { id }));
+        let out_idx = graph.add_node(Node::Connector(Connector { id }));
+        pos_to_connector.insert(self.base.position, (in_idx, out_idx));
+
+        let edge = Edge {
+            side: Side::None,
+            capacity: capacity(UNBOUNDED_THROUGHPUT),
+        };
+        graph.add_edge(in_idx, out_idx, edge);
+    }
+}
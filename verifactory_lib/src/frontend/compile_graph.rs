@@ -1,19 +1,29 @@
-use petgraph::Direction::{Incoming, Outgoing};
+use anyhow::{bail, Result};
+use fraction::GenericFraction;
+use petgraph::{
+    graph::NodeIndex,
+    Direction::{Incoming, Outgoing},
+};
 use relations::Relation;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
     hash::Hash,
     rc::Rc,
 };
+use tracing::warn;
 
 use crate::{
-    entities::{BeltType, EntityId, FBEntity, FBUnderground, InserterTrait},
-    ir::{Edge, FlowGraph, Input, Node, Output},
-    utils::{Direction, Position, Side},
+    entities::{
+        exact_throughput, BeltType, EntityId, FBEntity, FBUnderground, InserterTrait,
+        UndergroundReach,
+    },
+    ir::{Edge, FlowGraph, GraphHelper, Input, Merger, Node, Output},
+    utils::{bounding_box, Direction, Position, Side},
 };
 
-use super::compile_entities::AddToGraph;
+use super::compile_entities::{capacity, AddToGraph};
 
 trait RelationMap<T>
 where
@@ -21,6 +31,7 @@ where
 {
     fn transpose(self) -> Self;
     fn to_relation(&self) -> Relation<T>;
+    #[allow(dead_code)]
     fn get_cardinality(&self, key: &T) -> i32;
     fn add(&mut self, key: &T, value: T) -> bool;
 }
@@ -60,16 +71,65 @@ where
 
 pub type RelMap<T> = HashMap<T, HashSet<T>>;
 
+/// Role a tile plays in the graph created by [`Compiler::create_graph`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileRole {
+    /// Belt-like tile that is not fed by anything
+    Input,
+    /// Belt-like tile that does not feed anything
+    Output,
+    /// Belt-like tile that is fed by and feeds other tiles
+    Internal,
+    /// Belt-like tile that is neither fed by nor feeds other tiles
+    Disconnected,
+    /// Tile occupied by an entity that is not part of the graph, e.g. an inserter
+    NotCompiled,
+    /// Empty tile
+    Empty,
+}
+
+/// Explanation of how the [`Compiler`] interpreted a single tile of the blueprint
+#[derive(Debug, Clone)]
+pub struct TileExplanation {
+    /// Entity occupying the tile
+    pub entity: Option<FBEntity<i32>>,
+    /// All the tiles occupied by the entity, e.g. both halves of a splitter
+    pub occupies: Vec<Position<i32>>,
+    /// Tiles the tile feeds into
+    pub feeds_to: HashSet<Position<i32>>,
+    /// Tiles feeding into the tile
+    pub feeds_from: HashSet<Position<i32>>,
+    /// Role of the tile in the graph
+    pub role: TileRole,
+}
+
+/// Rule deciding which open ends of the belts are compiled into inputs and outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IoRule {
+    /// Every belt that is not fed by anything is an input and every belt that does not feed anything is an output.
+    #[default]
+    OpenEnds,
+    /// Only the open ends facing the boundary of the blueprint are inputs and outputs:
+    /// a belt is an input if the tile feeding it lies outside the bounding box of the blueprint,
+    /// and an output if the tile it feeds does.
+    ///
+    /// Open ends inside the blueprint, e.g. a belt whose feeder was cropped out of the blueprint,
+    /// are left unconnected and carry no items. Taps are always both an input and an output.
+    Boundary,
+}
+
 /* XXX: do we really need the entities vector?
  * => remove Rc, get entities with pos_to_entity.values() */
 pub struct Compiler {
     entities: Vec<Rc<FBEntity<i32>>>,
     positions: HashSet<Position<i32>>,
     belt_positions: HashSet<Position<i32>>,
+    #[allow(dead_code)]
     inserter_positions: HashSet<Position<i32>>,
     feeds_to: RelMap<Position<i32>>,
     pub feeds_from: RelMap<Position<i32>>,
     pos_to_entity: HashMap<Position<i32>, Rc<FBEntity<i32>>>,
+    io_rule: IoRule,
 }
 
 struct PostionSets {
@@ -131,6 +191,7 @@ impl Compiler {
     pub fn populate_feeds_to(
         pos_to_entity: &HashMap<Position<i32>, Rc<FBEntity<i32>>>,
         entities: &Vec<Rc<FBEntity<i32>>>,
+        reach: &UndergroundReach,
     ) -> RelMap<Position<i32>> {
         let mut feeds_to = HashMap::new();
 
@@ -151,6 +212,30 @@ impl Compiler {
             }
         }
 
+        /// Adds the feed of an inserter, unless it picks up from and drops to the same entity.
+        ///
+        /// An inserter dropping into an assembler feeds its input port and an inserter picking up from it
+        /// is fed by its output port, see [`assembler_port`].
+        fn add_inserter_feeds(
+            feeds_to: &mut RelMap<Position<i32>>,
+            pos_to_entity: &HashMap<Position<i32>, Rc<FBEntity<i32>>>,
+            id: EntityId,
+            source: Position<i32>,
+            destination: Position<i32>,
+        ) {
+            let same_entity = match (pos_to_entity.get(&source), pos_to_entity.get(&destination)) {
+                (Some(s), Some(d)) => s.get_base().id == d.get_base().id,
+                _ => false,
+            };
+            if source == destination || same_entity {
+                warn!("Ignoring inserter {id} picking up from and dropping to the same entity");
+                return;
+            }
+            let source = assembler_port(pos_to_entity, source).unwrap_or(source);
+            let destination = assembler_port(pos_to_entity, destination).unwrap_or(destination);
+            feeds_to.add(&source, destination);
+        }
+
         let output_undergrounds = entities.iter().filter_map(|e| match **e {
             FBEntity::Underground(x) if x.belt_type == BeltType::Output => Some(e.clone()),
             _ => None,
@@ -164,7 +249,7 @@ impl Compiler {
                 FBEntity::Belt(_) => add_feeds_to(&mut feeds_to, pos_to_entity, pos, dir),
                 FBEntity::Underground(u) if u.belt_type == BeltType::Input => {
                     if let Some(output_pos) =
-                        find_underground_output(&u, output_undergrounds.clone())
+                        find_underground_output(&u, output_undergrounds.clone(), reach)
                     {
                         feeds_to.add(&pos, output_pos);
                     }
@@ -174,29 +259,39 @@ impl Compiler {
                 FBEntity::SplitterPhantom(_) => {
                     add_feeds_to(&mut feeds_to, pos_to_entity, pos, dir)
                 }
-                FBEntity::Inserter(l) => {
-                    let source = l.get_source();
-                    let destination = l.get_destination();
-                    feeds_to.add(&source, destination);
-                }
-                FBEntity::LongInserter(l) => {
-                    let source = l.get_source();
-                    let destination = l.get_destination();
-                    feeds_to.add(&source, destination);
-                }
-                FBEntity::Assembler(_) => todo!(),
-                FBEntity::AssemblerPhantom(_) => todo!(),
+                FBEntity::Inserter(l) => add_inserter_feeds(
+                    &mut feeds_to,
+                    pos_to_entity,
+                    base.id,
+                    l.get_source(),
+                    l.get_destination(),
+                ),
+                FBEntity::LongInserter(l) => add_inserter_feeds(
+                    &mut feeds_to,
+                    pos_to_entity,
+                    base.id,
+                    l.get_source(),
+                    l.get_destination(),
+                ),
+                /* assemblers are only connected by the inserters around them */
+                FBEntity::Assembler(_) | FBEntity::AssemblerPhantom(_) => (),
             };
         }
-        /* validate that noting feeds into an output underground except for an input underground */
+        /* validate the feeds into undergrounds depending on the side they come from */
         for (source, set) in feeds_to.iter_mut() {
             set.retain(|dest| {
                 let source_entity = pos_to_entity.get(source);
                 let dest_entity = pos_to_entity.get(dest);
-                if let (Some(source), Some(dest)) = (source_entity, dest_entity) {
-                    let dest_is_output = matches!(**dest, FBEntity::Underground(x) if x.belt_type == BeltType::Output);
-                    let source_is_input = matches!(**source, FBEntity::Underground(x) if x.belt_type == BeltType::Input);
-                    return !dest_is_output || source_is_input;
+                if let (Some(source_entity), Some(dest_entity)) = (source_entity, dest_entity) {
+                    if let FBEntity::Underground(u) = **dest_entity {
+                        if !can_feed_underground(source_entity, &u) {
+                            warn!(
+                                "Underground at {:?} blocks the belt at {:?}, dropping feed",
+                                dest, source
+                            );
+                            return false;
+                        }
+                    }
                 }
                 true
             });
@@ -209,13 +304,19 @@ impl Compiler {
     pub fn populate_feeds_from(
         pos_to_entity: &HashMap<Position<i32>, Rc<FBEntity<i32>>>,
         entities: &Vec<Rc<FBEntity<i32>>>,
+        reach: &UndergroundReach,
     ) -> RelMap<Position<i32>> {
-        Self::populate_feeds_to(pos_to_entity, entities).transpose()
+        Self::populate_feeds_to(pos_to_entity, entities, reach).transpose()
     }
 }
 
 impl Compiler {
     pub fn new(entities: Vec<FBEntity<i32>>) -> Self {
+        Self::with_underground_reach(entities, &UndergroundReach::default())
+    }
+
+    /// Creates a compiler connecting underground belts according to the given `reach` table.
+    pub fn with_underground_reach(entities: Vec<FBEntity<i32>>, reach: &UndergroundReach) -> Self {
         let entities: Vec<_> = entities.into_iter().map(Rc::new).collect();
         let pos_to_entity = Self::generate_pos_to_entity(&entities);
 
@@ -224,8 +325,8 @@ impl Compiler {
             belt_positions,
             inserter_positions,
         } = Self::generate_position_sets(&pos_to_entity);
-        let feeds_to = Self::populate_feeds_to(&pos_to_entity, &entities);
-        let feeds_from = Self::populate_feeds_from(&pos_to_entity, &entities);
+        let feeds_to = Self::populate_feeds_to(&pos_to_entity, &entities, reach);
+        let feeds_from = Self::populate_feeds_from(&pos_to_entity, &entities, reach);
 
         Self {
             entities,
@@ -235,13 +336,85 @@ impl Compiler {
             feeds_to,
             feeds_from,
             pos_to_entity,
+            io_rule: IoRule::default(),
         }
     }
 
+    /// Sets the rule deciding which open ends become inputs and outputs, see [`IoRule`].
+    pub fn with_io_rule(mut self, io_rule: IoRule) -> Self {
+        self.io_rule = io_rule;
+        self
+    }
+
+    /// Returns the lowest and the highest corner of the bounding box of the blueprint, `None` if it is empty.
+    fn bounding_box(&self) -> Option<(Position<i32>, Position<i32>)> {
+        bounding_box(self.positions.iter().copied())
+    }
+
+    /// Returns whether the tile `distance` tiles away from `pos`, along the direction of its entity,
+    /// lies outside of the bounding box `(min, max)`.
+    fn faces_boundary(
+        &self,
+        pos: Position<i32>,
+        distance: i32,
+        (min, max): (Position<i32>, Position<i32>),
+    ) -> bool {
+        let Some(entity) = self.pos_to_entity.get(&pos) else {
+            return true;
+        };
+        let next = pos.shift(entity.get_base().direction, distance);
+        next.x < min.x || next.x > max.x || next.y < min.y || next.y > max.y
+    }
+
     pub fn pos_to_id(&self, position: &Position<i32>) -> Option<EntityId> {
         self.pos_to_entity.get(position).map(|e| e.get_base().id)
     }
 
+    /// Returns the position of each entity, the reverse of [`Compiler::pos_to_id`].
+    ///
+    /// Splitters occupy two tiles and are mapped to the position of the splitter, not of its phantom.
+    pub fn entity_positions(&self) -> HashMap<EntityId, Position<i32>> {
+        self.entities
+            .iter()
+            .filter(|e| !matches!(***e, FBEntity::SplitterPhantom(_)))
+            .map(|e| (e.get_base().id, e.get_base().position))
+            .collect()
+    }
+
+    /// Explains what the tile at `pos` was interpreted as by the compiler:
+    /// the entity occupying it, what it feeds to, what feeds it and its role in the graph.
+    pub fn explain_tile(&self, pos: Position<i32>) -> TileExplanation {
+        let entity = self.pos_to_entity.get(&pos).map(|e| **e);
+        let occupies = match entity {
+            Some(FBEntity::Splitter(s)) => vec![s.base.position, s.get_phantom().base.position],
+            Some(e) => vec![e.get_base().position],
+            None => vec![],
+        };
+        let feeds_to = self.feeds_to.get(&pos).cloned().unwrap_or_default();
+        let feeds_from = self.feeds_from.get(&pos).cloned().unwrap_or_default();
+
+        let role = if entity.is_none() {
+            TileRole::Empty
+        } else if !self.belt_positions.contains(&pos) {
+            TileRole::NotCompiled
+        } else {
+            match (feeds_from.is_empty(), feeds_to.is_empty()) {
+                (true, true) => TileRole::Disconnected,
+                (true, false) => TileRole::Input,
+                (false, true) => TileRole::Output,
+                (false, false) => TileRole::Internal,
+            }
+        };
+
+        TileExplanation {
+            entity,
+            occupies,
+            feeds_to,
+            feeds_from,
+            role,
+        }
+    }
+
     /// Creates a relation of positions that feed other positions
     ///
     /// Usable to peform reachability analysis.
@@ -282,7 +455,7 @@ impl Compiler {
     pub fn find_input_positions(&self) -> Vec<Position<i32>> {
         self.belt_positions
             .iter()
-            .filter(|k| self.feeds_from.get(k).is_none())
+            .filter(|k| !self.feeds_from.contains_key(k))
             .cloned()
             .collect()
     }
@@ -290,12 +463,97 @@ impl Compiler {
     pub fn find_output_positions(&self) -> Vec<Position<i32>> {
         self.belt_positions
             .iter()
-            .filter(|k| self.feeds_to.get(k).is_none())
+            .filter(|k| !self.feeds_to.contains_key(k))
             .cloned()
             .collect()
     }
 
+    /// Returns the positions of entities, other than splitters and assemblers, that feed more than one position.
+    ///
+    /// Such a "tee" arises e.g. when an inserter picks up from a belt that also feeds forward.
+    /// Splitting flow without a splitter is currently not supported: [`Compiler::create_graph`]
+    /// only keeps the forward feed of a tee and drops the feeds created by inserters.
+    pub fn find_tees(&self) -> Vec<Position<i32>> {
+        self.feeds_to
+            .iter()
+            .filter(|(pos, set)| {
+                let splits = self
+                    .pos_to_entity
+                    .get(pos)
+                    .is_some_and(|e| matches!(**e, FBEntity::Splitter(_) | FBEntity::Assembler(_)));
+                set.len() > 1 && !splits
+            })
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    /// Returns the `(source, destination)` pairs of all the feeds created by inserters,
+    /// together with the combined throughput of the inserters creating the feed
+    fn inserter_feeds(&self) -> HashMap<(Position<i32>, Position<i32>), GenericFraction<u128>> {
+        let mut feeds = HashMap::new();
+        for e in &self.entities {
+            let (source, destination) = match **e {
+                FBEntity::Inserter(i) => (i.get_source(), i.get_destination()),
+                FBEntity::LongInserter(l) => (l.get_source(), l.get_destination()),
+                _ => continue,
+            };
+            let port = |pos| assembler_port(&self.pos_to_entity, pos).unwrap_or(pos);
+            let feed = (port(source), port(destination));
+            *feeds.entry(feed).or_insert(GenericFraction::from(0)) +=
+                capacity(e.get_base().throughput);
+        }
+        feeds
+    }
+
+    /// Returns the throughput of the tunnel if `source` is an input underground belt connected to the output at `dest`.
+    ///
+    /// Only undergrounds of the same tier are connected, so the tunnel has the throughput of either end.
+    fn tunnel_capacity(
+        &self,
+        source: &Position<i32>,
+        dest: &Position<i32>,
+    ) -> Option<GenericFraction<u128>> {
+        match (
+            **self.pos_to_entity.get(source)?,
+            **self.pos_to_entity.get(dest)?,
+        ) {
+            (FBEntity::Underground(input), FBEntity::Underground(output))
+                if input.belt_type == BeltType::Input && output.belt_type == BeltType::Output =>
+            {
+                Some(capacity(input.base.throughput))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks that the throughput of every entity has an exact rational value.
+    ///
+    /// The compiler falls back to approximating unknown throughputs, e.g. those of modded entities.
+    /// Call this before compiling to reject them instead, so that the solver only reasons over exact values.
+    pub fn check_exact_throughputs(&self) -> Result<()> {
+        for e in &self.entities {
+            let base = e.get_base();
+            if exact_throughput(base.throughput).is_none() {
+                bail!(
+                    "Entity {} has throughput {}, which has no exact value",
+                    base.id,
+                    base.throughput
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn create_graph(&self) -> FlowGraph {
+        self.create_graph_with_taps(&[])
+    }
+
+    /// Creates the graph, compiling the belts in `taps` into both an input and an output.
+    ///
+    /// A tap is cut in two: everything feeding into the belt ends in an [`Output`] and
+    /// everything fed by the belt starts from an [`Input`], both with the id of the belt.
+    /// This allows analyzing a belt that can serve as either, e.g. a tap point of a bus.
+    pub fn create_graph_with_taps(&self, taps: &[EntityId]) -> FlowGraph {
         let mut graph = petgraph::Graph::new();
 
         let mut pos_to_connector = HashMap::new();
@@ -312,19 +570,57 @@ impl Compiler {
                 _ => (),
             }
         }
+        let tees = self.find_tees();
+        let inserter_feeds = self.inserter_feeds();
         for (source, set) in &self.feeds_to {
-            if let Some(source_idx) = pos_to_connector.get(source).map(|i| i.1) {
-                for dest in set {
-                    if let Some(dest_idx) = pos_to_connector.get(dest).map(|i| i.0) {
-                        let edge = Edge {
-                            side: Side::None,
-                            capacity: 69.into(),
-                        };
-                        graph.add_edge(source_idx, dest_idx, edge);
-                    }
+            for dest in set {
+                /* a tee only keeps its forward feed, see `find_tees` */
+                if tees.contains(source) && inserter_feeds.contains_key(&(*source, *dest)) {
+                    warn!(
+                        "Unsupported tee at {:?}, dropping feed to {:?}",
+                        source, dest
+                    );
+                    continue;
+                }
+                if !self.has_connectors(source, &pos_to_connector)
+                    || !self.has_connectors(dest, &pos_to_connector)
+                {
+                    continue;
                 }
+                let source_idx = self
+                    .connectors(*source, &mut graph, &mut pos_to_connector)
+                    .1;
+                let dest_idx = self.connectors(*dest, &mut graph, &mut pos_to_connector).0;
+                /* an inserter or an underground tunnel limits the feed to its throughput */
+                let capacity = inserter_feeds
+                    .get(&(*source, *dest))
+                    .copied()
+                    .or_else(|| self.tunnel_capacity(source, dest))
+                    .unwrap_or(69.into());
+                let edge = Edge {
+                    side: Side::None,
+                    capacity,
+                };
+                graph.add_edge(source_idx, dest_idx, edge);
             }
         }
+        insert_implicit_mergers(&mut graph);
+        /* cut the taps, so that they get promoted to both an output and an input */
+        for e in &self.entities {
+            let base = e.get_base();
+            if !taps.contains(&base.id) || !matches!(**e, FBEntity::Belt(_)) {
+                continue;
+            }
+            let (in_idx, out_idx) = pos_to_connector[&base.position];
+            if let Some(edge) = graph.find_edge(in_idx, out_idx) {
+                graph.remove_edge(edge);
+            }
+        }
+        let bounds = self.bounding_box();
+        let node_to_pos = pos_to_connector
+            .iter()
+            .flat_map(|(pos, (in_idx, out_idx))| [(*in_idx, *pos), (*out_idx, *pos)])
+            .collect::<HashMap<_, _>>();
         /* promote suitable connectors to input or output nodes */
         for node in graph.node_indices() {
             if let Some(Node::Connector(c)) = graph.node_weight(node) {
@@ -334,8 +630,15 @@ impl Compiler {
 
                 let is_output = out_degree == 0;
                 let is_input = in_degree == 0;
+                /* with the boundary rule, only the ends facing out of the blueprint are open */
+                let is_open = match (self.io_rule, node_to_pos.get(&node), bounds) {
+                    (IoRule::Boundary, Some(pos), Some(bounds)) if !taps.contains(&id) => {
+                        self.faces_boundary(*pos, if is_input { -1 } else { 1 }, bounds)
+                    }
+                    _ => true,
+                };
                 /* if the connector is not connected, leave it as is */
-                if is_input ^ is_output {
+                if is_input ^ is_output && is_open {
                     let new_node = if is_input {
                         Node::Input(Input { id })
                     } else {
@@ -346,11 +649,162 @@ impl Compiler {
                 }
             }
         }
+        debug_assert!(
+            self.phantom_leaks(&graph, &pos_to_connector).is_empty(),
+            "phantoms promoted to inputs/outputs at {:?}",
+            self.phantom_leaks(&graph, &pos_to_connector)
+        );
         graph
     }
+
+    /// Returns true if the tile at `pos` has connectors in the graph, or gets them once fed, see [`Compiler::connectors`]
+    fn has_connectors(
+        &self,
+        pos: &Position<i32>,
+        pos_to_connector: &HashMap<Position<i32>, (NodeIndex, NodeIndex)>,
+    ) -> bool {
+        pos_to_connector.contains_key(pos)
+            || matches!(
+                self.pos_to_entity.get(pos).map(|e| **e),
+                Some(FBEntity::Assembler(_))
+            )
+    }
+
+    /// Returns the input and the output connector of the tile at `pos`.
+    ///
+    /// The ports of an assembler are only added once an inserter connects it,
+    /// as an assembler without inserters would be an input and an output of its own.
+    fn connectors(
+        &self,
+        pos: Position<i32>,
+        graph: &mut FlowGraph,
+        pos_to_connector: &mut HashMap<Position<i32>, (NodeIndex, NodeIndex)>,
+    ) -> (NodeIndex, NodeIndex) {
+        if let Some(FBEntity::Assembler(assembler)) = self.pos_to_entity.get(&pos).map(|e| **e) {
+            if !pos_to_connector.contains_key(&pos) {
+                assembler.add_to_graph(graph, pos_to_connector);
+            }
+        }
+        pos_to_connector[&pos]
+    }
+
+    /// Returns the positions of the [`Input`] and [`Output`] nodes that do not belong to a real entity.
+    ///
+    /// Phantoms share the id of the entity they are part of, so the id alone can not tell them apart:
+    /// instead, every input and output has to come from a tile whose entity is not a phantom and
+    /// has the same id as the node. E.g. the unfed left lane of a splitter is an input of the
+    /// splitter, even though it lies on the tile of the splitter's phantom.
+    fn phantom_leaks(
+        &self,
+        graph: &FlowGraph,
+        pos_to_connector: &HashMap<Position<i32>, (NodeIndex, NodeIndex)>,
+    ) -> Vec<Position<i32>> {
+        pos_to_connector
+            .iter()
+            .flat_map(|(pos, (in_idx, out_idx))| [(*pos, *in_idx), (*pos, *out_idx)])
+            .filter(|(_, idx)| matches!(graph[*idx], Node::Input(_) | Node::Output(_)))
+            .filter(|(pos, idx)| {
+                let owner = self.pos_to_entity.get(pos).map(|e| **e);
+                !matches!(
+                    owner,
+                    Some(e) if !matches!(
+                        e,
+                        FBEntity::SplitterPhantom(_) | FBEntity::AssemblerPhantom(_)
+                    ) && e.get_base().id == graph[*idx].get_id()
+                )
+            })
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+}
+
+/// Returns the center of the assembler covering `pos`, where the feeds of the inserters around it are attached.
+///
+/// The inserters can pick up from and drop to any tile of the 3x3 footprint of an assembler,
+/// so they are all wired to the ports at its center. Returns `None` if `pos` is not covered by an assembler.
+fn assembler_port(
+    pos_to_entity: &HashMap<Position<i32>, Rc<FBEntity<i32>>>,
+    pos: Position<i32>,
+) -> Option<Position<i32>> {
+    let id = match **pos_to_entity.get(&pos)? {
+        FBEntity::Assembler(_) => return Some(pos),
+        FBEntity::AssemblerPhantom(p) => p.base.id,
+        _ => return None,
+    };
+    (-1..=1)
+        .flat_map(|dx| {
+            (-1..=1).map(move |dy| Position {
+                x: pos.x + dx,
+                y: pos.y + dy,
+            })
+        })
+        .find(|center| {
+            matches!(
+                pos_to_entity.get(center).map(|e| **e),
+                Some(FBEntity::Assembler(a)) if a.base.id == id
+            )
+        })
 }
 
-fn find_underground_output<I>(underground: &FBUnderground<i32>, outputs: I) -> Option<Position<i32>>
+/// Merges the flows of multiple entities feeding the same tile, e.g. a belt side-loaded from both sides.
+///
+/// A connector only has a single input, so a [`Merger`] without priority is inserted for each additional feed.
+fn insert_implicit_mergers(graph: &mut FlowGraph) {
+    let merge_points = graph
+        .node_indices()
+        .filter(|idx| matches!(graph[*idx], Node::Connector(_)) && graph.in_deg(*idx) > 1)
+        .collect::<Vec<_>>();
+    for idx in merge_points {
+        let id = graph[idx].get_id();
+        while graph.in_deg(idx) > 1 {
+            let merger = graph.add_node(Node::Merger(Merger {
+                input_priority: Side::None,
+                id,
+            }));
+            /* remove the higher edge index first, as removing an edge invalidates the last index */
+            let mut in_edges = graph.in_edge_idx(idx)[..2].to_vec();
+            in_edges.sort_by(|a, b| b.cmp(a));
+            for (edge, side) in in_edges.into_iter().zip([Side::Left, Side::Right]) {
+                let (source, _) = graph.edge_endpoints(edge).unwrap();
+                let mut weight = graph.remove_edge(edge).unwrap();
+                weight.side = side;
+                graph.add_edge(source, merger, weight);
+            }
+            let edge = Edge {
+                side: Side::None,
+                capacity: 69.into(),
+            };
+            graph.add_edge(merger, idx, edge);
+        }
+    }
+}
+
+/// Returns whether `source` can feed the underground belt `dest`, depending on the side it comes from.
+///
+/// The hood of an underground blocks belts, so nothing can be fed into the tunnel side of an input
+/// underground or into the back of an output underground, except for the matching input underground.
+/// A belt facing the exit of an output underground head-on is blocked as well.
+/// Belts coming from the side side-load the underground and are modelled like a belt side-loading
+/// another belt, i.e. without distinguishing the lane they are placed on.
+fn can_feed_underground(source: &FBEntity<i32>, dest: &FBUnderground<i32>) -> bool {
+    let source_dir = source.get_base().direction;
+    let dest_dir = dest.base.direction;
+    match dest.belt_type {
+        BeltType::Input => source_dir != dest_dir.flip(),
+        BeltType::Output => {
+            let is_tunnel =
+                matches!(source, FBEntity::Underground(u) if u.belt_type == BeltType::Input);
+            let is_side = source_dir != dest_dir && source_dir != dest_dir.flip();
+            is_tunnel || is_side
+        }
+    }
+}
+
+fn find_underground_output<I>(
+    underground: &FBUnderground<i32>,
+    outputs: I,
+    reach: &UndergroundReach,
+) -> Option<Position<i32>>
 where
     I: Iterator<Item = Rc<FBEntity<i32>>> + Clone,
 {
@@ -358,7 +812,10 @@ where
     let pos = base.position;
     let dir = base.direction;
     let throughput = base.throughput;
-    let max_distance = 3 + 2 * throughput as i32 / 15;
+    let Some(max_distance) = reach.max_distance(throughput) else {
+        warn!("No underground reach known for tier with throughput {throughput}");
+        return None;
+    };
     /* only matching underground belt tiers can be connected */
     let outputs = outputs.filter(|u| u.get_base().throughput == throughput);
     /* XXX: runs in O(8n), with n = #outputs
@@ -381,7 +838,14 @@ where
 mod tests {
     use petgraph::dot::Dot;
 
-    use crate::{import::string_to_entities, ir::FlowGraphFun};
+    use crate::{
+        entities::{
+            add_phantoms, FBBaseEntity, FBBelt, FBInserter, FBSplitter, Priority,
+            UNBOUNDED_THROUGHPUT,
+        },
+        import::string_to_entities,
+        ir::{CoalesceStrength, FlowGraphFun, GraphHelper},
+    };
 
     use super::*;
     use std::fs;
@@ -403,6 +867,29 @@ mod tests {
         assert_eq!(feeds_to, feeds_from.transpose());
     }
 
+    #[test]
+    fn io_rule_boundary() {
+        // two parallel lines, the feeder of the shorter one was cropped out of the blueprint
+        let entities = load("tests/cropped_feeder");
+        let io = |rule| {
+            let mut graph = Compiler::new(entities.clone())
+                .with_io_rule(rule)
+                .create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            graph
+                .node_weights()
+                .filter(|n| matches!(n, Node::Input(_) | Node::Output(_)))
+                .map(|n| (matches!(n, Node::Input(_)), n.get_id()))
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(
+            io(IoRule::OpenEnds),
+            HashSet::from([(true, 1), (false, 4), (true, 5), (false, 9)])
+        );
+        // the open end of the shorter line is inside the blueprint, so the line carries no items
+        assert_eq!(io(IoRule::Boundary), HashSet::from([(true, 5), (false, 9)]));
+    }
+
     #[test]
     fn inputs_generation() {
         let entities = load("tests/input_output_gen");
@@ -435,6 +922,77 @@ mod tests {
         println!("{:?}", Dot::with_config(&graph, &[]));
     }
 
+    #[test]
+    fn explain_splitter_tile() {
+        let entities = load("tests/simple_splitter");
+        let ctx = Compiler::new(entities);
+        let splitter = ctx
+            .entities
+            .iter()
+            .find_map(|e| match **e {
+                FBEntity::Splitter(s) => Some(s),
+                _ => None,
+            })
+            .unwrap();
+        let pos = splitter.base.position;
+        let phantom = splitter.get_phantom().base.position;
+
+        let explanation = ctx.explain_tile(pos);
+        assert!(matches!(explanation.entity, Some(FBEntity::Splitter(_))));
+        assert_eq!(explanation.occupies, vec![pos, phantom]);
+        assert_eq!(explanation.role, TileRole::Disconnected);
+
+        let explanation = ctx.explain_tile(phantom);
+        assert!(matches!(explanation.entity, Some(FBEntity::Splitter(_))));
+        assert_eq!(explanation.occupies, vec![pos, phantom]);
+
+        let entities = load("tests/mixed_output_tiers");
+        let ctx = Compiler::new(entities);
+        let splitter = ctx
+            .entities
+            .iter()
+            .find_map(|e| match **e {
+                FBEntity::Splitter(s) => Some(s),
+                _ => None,
+            })
+            .unwrap();
+        let pos = splitter.base.position;
+        let phantom = splitter.get_phantom().base.position;
+        let dir = splitter.base.direction;
+
+        let explanation = ctx.explain_tile(pos);
+        assert_eq!(explanation.occupies, vec![pos, phantom]);
+        assert!(explanation.feeds_to.contains(&pos.shift(dir, 1)));
+        assert!(explanation.feeds_from.contains(&pos.shift(dir, -1)));
+        assert_eq!(explanation.role, TileRole::Internal);
+
+        let explanation = ctx.explain_tile(phantom);
+        assert!(explanation.feeds_to.contains(&phantom.shift(dir, 1)));
+        assert_eq!(explanation.role, TileRole::Internal);
+    }
+
+    #[test]
+    fn tee() {
+        let entities = load("tests/tee");
+        let ctx = Compiler::new(entities);
+        assert_eq!(ctx.find_tees().len(), 1);
+        let graph = ctx.create_graph();
+        for node in graph.node_indices() {
+            assert!(graph.in_deg(node) <= 1);
+            assert!(graph.out_deg(node) <= 1);
+        }
+        let inputs = graph
+            .node_weights()
+            .filter(|n| matches!(n, Node::Input(_)))
+            .count();
+        let outputs = graph
+            .node_weights()
+            .filter(|n| matches!(n, Node::Output(_)))
+            .count();
+        assert_eq!(inputs, 2);
+        assert_eq!(outputs, 2);
+    }
+
     #[test]
     fn belt_weave() {
         let entities = load("tests/belt_weave");
@@ -444,4 +1002,363 @@ mod tests {
         assert_eq!(graph.node_count(), 2);
         assert_eq!(graph.edge_count(), 1);
     }
+
+    #[test]
+    fn tap() {
+        let entities = load("tests/simple_belt");
+        let ctx = Compiler::new(entities.clone());
+        let tap = entities[entities.len() / 2].get_base().id;
+
+        let mut graph = ctx.create_graph_with_taps(&[tap]);
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        let count = |graph: &FlowGraph, kind: fn(&Node) -> bool| {
+            graph.node_weights().filter(|n| kind(n)).count()
+        };
+        let is_input = |n: &Node| matches!(n, Node::Input(_));
+        let is_output = |n: &Node| matches!(n, Node::Output(_));
+        assert_eq!(count(&graph, is_input), 2);
+        assert_eq!(count(&graph, is_output), 2);
+        assert_eq!(graph.edge_count(), 2);
+
+        // the input and output of the tap can be removed independently
+        let mut without_input = graph.clone();
+        without_input.remove_io(&[tap], &[]);
+        assert_eq!(count(&without_input, is_input), 1);
+        assert_eq!(count(&without_input, is_output), 2);
+
+        let mut without_output = graph.clone();
+        without_output.remove_io(&[], &[tap]);
+        assert_eq!(count(&without_output, is_input), 2);
+        assert_eq!(count(&without_output, is_output), 1);
+    }
+
+    #[test]
+    fn lone_belt() {
+        let entities = load("tests/lone_belt");
+        let ctx = Compiler::new(entities);
+        let mut graph = ctx.create_graph();
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph.edge_count(), 1);
+
+        // tapping the belt cuts its only edge, leaving two isolated connectors
+        let mut graph = ctx.create_graph_with_taps(&[1]);
+        assert_eq!(graph.edge_count(), 0);
+        assert!(graph
+            .node_weights()
+            .all(|n| matches!(n, Node::Connector(_))));
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        assert_eq!(graph.node_count(), 0);
+    }
+
+    /// A yellow underground in an express belt line bottlenecks it to 15/s
+    #[test]
+    fn underground_bottleneck() {
+        let entities = load("tests/underground_bottleneck");
+        let ctx = Compiler::new(entities);
+        let mut graph = ctx.create_graph();
+
+        let tunnel = graph
+            .edge_indices()
+            .find(|e| {
+                let (src, dst) = graph.edge_endpoints(*e).unwrap();
+                (graph[src].get_id(), graph[dst].get_id()) == (2, 3)
+            })
+            .unwrap();
+        assert_eq!(graph[tunnel].capacity, 15.into());
+
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        assert_eq!(graph.edge_count(), 1);
+        let edge = graph.edge_weights().next().unwrap();
+        assert_eq!(edge.capacity, 15.into());
+    }
+
+    #[test]
+    fn underground_crossing() {
+        let entities = load("tests/underground_crossing");
+        let ctx = Compiler::new(entities);
+        let mut graph = ctx.create_graph();
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+
+        // each tunnel only connects to its colinear partner,
+        // even if a perpendicular underground lies within its reach
+        let mut connections = graph
+            .edge_indices()
+            .map(|e| {
+                let (src, dst) = graph.edge_endpoints(e).unwrap();
+                (graph[src].clone(), graph[dst].clone())
+            })
+            .map(|(src, dst)| {
+                assert!(matches!(src, Node::Input(_)));
+                assert!(matches!(dst, Node::Output(_)));
+                (src.get_id(), dst.get_id())
+            })
+            .collect::<Vec<_>>();
+        connections.sort();
+        assert_eq!(connections, vec![(1, 4), (5, 8), (9, 12)]);
+    }
+
+    /// Compiles an input underground belt and an output `distance` tiles north of it,
+    /// returning whether they are connected.
+    fn underground_connects(throughput: f64, distance: i32, reach: &UndergroundReach) -> bool {
+        let underground = |id, y, belt_type| {
+            FBEntity::Underground(FBUnderground {
+                base: FBBaseEntity {
+                    id,
+                    position: Position { x: 0, y },
+                    direction: Direction::North,
+                    throughput,
+                    quality: None,
+                },
+                belt_type,
+            })
+        };
+        let input = underground(1, 0, BeltType::Input);
+        let output_pos = Position { x: 0, y: 0 }.shift(Direction::North, distance);
+        let output = underground(2, output_pos.y, BeltType::Output);
+        let ctx = Compiler::with_underground_reach(vec![input, output], reach);
+        ctx.feeds_to
+            .get(&Position { x: 0, y: 0 })
+            .is_some_and(|set| set.contains(&output_pos))
+    }
+
+    #[test]
+    fn underground_reach() {
+        let reach = UndergroundReach::default();
+        for (throughput, max_distance) in [(15.0, 5), (30.0, 7), (45.0, 9), (60.0, 11)] {
+            assert_eq!(reach.max_distance(throughput), Some(max_distance));
+            assert!(underground_connects(throughput, max_distance, &reach));
+            assert!(!underground_connects(throughput, max_distance + 1, &reach));
+        }
+
+        assert!(!underground_connects(90.0, 1, &reach));
+        let reach = reach.with_tier(90.0, 13);
+        assert!(underground_connects(90.0, 13, &reach));
+        assert!(!underground_connects(90.0, 14, &reach));
+    }
+
+    /// An inserter whose source and destination are the same entity must not produce a self-edge
+    #[test]
+    fn inserter_same_entity() {
+        let base = |id, x, y| FBBaseEntity::new(id, Position { x, y }, Direction::North, 15.0);
+        // both ends of the inserter are tiles of the same (malformed) entity 1
+        let entities = vec![
+            FBEntity::Belt(FBBelt::new(base(1, 0, 0))),
+            FBEntity::Belt(FBBelt::new(base(1, 0, 2))),
+            FBEntity::Inserter(FBInserter::new(base(2, 0, 1))),
+        ];
+        let ctx = Compiler::new(entities);
+        assert!(!ctx
+            .feeds_to
+            .get(&Position { x: 0, y: 0 })
+            .is_some_and(|set| set.contains(&Position { x: 0, y: 2 })));
+
+        let graph = ctx.create_graph();
+        assert!(graph.edge_indices().all(|e| {
+            let (src, dst) = graph.edge_endpoints(e).unwrap();
+            src != dst
+        }));
+    }
+
+    #[test]
+    fn inserter_capacity_exact() {
+        let base = |id, x, y, throughput| {
+            FBBaseEntity::new(id, Position { x, y }, Direction::East, throughput)
+        };
+        // belt 1 --(inserter 2)--> belt 3
+        let mut inserter = base(2, 0, 1, 0.83);
+        inserter.direction = Direction::North;
+        let entities = vec![
+            FBEntity::Belt(FBBelt::new(base(1, 0, 0, 15.0))),
+            FBEntity::Inserter(FBInserter::new(inserter)),
+            FBEntity::Belt(FBBelt::new(base(3, 0, 2, 15.0))),
+        ];
+        let ctx = Compiler::new(entities.clone());
+        assert!(ctx.check_exact_throughputs().is_ok());
+
+        let graph = ctx.create_graph();
+        let feed = graph
+            .edge_indices()
+            .find(|e| {
+                let (src, dst) = graph.edge_endpoints(*e).unwrap();
+                graph[src].get_id() == 1 && graph[dst].get_id() == 3
+            })
+            .unwrap();
+        assert_eq!(graph[feed].capacity, GenericFraction::new(83u128, 100u128));
+
+        let mut entities = entities;
+        entities[1].get_base_mut().throughput = 0.7;
+        assert!(Compiler::new(entities).check_exact_throughputs().is_err());
+    }
+
+    /// The inserter dropping into the assembler feeds its input port, the one picking up from it is fed by its output port
+    #[test]
+    fn assembler_ports() {
+        let entities = load("tests/assembler_flow");
+        let position = |id| {
+            entities
+                .iter()
+                .find(|e| e.get_base().id == id && !matches!(e, FBEntity::AssemblerPhantom(_)))
+                .unwrap()
+                .get_base()
+                .position
+        };
+        let ctx = Compiler::new(entities.clone());
+        // belt 3 --(inserter 4)--> assembler 5 --(fast inserter 6)--> belt 7
+        assert_eq!(ctx.feeds_to[&position(3)], HashSet::from([position(5)]));
+        assert_eq!(ctx.feeds_to[&position(5)], HashSet::from([position(7)]));
+
+        let graph = ctx.create_graph();
+        let io = |f: fn(&Node) -> bool| {
+            graph
+                .node_weights()
+                .filter(|n| f(n))
+                .map(Node::get_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(io(|n| matches!(n, Node::Input(_))), vec![1]);
+        assert_eq!(io(|n| matches!(n, Node::Output(_))), vec![9]);
+        let capacities = |src, dst| {
+            graph
+                .edge_indices()
+                .filter(|e| {
+                    let (s, d) = graph.edge_endpoints(*e).unwrap();
+                    graph[s].get_id() == src && graph[d].get_id() == dst
+                })
+                .map(|e| graph[e].capacity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            capacities(3, 5),
+            vec![GenericFraction::new(83u128, 100u128)]
+        );
+        assert_eq!(
+            capacities(5, 7),
+            vec![GenericFraction::new(231u128, 100u128)]
+        );
+    }
+
+    #[test]
+    fn phantoms_stay_internal() {
+        let base = |id, x, y| FBBaseEntity::new(id, Position { x, y }, Direction::North, 15.0);
+        // only the right half of the splitter is fed and only its left half feeds a belt,
+        // so the lanes on the phantom tile become an input and an output of the splitter
+        let splitter = FBSplitter::new(base(2, 1, 1), Priority::None, Priority::None);
+        let phantom = splitter.get_phantom();
+        let mut entities = vec![
+            FBEntity::Belt(FBBelt::new(base(1, 1, 0))),
+            FBEntity::Splitter(splitter),
+            FBEntity::Belt(FBBelt::new(base(3, 0, 2))),
+        ];
+        add_phantoms(&mut entities);
+        // a stray phantom without its splitter, as left behind by a coordinate bug
+        let mut stray = phantom;
+        stray.base.id = 4;
+        stray.base.position = Position { x: 0, y: 3 };
+        entities.push(FBEntity::SplitterPhantom(stray));
+
+        let ctx = Compiler::new(entities);
+        let graph = ctx.create_graph();
+        let mut io = graph
+            .node_weights()
+            .filter(|n| matches!(n, Node::Input(_) | Node::Output(_)))
+            .map(|n| (matches!(n, Node::Input(_)), n.get_id()))
+            .collect::<Vec<_>>();
+        io.sort();
+        assert_eq!(io, vec![(false, 2), (false, 3), (true, 1), (true, 2)]);
+        assert!(graph.node_weights().all(|n| n.get_id() != 4));
+        assert!(matches!(
+            ctx.explain_tile(phantom.base.position).entity,
+            Some(FBEntity::Splitter(_))
+        ));
+    }
+
+    #[test]
+    fn underground_sides() {
+        let entities = load("tests/underground_sides");
+        let ctx = Compiler::new(entities);
+        let pos = |id| {
+            ctx.entities
+                .iter()
+                .find(|e| e.get_base().id == id)
+                .unwrap()
+                .get_base()
+                .position
+        };
+        let mut feeds = ctx
+            .feeds_to
+            .iter()
+            .flat_map(|(source, set)| set.iter().map(move |dest| (*source, *dest)))
+            .collect::<Vec<_>>();
+        feeds.sort_by_key(|(s, d)| (s.x, s.y, d.x, d.y));
+        // the belt side-loading the output underground (5) feeds it, the belts hitting the hood
+        // of the input underground (6) and the back of the output underground (7) are blocked
+        let mut expected = vec![
+            (pos(1), pos(2)),
+            (pos(2), pos(3)),
+            (pos(3), pos(4)),
+            (pos(5), pos(3)),
+        ];
+        expected.sort_by_key(|(s, d)| (s.x, s.y, d.x, d.y));
+        assert_eq!(feeds, expected);
+    }
+
+    #[test]
+    fn belt_merge() {
+        let entities = load("tests/belt_merge");
+        let mut graph = Compiler::new(entities).create_graph();
+        let merger = |graph: &FlowGraph| {
+            let mergers = graph
+                .node_indices()
+                .filter(|idx| matches!(graph[*idx], Node::Merger(_)))
+                .collect::<Vec<_>>();
+            assert_eq!(mergers.len(), 1);
+            mergers[0]
+        };
+        // the belt side-loaded from both sides (3) merges the two side belts (1 and 2)
+        let idx = merger(&graph);
+        assert_eq!(graph[idx].get_id(), 3);
+        let mut sources = graph
+            .in_nodes(idx)
+            .iter()
+            .map(|n| graph[*n].get_id())
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(sources, vec![1, 2]);
+
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        let idx = merger(&graph);
+        assert_eq!(graph.in_deg(idx), 2);
+        assert!(graph
+            .in_nodes(idx)
+            .iter()
+            .all(|n| matches!(graph[*n], Node::Input(_))));
+        assert!(graph.node_indices().all(|n| graph.in_deg(n) <= 2));
+    }
+
+    #[test]
+    fn infinity_io() {
+        // an infinity chest feeds the left input of a splitter, another one takes its left output
+        let entities = load("tests/infinity_io");
+        let infinity = entities
+            .iter()
+            .filter(|e| e.get_base().throughput == UNBOUNDED_THROUGHPUT)
+            .map(|e| e.get_base().id)
+            .collect::<Vec<_>>();
+        assert_eq!(infinity, vec![1, 8]);
+
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], crate::ir::CoalesceStrength::Aggressive);
+        let io = graph
+            .node_indices()
+            .filter_map(|idx| match &graph[idx] {
+                Node::Input(i) => Some((i.id, true, graph.out_edges(idx)[0].capacity)),
+                Node::Output(o) => Some((o.id, false, graph.in_edges(idx)[0].capacity)),
+                _ => None,
+            })
+            .filter(|(id, _, _)| infinity.contains(id))
+            .collect::<Vec<_>>();
+        // the attached yellow belts limit the flow
+        assert_eq!(io, vec![(1, true, 15.into()), (8, false, 15.into())]);
+    }
 }
@@ -3,4 +3,4 @@
 mod compile_entities;
 mod compile_graph;
 
-pub use compile_graph::{Compiler, RelMap};
+pub use compile_graph::{Compiler, IoRule, RelMap, TileExplanation, TileRole};
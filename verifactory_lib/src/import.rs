@@ -1,35 +1,346 @@
 //! Utility functions to convert a Factorio blueprint string into a list of `FBEntity`s.
 //! A description of the JSON representation of the blueprint string can be found [here](https://wiki.factorio.com/Blueprint_string_format).
 
-use anyhow::{anyhow, Context, Result};
 use base64::engine::{general_purpose, Engine as _};
 use inflate::inflate_bytes_zlib;
-use serde::{de::Error, Deserialize, Deserializer};
-use serde_json::Value;
-use std::fs;
+use serde::{
+    de::{DeserializeSeed, Error, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::{json, Value};
+use std::{collections::HashMap, fmt, fs, io};
+use tracing::warn;
 
 use crate::{
     entities::*,
     utils::{Direction, Position, Rotation},
 };
 
+/// Reasons why a blueprint or a data dump could not be imported.
+///
+/// Converts into an `anyhow::Error` for callers that don't need to tell the failures apart.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The blueprint string is empty
+    Empty,
+    /// The file could not be read
+    Io(io::Error),
+    /// The blueprint string is not valid base64
+    Base64(base64::DecodeError),
+    /// The decoded bytes are not a valid zlib stream
+    Decompress(String),
+    /// The JSON is malformed or doesn't describe a blueprint
+    Json(serde_json::Error),
+    /// The blueprint has more than `limit` entities
+    TooLarge { count: usize, limit: usize },
+    /// Two distinct entities occupy the same tile
+    Overlap {
+        first: EntityId,
+        second: EntityId,
+        x: i32,
+        y: i32,
+    },
+    /// None of the entities of the blueprint are supported, `name` is the first of them
+    UnsupportedEntity { name: String },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty blueprint string"),
+            Self::Io(e) => write!(f, "could not read file: {}", e),
+            Self::Base64(e) => write!(f, "blueprint string is not valid base64: {}", e),
+            Self::Decompress(e) => write!(f, "could not decompress blueprint string: {}", e),
+            Self::Json(e) => write!(f, "malformed blueprint JSON: {}", e),
+            Self::TooLarge { count, limit } => write!(
+                f,
+                "blueprint too large: {} entities, limit {}",
+                count, limit
+            ),
+            Self::Overlap {
+                first,
+                second,
+                x,
+                y,
+            } => write!(
+                f,
+                "entities {} and {} overlap at ({}, {})",
+                first, second, x, y
+            ),
+            Self::UnsupportedEntity { name } => write!(f, "unsupported entity: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Base64(e) => Some(e),
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<base64::DecodeError> for ImportError {
+    fn from(e: base64::DecodeError) -> Self {
+        Self::Base64(e)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+type Result<T, E = ImportError> = std::result::Result<T, E>;
+
+/// Decompresses the string into the bytes of the JSON it encodes.
+fn decompress_bytes(blueprint_string: &str) -> Result<Vec<u8>> {
+    /* the first byte is the version of the format */
+    let Some(encoded) = blueprint_string.as_bytes().get(1..) else {
+        return Err(ImportError::Empty);
+    };
+    let base64_decoded = general_purpose::STANDARD.decode(encoded)?;
+    inflate_bytes_zlib(&base64_decoded).map_err(ImportError::Decompress)
+}
+
 /// Decompresses the string such that it can be interpreted as a JSON.
-fn decompress_string(blueprint_string: &str) -> Result<Value> {
-    let skip_first_byte = &blueprint_string.as_bytes()[1..blueprint_string.len()];
-    let base64_decoded = general_purpose::STANDARD.decode(skip_first_byte)?;
-    let decoded = inflate_bytes_zlib(&base64_decoded).map_err(|s| anyhow!(s))?;
-    Ok(serde_json::from_slice(&decoded)?)
+///
+/// This materializes the whole JSON tree, so it is meant for reading metadata like the label.
+/// The entities are streamed by [`string_to_entities`] instead.
+pub fn decompress_string(blueprint_string: &str) -> Result<Value> {
+    Ok(serde_json::from_slice(&decompress_bytes(
+        blueprint_string,
+    )?)?)
+}
+
+/// Deserializes the value of the key `field` of a JSON object using `inner`, skipping all other keys.
+struct FieldSeed<S> {
+    field: &'static str,
+    parent: &'static str,
+    inner: S,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for FieldSeed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> Visitor<'de> for FieldSeed<S> {
+    type Value = S::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an object with a {} key", self.field)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut inner = Some(self.inner);
+        let mut value = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match inner.take() {
+                Some(seed) if key == self.field => value = Some(map.next_value_seed(seed)?),
+                seed => {
+                    inner = seed;
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        value.ok_or_else(|| A::Error::custom(format!("No {} key in {}", self.field, self.parent)))
+    }
+}
+
+/// Streams the entities array, deserializing one entity at a time.
+///
+/// Unsupported entities are skipped, returning the name of the first one, and only the first `max_entities`
+/// entities are deserialized, the rest is only counted. The entities listed in the `dump` are deserialized according to it, see [`DataDump`].
+struct EntitiesSeed<'a> {
+    max_entities: usize,
+    dump: Option<&'a DataDump>,
+}
+
+impl<'de> DeserializeSeed<'de> for EntitiesSeed<'_> {
+    type Value = (Vec<FBEntity<f64>>, usize, Option<String>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitiesSeed<'_> {
+    type Value = (Vec<FBEntity<f64>>, usize, Option<String>);
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an array of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut entities = vec![];
+        let mut count = 0;
+        let mut unsupported = None;
+        while let Some(mut value) = seq.next_element::<Value>()? {
+            count += 1;
+            if count > self.max_entities {
+                /* keep counting to report the size of the blueprint */
+                while seq.next_element::<IgnoredAny>()?.is_some() {
+                    count += 1;
+                }
+                break;
+            }
+            let dumped = self.dump.and_then(|dump| {
+                let name = value.get("name")?.as_str()?;
+                dump.entities.get(name).copied()
+            });
+            if let Some(dumped) = dumped {
+                /* deserialize as the vanilla entity of the same type, so that the name heuristic can't mistake it */
+                value["name"] = Value::from(dumped.kind.vanilla_name());
+            }
+            let name = value.get("name").and_then(Value::as_str).map(str::to_owned);
+            match serde_json::from_value::<FBEntity<f64>>(value) {
+                Ok(mut entity) => {
+                    if let Some(dumped) = dumped {
+                        entity.get_base_mut().throughput = dumped.throughput;
+                    }
+                    entities.push(entity);
+                }
+                Err(e) => {
+                    warn!("Skipping unsupported entity: {}", e);
+                    unsupported = unsupported.or(name);
+                }
+            }
+        }
+        Ok((entities, count, unsupported))
+    }
+}
+
+/// Deserializes the entities of the blueprint without materializing the whole JSON tree.
+///
+/// Returns an error if none of the entities are supported.
+fn stream_entities(
+    json: &[u8],
+    max_entities: usize,
+    dump: Option<&DataDump>,
+) -> Result<Vec<FBEntity<f64>>> {
+    let entities = FieldSeed {
+        field: "entities",
+        parent: "blueprint",
+        inner: EntitiesSeed { max_entities, dump },
+    };
+    let blueprint = FieldSeed {
+        field: "blueprint",
+        parent: "json",
+        inner: entities,
+    };
+    let mut deserializer = serde_json::Deserializer::from_slice(json);
+    let (entities, count, unsupported) = blueprint.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    check_size(count, max_entities)?;
+    match unsupported {
+        Some(name) if entities.is_empty() => Err(ImportError::UnsupportedEntity { name }),
+        _ => Ok(entities),
+    }
+}
+
+/// Prototype types of a [`DataDump`] that are imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpedKind {
+    Belt,
+    Underground,
+    Splitter,
+}
+
+impl DumpedKind {
+    /// Name of the vanilla entity of this type
+    fn vanilla_name(&self) -> &'static str {
+        match self {
+            Self::Belt => "transport-belt",
+            Self::Underground => "underground-belt",
+            Self::Splitter => "splitter",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DumpedEntity {
+    kind: DumpedKind,
+    throughput: f64,
+    max_distance: Option<i32>,
+}
+
+/// Prototype of a belt, underground belt or splitter in `data.raw`, only keeping the fields that are needed
+#[derive(Deserialize)]
+struct Prototype {
+    speed: f64,
+    max_distance: Option<i32>,
 }
 
-/// Turns a JSON string into a list of JSON substrings, each representing an entity of the blueprint.
-fn get_json_entities(json: Value) -> Result<Vec<Value>> {
-    json.get("blueprint")
-        .context("No blueprint key in json")?
-        .get("entities")
-        .context("No entities key in blueprint")?
-        .as_array()
-        .context("Entities are not an array")
-        .map(|v| v.to_owned())
+/// Belt entities exported from the data stage of the game, e.g. using `factorio --dump-data`.
+///
+/// Without a dump the type and throughput of an entity are derived from its name, which only works for
+/// the vanilla entities and mods following their naming. With a dump, the entities listed in it are
+/// imported with the throughput and underground reach of their prototype instead, so any mod set is supported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataDump {
+    entities: HashMap<String, DumpedEntity>,
+}
+
+impl DataDump {
+    /// Items/s carried by a belt moving one tile per tick, the `speed` of the prototypes is in tiles/tick
+    const ITEMS_PER_SPEED: f64 = 480.0;
+
+    /// Parses the `data.raw` JSON dumped by the game.
+    ///
+    /// Only the `transport-belt`, `underground-belt` and `splitter` prototypes are read, all other types are ignored.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut raw: HashMap<String, Value> = serde_json::from_str(json)?;
+        let mut entities = HashMap::new();
+        for (prototype_type, kind) in [
+            ("transport-belt", DumpedKind::Belt),
+            ("underground-belt", DumpedKind::Underground),
+            ("splitter", DumpedKind::Splitter),
+        ] {
+            let Some(prototypes) = raw.remove(prototype_type) else {
+                continue;
+            };
+            let prototypes: HashMap<String, Prototype> = serde_json::from_value(prototypes)?;
+            for (name, prototype) in prototypes {
+                let dumped = DumpedEntity {
+                    kind,
+                    throughput: prototype.speed * Self::ITEMS_PER_SPEED,
+                    max_distance: prototype.max_distance,
+                };
+                entities.insert(name, dumped);
+            }
+        }
+        Ok(Self { entities })
+    }
+
+    /// Reads a `data.raw` dump from a file, see [`DataDump::from_json`].
+    pub fn from_file(file: &str) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(file)?)
+    }
+
+    /// Returns the vanilla underground reach extended by the reach of the underground belts in the dump.
+    pub fn underground_reach(&self) -> UndergroundReach {
+        self.entities
+            .values()
+            .filter_map(|e| Some((e.throughput, e.max_distance?)))
+            .fold(
+                UndergroundReach::default(),
+                |reach, (throughput, distance)| reach.with_tier(throughput, distance),
+            )
+    }
 }
 
 /// Helper function that deserializes the attributes shared by each entity.
@@ -55,11 +366,16 @@ impl<'de> Deserialize<'de> for FBBaseEntity<f64> {
             .and_then(|v| serde_json::from_value(v.clone()).ok())
             .unwrap_or(Direction::North);
 
+        let quality = value
+            .get("quality")
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+
         let base = FBBaseEntity {
             id,
             position,
             direction,
             throughput: 0.0,
+            quality,
         };
         Ok(base)
     }
@@ -80,7 +396,9 @@ impl<'de> Deserialize<'de> for FBEntity<f64> {
 
         let mut base: FBBaseEntity<f64> = serde_json::from_value(value.clone())
             .map_err(|_| Error::custom("Could not deserialize BaseEntity"))?;
-        base.throughput = if name.contains("express") {
+        base.throughput = if name.contains("turbo") {
+            60.0
+        } else if name.contains("express") {
             45.0
         } else if name.contains("fast") {
             30.0
@@ -88,7 +406,11 @@ impl<'de> Deserialize<'de> for FBEntity<f64> {
             15.0
         };
 
-        if name.contains("transport-belt") {
+        if name == INFINITY_CHEST {
+            /* test infrastructure of the map editor, modelled as a belt carrying any amount of items */
+            base.throughput = UNBOUNDED_THROUGHPUT;
+            Ok(Self::Belt(FBBelt { base }))
+        } else if name.contains("transport-belt") {
             Ok(Self::Belt(FBBelt { base }))
         } else if name.contains("underground-belt") {
             let belt_type = value
@@ -114,6 +436,8 @@ impl<'de> Deserialize<'de> for FBEntity<f64> {
                 output_prio,
             }))
         } else if name.contains("inserter") {
+            /* Factorio stores the direction of the pickup, but inserters move items the opposite way */
+            base.direction = base.direction.flip();
             if name.contains("long-handed") {
                 base.throughput = 1.2;
                 return Ok(Self::LongInserter(FBLongInserter { base }));
@@ -137,7 +461,7 @@ impl<'de> Deserialize<'de> for FBEntity<f64> {
                 "2" => 0.75,
                 "3" => 1.25,
                 _ => panic!(),
-            };
+            } * base.quality.map_or(1.0, |q| q.crafting_speed_multiplier());
             Ok(Self::Assembler(FBAssembler { base }))
         } else {
             Err(format!("Invalid entity: ({})", name)).map_err(serde::de::Error::custom)
@@ -149,29 +473,16 @@ impl<'de> Deserialize<'de> for FBEntity<f64> {
 /// This function snaps these coordinates to an integer coordinate system.
 fn snap_to_grid(entities: &mut [FBEntity<f64>]) {
     for e in entities {
-        match e {
-            /* snap splitters to the grid as they are offset by 0.5 */
-            FBEntity::Splitter(splitter) => {
-                let shift_dir = splitter.base.direction.rotate(Rotation::Anticlockwise, 1);
-                /* in Factorio blueprints the y-axis is inverted */
-                let shift_dir = match shift_dir {
-                    Direction::East => Direction::West,
-                    Direction::West => Direction::East,
-                    x => x,
-                };
-                splitter.base.shift(shift_dir, 0.5);
-            }
-            /* flip direction of inserters */
-            FBEntity::Inserter(inserter) => {
-                let dir = inserter.base.direction;
-                inserter.base.direction = dir.flip();
-            }
-            /* flip direction of long inserters */
-            FBEntity::LongInserter(inserter) => {
-                let dir = inserter.base.direction;
-                inserter.base.direction = dir.flip();
-            }
-            _ => (),
+        /* snap splitters to the grid as they are offset by 0.5 */
+        if let FBEntity::Splitter(splitter) = e {
+            let shift_dir = splitter.base.direction.rotate(Rotation::Anticlockwise, 1);
+            /* in Factorio blueprints the y-axis is inverted */
+            let shift_dir = match shift_dir {
+                Direction::East => Direction::West,
+                Direction::West => Direction::East,
+                x => x,
+            };
+            splitter.base.shift(shift_dir, 0.5);
         }
     }
 }
@@ -196,15 +507,17 @@ fn normalize_entities(entities: &[FBEntity<f64>]) -> Vec<FBEntity<i32>> {
         .iter()
         .map(|e| {
             let base = e.get_base();
-            let x = (base.position.x - min_x) as i32;
+            /* round instead of truncating, so that float errors can't move an entity to the previous tile */
+            let x = (base.position.x - min_x).round() as i32;
             /* uninvert the y-axis */
-            let y = (max_y - base.position.y) as i32;
+            let y = (max_y - base.position.y).round() as i32;
             let position = Position { x, y };
             let base = FBBaseEntity {
                 position,
                 id: base.id,
                 direction: base.direction,
                 throughput: base.throughput,
+                quality: base.quality,
             };
             match e {
                 FBEntity::Belt(_) => FBEntity::Belt(FBBelt { base }),
@@ -231,41 +544,141 @@ fn normalize_entities(entities: &[FBEntity<f64>]) -> Vec<FBEntity<i32>> {
         .collect()
 }
 
+/// Returns an error if two distinct entities occupy the same tile.
+///
+/// The phantoms of an entity share its id, so they only collide with other entities.
+fn check_collisions(entities: &[FBEntity<i32>]) -> Result<()> {
+    let mut occupied = HashMap::new();
+    for e in entities {
+        let base = e.get_base();
+        if let Some(other) = occupied.insert(base.position, base.id) {
+            if other != base.id {
+                return Err(ImportError::Overlap {
+                    first: other,
+                    second: base.id,
+                    x: base.position.x,
+                    y: base.position.y,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Default maximum number of entities of a blueprint, see [`string_to_entities_with_limit`].
+pub const DEFAULT_MAX_ENTITIES: usize = 5000;
+
+/// Returns an error if the blueprint has more than `max_entities` entities.
+fn check_size(count: usize, max_entities: usize) -> Result<()> {
+    if count > max_entities {
+        return Err(ImportError::TooLarge {
+            count,
+            limit: max_entities,
+        });
+    }
+    Ok(())
+}
+
 /// Parses a blueprint string, as exported from Factorio, to a list of `FBEntity`s
 ///
-/// Unsupported entities, like power poles, are skipped.
+/// Unsupported entities, like power poles, are skipped, but blueprints made only of unsupported entities are rejected.
+/// Blueprints with overlapping entities are rejected.
+/// Blueprints with more than [`DEFAULT_MAX_ENTITIES`] entities are rejected.
 pub fn string_to_entities(blueprint_string: &str) -> Result<Vec<FBEntity<i32>>> {
-    let json = decompress_string(blueprint_string)?;
-    let mut entities: Vec<_> = get_json_entities(json)?
-        .into_iter()
-        .flat_map(serde_json::from_value)
-        .collect::<Vec<_>>();
+    string_to_entities_with_limit(blueprint_string, DEFAULT_MAX_ENTITIES)
+}
+
+/// Same as [`string_to_entities`] but rejects blueprints with more than `max_entities` entities.
+///
+/// The limit protects against blueprints that are too large to be compiled and proven in reasonable time.
+pub fn string_to_entities_with_limit(
+    blueprint_string: &str,
+    max_entities: usize,
+) -> Result<Vec<FBEntity<i32>>> {
+    parse_entities(blueprint_string, max_entities, None)
+}
+
+/// Same as [`string_to_entities`] but imports the entities listed in the `dump` according to it, see [`DataDump`].
+pub fn string_to_entities_with_dump(
+    blueprint_string: &str,
+    dump: &DataDump,
+) -> Result<Vec<FBEntity<i32>>> {
+    parse_entities(blueprint_string, DEFAULT_MAX_ENTITIES, Some(dump))
+}
+
+fn parse_entities(
+    blueprint_string: &str,
+    max_entities: usize,
+    dump: Option<&DataDump>,
+) -> Result<Vec<FBEntity<i32>>> {
+    let json = decompress_bytes(blueprint_string)?;
+    entities_from_json(&json, max_entities, dump)
+}
+
+/// Deserializes, snaps and normalizes the entities of the JSON of a blueprint.
+fn entities_from_json(
+    json: &[u8],
+    max_entities: usize,
+    dump: Option<&DataDump>,
+) -> Result<Vec<FBEntity<i32>>> {
+    let mut entities = stream_entities(json, max_entities, dump)?;
 
     snap_to_grid(&mut entities);
     let mut entities = normalize_entities(&entities);
 
-    // add splitter phantoms
-    let phantoms = entities
-        .iter()
-        .filter_map(|&e| match e {
-            FBEntity::Splitter(s) => Some(FBEntity::SplitterPhantom(s.get_phantom())),
-            _ => None,
-        })
-        .collect::<Vec<_>>();
-    entities.extend(phantoms);
+    add_phantoms(&mut entities);
+    check_collisions(&entities)?;
+    Ok(entities)
+}
 
-    // add assembler phantoms
-    let phantoms = entities
-        .iter()
-        .filter_map(|&e| match e {
-            FBEntity::Assembler(a) => Some(a.get_phantoms()),
-            _ => None,
+/// Parses a blueprint book string to the label and the entities of each blueprint in it, in the order of the book.
+///
+/// Nested books are flattened and other items, like deconstruction planners, are skipped.
+/// A blueprint string that is not a book is returned as a book with a single blueprint.
+/// Blueprints without a label are labelled by their position in the flattened book, e.g. `#2`.
+pub fn string_to_book(blueprint_string: &str) -> Result<Vec<(String, Vec<FBEntity<i32>>)>> {
+    let json = decompress_string(blueprint_string)?;
+    let mut blueprints = vec![];
+    if !collect_blueprints(&json, &mut blueprints) {
+        return Err(serde_json::Error::custom("No blueprint or blueprint_book key in json").into());
+    }
+    blueprints
+        .into_iter()
+        .map(|(label, blueprint)| {
+            let json = serde_json::to_vec(&json!({ "blueprint": blueprint }))?;
+            Ok((
+                label,
+                entities_from_json(&json, DEFAULT_MAX_ENTITIES, None)?,
+            ))
         })
-        .flatten()
-        .map(FBEntity::AssemblerPhantom)
-        .collect::<Vec<_>>();
-    entities.extend(phantoms);
-    Ok(entities)
+        .collect()
+}
+
+/// Appends the blueprints of `json`, which is either a blueprint or a book, to `blueprints`.
+///
+/// Returns `false` if `json` is neither.
+fn collect_blueprints<'a>(json: &'a Value, blueprints: &mut Vec<(String, &'a Value)>) -> bool {
+    if let Some(blueprint) = json.get("blueprint") {
+        let label = blueprint
+            .get("label")
+            .and_then(Value::as_str)
+            .map_or_else(|| format!("#{}", blueprints.len() + 1), str::to_owned);
+        blueprints.push((label, blueprint));
+        true
+    } else if let Some(book) = json.get("blueprint_book") {
+        let mut children = book
+            .get("blueprints")
+            .and_then(Value::as_array)
+            .map(|c| c.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        children.sort_by_key(|c| c.get("index").and_then(Value::as_u64));
+        for child in children {
+            collect_blueprints(child, blueprints);
+        }
+        true
+    } else {
+        false
+    }
 }
 
 /// Parses a file containing a blueprint string to a list of `FBEntity`s.
@@ -279,12 +692,15 @@ pub fn file_to_entities(file: &str) -> Result<Vec<FBEntity<i32>>> {
 #[cfg(test)]
 mod tests {
     use crate::{
-        entities::{BeltType, Priority},
+        entities::{BeltType, Priority, Quality},
+        frontend::Compiler,
+        ir::FlowGraphAnalysis,
         utils::Direction,
     };
 
     use super::*;
-    use std::fs;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::{fs, io::Write};
     fn get_belt_entities() -> Vec<FBEntity<i32>> {
         let blueprint_string = fs::read_to_string("tests/belts").unwrap();
         string_to_entities(&blueprint_string).unwrap()
@@ -295,6 +711,108 @@ mod tests {
         string_to_entities(&blueprint_string).unwrap()
     }
 
+    #[test]
+    fn data_dump() {
+        let blueprint_string = fs::read_to_string("tests/modded_belts").unwrap();
+        // the names of the modded entities are unknown to the name heuristic
+        let err = string_to_entities(&blueprint_string).unwrap_err();
+        assert!(
+            matches!(&err, ImportError::UnsupportedEntity { name } if name.starts_with("ultimate-")),
+            "{:?}",
+            err
+        );
+
+        let dump = DataDump::from_file("tests/data_dump.json").unwrap();
+        let entities = string_to_entities_with_dump(&blueprint_string, &dump).unwrap();
+        assert_eq!(entities.len(), 4);
+        assert!(entities.iter().all(|e| e.get_base().throughput == 90.0));
+        let reach = dump.underground_reach();
+        assert_eq!(reach.max_distance(90.0), Some(13));
+        assert_eq!(reach.max_distance(15.0), Some(5));
+
+        // the underground belts are 13 tiles apart, so they only connect with the reach from the dump
+        let io = |reach: &UndergroundReach| {
+            Compiler::with_underground_reach(entities.clone(), reach)
+                .create_graph()
+                .io_dimensions()
+        };
+        assert_eq!(io(&reach), (1, 1));
+        assert_eq!(io(&UndergroundReach::default()), (2, 2));
+    }
+
+    /// The splitter has to end up on its right half and the phantom on its left half, in every orientation
+    #[test]
+    fn snap_splitter_orientations() {
+        // center of the splitter, center of its right half and of its left half in Factorio coordinates
+        let cases = [
+            (Direction::North, (0.0, 0.5), (0.5, 0.5), (-0.5, 0.5)),
+            (Direction::East, (0.5, 0.0), (0.5, 0.5), (0.5, -0.5)),
+            (Direction::South, (0.0, 0.5), (-0.5, 0.5), (0.5, 0.5)),
+            (Direction::West, (0.5, 0.0), (0.5, -0.5), (0.5, 0.5)),
+        ];
+        for (direction, center, right, left) in cases {
+            let base = |id, (x, y)| FBBaseEntity::new(id, Position { x, y }, direction, 15.0);
+            // belts mark the tiles the halves of the splitter should land on
+            let mut entities = vec![
+                FBEntity::Splitter(FBSplitter::new(
+                    base(1, center),
+                    Priority::None,
+                    Priority::None,
+                )),
+                FBEntity::Belt(FBBelt::new(base(2, right))),
+                FBEntity::Belt(FBBelt::new(base(3, left))),
+            ];
+            snap_to_grid(&mut entities);
+            let mut entities = normalize_entities(&entities);
+            add_phantoms(&mut entities);
+
+            let position = |f: fn(&FBEntity<i32>) -> bool| {
+                entities.iter().find(|e| f(e)).unwrap().get_base().position
+            };
+            let splitter = position(|e| matches!(e, FBEntity::Splitter(_)));
+            let phantom = position(|e| matches!(e, FBEntity::SplitterPhantom(_)));
+            let right = position(|e| matches!(e, FBEntity::Belt(b) if b.base.id == 2));
+            let left = position(|e| matches!(e, FBEntity::Belt(b) if b.base.id == 3));
+            assert_eq!((splitter, phantom), (right, left), "{:?}", direction);
+        }
+    }
+
+    #[test]
+    fn max_entities() {
+        let err = check_size(DEFAULT_MAX_ENTITIES + 1, DEFAULT_MAX_ENTITIES).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "blueprint too large: {} entities, limit {}",
+                DEFAULT_MAX_ENTITIES + 1,
+                DEFAULT_MAX_ENTITIES
+            )
+        );
+        assert!(check_size(DEFAULT_MAX_ENTITIES + 1, DEFAULT_MAX_ENTITIES + 1).is_ok());
+
+        let blueprint_string = fs::read_to_string("tests/4-4").unwrap();
+        let err = string_to_entities_with_limit(&blueprint_string, 5).unwrap_err();
+        assert!(err.to_string().starts_with("blueprint too large"));
+        assert!(string_to_entities(&blueprint_string).is_ok());
+    }
+
+    #[test]
+    fn splitter_belt_grid() {
+        let blueprint_string = fs::read_to_string("tests/splitter_belt_grid").unwrap();
+        let entities = string_to_entities(&blueprint_string).unwrap();
+        // 4 splitters with their phantoms and 6 belts around each of them
+        assert_eq!(entities.len(), 4 * 2 + 4 * 6);
+        let tiles = entities
+            .iter()
+            .map(|e| e.get_base().position)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(tiles.len(), entities.len());
+
+        let blueprint_string = fs::read_to_string("tests/overlapping_entities").unwrap();
+        let err = string_to_entities(&blueprint_string).unwrap_err();
+        assert!(err.to_string().contains("overlap"));
+    }
+
     #[test]
     fn throughput_tiers() {
         let entities = get_belt_entities();
@@ -307,6 +825,24 @@ mod tests {
         assert_eq!(throughput, [4, 5, 1]);
     }
 
+    #[test]
+    fn turbo_tier() {
+        for name in [
+            "turbo-transport-belt",
+            "turbo-underground-belt",
+            "turbo-splitter",
+        ] {
+            let json = serde_json::json!({
+                "entity_number": 1,
+                "name": name,
+                "position": { "x": 0.5, "y": 0.5 },
+                "type": "input",
+            });
+            let entity: FBEntity<f64> = serde_json::from_value(json).unwrap();
+            assert_eq!(entity.get_base().throughput, 60.0, "{}", name);
+        }
+    }
+
     #[test]
     fn belt_direction() {
         let entities = get_belt_entities();
@@ -388,4 +924,207 @@ mod tests {
         println!("{:?}", &entities);
         assert_eq!(entities.len(), 9 + 3);
     }
+
+    #[test]
+    fn quality() {
+        let entities = file_to_entities("tests/quality").unwrap();
+        let mut found = entities
+            .iter()
+            .filter(|e| !matches!(e, FBEntity::AssemblerPhantom(_)))
+            .map(|e| {
+                (
+                    e.get_base().id,
+                    e.get_base().quality,
+                    e.get_base().throughput,
+                )
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(id, _, _)| *id);
+        // quality does not change the speed of belts, but the crafting speed of assemblers
+        let expected = vec![
+            (1, Some(Quality::Legendary), 15.0),
+            (2, Some(Quality::Rare), 30.0),
+            (3, None, 45.0),
+            (4, Some(Quality::Legendary), 3.125),
+            (5, Some(Quality::Uncommon), 0.75 * 1.3),
+            (6, Some(Quality::Normal), 0.5),
+        ];
+        assert_eq!(found, expected);
+    }
+
+    /// The source of an inserter is where it picks up items in game, e.g. an inserter
+    /// facing north in the blueprint picks up from the north and drops to the south.
+    #[test]
+    fn inserter_directions() {
+        let entities = file_to_entities("tests/inserter_directions").unwrap();
+        let offsets = |pos: Position<i32>, source: Position<i32>, destination: Position<i32>| {
+            (
+                (source.x - pos.x, source.y - pos.y),
+                (destination.x - pos.x, destination.y - pos.y),
+            )
+        };
+        let mut found = entities
+            .iter()
+            .filter_map(|e| match e {
+                FBEntity::Inserter(i) => Some((
+                    i.base.id,
+                    offsets(i.base.position, i.get_source(), i.get_destination()),
+                )),
+                FBEntity::LongInserter(l) => Some((
+                    l.base.id,
+                    offsets(l.base.position, l.get_source(), l.get_destination()),
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        /* north is y+1 after uninverting the y-axis */
+        let expected = vec![
+            (1, ((0, 1), (0, -1))),
+            (2, ((1, 0), (-1, 0))),
+            (3, ((0, -1), (0, 1))),
+            (4, ((-1, 0), (1, 0))),
+            (5, ((0, 2), (0, -2))),
+        ];
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn stream_entities_matches_json() {
+        let blueprint_string = fs::read_to_string("tests/inserter_assembler").unwrap();
+        let json = decompress_string(&blueprint_string).unwrap();
+        let expected = json["blueprint"]["entities"].as_array().unwrap().len();
+
+        let bytes = decompress_bytes(&blueprint_string).unwrap();
+        let entities = stream_entities(&bytes, DEFAULT_MAX_ENTITIES, None).unwrap();
+        assert_eq!(entities.len(), expected);
+
+        let err = stream_entities(br#"{"label": "test"}"#, DEFAULT_MAX_ENTITIES, None).unwrap_err();
+        assert!(err.to_string().contains("No blueprint key in json"));
+    }
+
+    #[test]
+    fn import_errors() {
+        let encode = |json: &str| {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(json.as_bytes()).unwrap();
+            format!(
+                "0{}",
+                general_purpose::STANDARD.encode(encoder.finish().unwrap())
+            )
+        };
+
+        let err = string_to_entities("").unwrap_err();
+        assert!(matches!(err, ImportError::Empty), "{:?}", err);
+        let err = string_to_entities("0not base64!").unwrap_err();
+        assert!(matches!(err, ImportError::Base64(_)), "{:?}", err);
+        let err = string_to_entities("0aGVsbG8=").unwrap_err();
+        assert!(matches!(err, ImportError::Decompress(_)), "{:?}", err);
+        let err = string_to_entities(&encode("{\"blueprint\": [")).unwrap_err();
+        assert!(matches!(err, ImportError::Json(_)), "{:?}", err);
+        let err = string_to_entities(&encode(r#"{"blueprint_book": {}}"#)).unwrap_err();
+        assert!(matches!(err, ImportError::Json(_)), "{:?}", err);
+        let err = file_to_entities("tests/does_not_exist").unwrap_err();
+        assert!(matches!(err, ImportError::Io(_)), "{:?}", err);
+
+        let blueprint_string = fs::read_to_string("tests/4-4").unwrap();
+        let err = string_to_entities_with_limit(&blueprint_string, 5).unwrap_err();
+        assert!(
+            matches!(err, ImportError::TooLarge { limit: 5, .. }),
+            "{:?}",
+            err
+        );
+        let blueprint_string = fs::read_to_string("tests/overlapping_entities").unwrap();
+        let err = string_to_entities(&blueprint_string).unwrap_err();
+        assert!(matches!(err, ImportError::Overlap { .. }), "{:?}", err);
+        let err = string_to_entities(&encode(
+            r#"{"blueprint": {"entities": [{"entity_number": 1, "name": "small-electric-pole", "position": {"x": 0.5, "y": 0.5}}]}}"#,
+        ))
+        .unwrap_err();
+        assert!(
+            matches!(&err, ImportError::UnsupportedEntity { name } if name == "small-electric-pole"),
+            "{:?}",
+            err
+        );
+        /* supported entities are still imported next to unsupported ones */
+        let entities = string_to_entities(&encode(
+            r#"{"blueprint": {"entities": [
+                {"entity_number": 1, "name": "small-electric-pole", "position": {"x": 0.5, "y": 0.5}},
+                {"entity_number": 2, "name": "transport-belt", "position": {"x": 1.5, "y": 0.5}}
+            ]}}"#,
+        ))
+        .unwrap();
+        assert_eq!(entities.len(), 1);
+
+        /* still usable with anyhow */
+        let err: anyhow::Error = string_to_entities("").unwrap_err().into();
+        assert_eq!(err.to_string(), "empty blueprint string");
+    }
+
+    #[test]
+    fn book() {
+        let book = string_to_book(&fs::read_to_string("tests/book").unwrap()).unwrap();
+        let labels = book
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["4-4", "4-4 broken"]);
+        assert_eq!(
+            book[0].1.len(),
+            file_to_entities("tests/4-4").unwrap().len()
+        );
+
+        /* a single blueprint is a book of one */
+        let book = string_to_book(&fs::read_to_string("tests/contract").unwrap()).unwrap();
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn import_all_fixtures() {
+        /* files in `tests/` that are not blueprint strings */
+        const NOT_BLUEPRINTS: [&str; 4] = [
+            "tests/community/README.md",
+            "tests/data_dump.json",
+            "tests/entities_in_code.rs",
+            "tests/library/README.md",
+        ];
+        /* fixtures that are books, imported with `string_to_book` */
+        const BOOKS: [&str; 1] = ["tests/book"];
+        /* fixtures that must fail to import */
+        /* the modded belts are only supported with a data dump, see `data_dump` */
+        const UNSUPPORTED: [&str; 2] = ["tests/modded_belts", "tests/overlapping_entities"];
+
+        let mut dirs = vec![std::path::PathBuf::from("tests")];
+        let mut files = vec![];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    files.push(path.to_str().unwrap().to_owned());
+                }
+            }
+        }
+        files.sort();
+
+        let failures = files
+            .iter()
+            .filter(|file| !NOT_BLUEPRINTS.contains(&file.as_str()))
+            .filter_map(|file| {
+                let result = if BOOKS.contains(&file.as_str()) {
+                    string_to_book(&fs::read_to_string(file).unwrap()).map(|_| ())
+                } else {
+                    file_to_entities(file).map(|_| ())
+                };
+                match (result, UNSUPPORTED.contains(&file.as_str())) {
+                    (Err(err), false) => Some(format!("{}: {}", file, err)),
+                    (Ok(_), true) => Some(format!("{}: imported but listed as unsupported", file)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+        assert!(files.len() > NOT_BLUEPRINTS.len() + UNSUPPORTED.len());
+    }
 }
@@ -0,0 +1,877 @@
+//! Structural analyses on the [`FlowGraph`] that don't require a solver.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+use fraction::GenericFraction;
+use petgraph::{
+    algo::{tarjan_scc, toposort},
+    prelude::{EdgeIndex, NodeIndex},
+    unionfind::UnionFind,
+    visit::{Dfs, DfsPostOrder, EdgeRef, VisitMap},
+    Direction::Outgoing,
+};
+
+use crate::entities::EntityId;
+
+use super::{FlowGraph, GraphDiff, GraphHelper, Node};
+
+/// Error returned when a graph contains a cycle, but an acyclic one was required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleError {
+    /// A node that is part of a cycle
+    pub node: NodeIndex,
+}
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Graph contains a cycle at node {}", self.node.index())
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Trait exposing structural analyses of the IR graph
+pub trait FlowGraphAnalysis {
+    /// Returns the nodes of the graph in topological order.
+    ///
+    /// Returns a [`CycleError`] if the graph contains a cycle.
+    fn topo_order(&self) -> Result<Vec<NodeIndex>, CycleError>;
+
+    /// Returns the number of splitter stages of the graph,
+    /// i.e. the maximum number of splitter entities on a path from an input to an output.
+    ///
+    /// A splitter entity is modelled by a merger followed by a splitter, which count as a single stage.
+    ///
+    /// On a graph with cycles, the edges closing a cycle (the back edges of a depth-first search starting at the inputs)
+    /// are ignored, so every stage on a loop is only counted once.
+    fn splitter_depth(&self) -> usize;
+
+    /// Returns the number of edges for each capacity.
+    ///
+    /// On a simplified graph the capacities have been shrunk to their minimum,
+    /// so the lowest capacities show where the flow is constrained.
+    fn capacity_histogram(&self) -> BTreeMap<GenericFraction<u128>, usize>;
+
+    /// Returns the nodes of each weakly connected component of the graph.
+    ///
+    /// A blueprint containing multiple independent belt networks results in multiple components,
+    /// which can be analyzed separately using [`FlowGraphAnalysis::component_subgraph`].
+    /// The components are ordered by their lowest node index.
+    fn connected_components(&self) -> Vec<Vec<NodeIndex>>;
+
+    /// Returns the sub-graph only containing the given `nodes` and the edges between them.
+    fn component_subgraph(&self, nodes: &[NodeIndex]) -> FlowGraph;
+
+    /// Returns true if the graph contains no splitters or mergers, i.e. only chains of belts.
+    ///
+    /// No balancing happens in such a graph, so it is only a balancer if it has a single input.
+    fn is_trivial(&self) -> bool;
+
+    /// Returns the number of inputs and outputs of the graph.
+    fn io_dimensions(&self) -> (usize, usize);
+
+    /// Returns the number of splitter entities in the graph.
+    ///
+    /// Side-loading is modelled with mergers too, so only the splitters of the graph are counted.
+    fn splitter_count(&self) -> usize;
+
+    /// Returns the ratio between the [`min_splitters`] of the dimensions of the graph and its [`FlowGraphAnalysis::splitter_count`].
+    ///
+    /// A design using the minimum number of splitters has an efficiency of 1.
+    /// As the minimum is only a lower bound, even the best design might not reach it.
+    fn splitter_efficiency(&self) -> f64 {
+        let (inputs, outputs) = self.io_dimensions();
+        match self.splitter_count() {
+            0 => 1.0,
+            count => min_splitters(inputs, outputs) as f64 / count as f64,
+        }
+    }
+
+    /// Returns the `(input, output)` pairs of entity ids where the input can not reach the output.
+    ///
+    /// A balancer has to distribute every input over all outputs, so any such pair rules it out.
+    fn unreachable_io_pairs(&self) -> Vec<(EntityId, EntityId)>;
+
+    /// Returns true if every input can reach every output.
+    ///
+    /// This is a cheap structural check that catches obvious non-balancers without invoking a solver.
+    fn is_fully_connected_io(&self) -> bool {
+        self.unreachable_io_pairs().is_empty()
+    }
+
+    /// Returns for each output, identified by its entity id, the number of inputs that can reach it.
+    ///
+    /// An output with a lower fan-in than the others can not receive items from every input,
+    /// a structural hint that the design does not balance.
+    fn output_fanin(&self) -> HashMap<EntityId, usize>;
+
+    /// Returns the maximum throughput from all the inputs to all the outputs, ignoring splitter priorities and balancing.
+    ///
+    /// By the max-flow min-cut theorem this is also the total capacity of the [`FlowGraphAnalysis::critical_edges`].
+    fn min_cut_value(&self) -> GenericFraction<u128>;
+
+    /// Returns the edges of a minimum cut between the inputs and the outputs, i.e. the bottleneck of the graph.
+    ///
+    /// These are the edges to upgrade first to increase the throughput: the total throughput
+    /// can not exceed the sum of their capacities, see [`FlowGraphAnalysis::min_cut_value`].
+    /// If there are multiple minimum cuts, the one closest to the inputs is returned.
+    fn critical_edges(&self) -> Vec<EdgeIndex>;
+
+    /// Returns the structural differences from this graph to `other`, see [`GraphDiff`].
+    ///
+    /// Useful to see how a change to the compiler or to the simplification affects the resulting graph.
+    fn diff(&self, other: &FlowGraph) -> GraphDiff;
+
+    /// Returns the skeleton of the graph: for each splitter or merger entity, the other splitter or merger
+    /// entities it feeds, following the belts in between.
+    ///
+    /// Only splitters and mergers are kept, so the skeleton is the same for the raw and the simplified graph.
+    /// The entries and their neighbours are sorted by entity id.
+    fn structural_adjacency(&self) -> Vec<(EntityId, Vec<EntityId>)>;
+
+    /// Returns a canonical fingerprint of the skeleton of the graph, independent of entity ids, positions and belt tiers.
+    ///
+    /// Graphs with the same arrangement of inputs, outputs, splitters and mergers, following the belts in between,
+    /// have the same fingerprint, so it can be used to deduplicate designs or to look them up with [`known_design`].
+    /// The fingerprint starts with the dimensions, e.g. `4-4:`, followed by a hash from a Weisfeiler-Lehman refinement.
+    /// Different designs may collide, although this is unlikely.
+    fn fingerprint(&self) -> String;
+
+    /// Returns the edges of each cycle that can never start carrying items when the design starts out empty.
+    ///
+    /// Items only enter a cycle through an edge from outside of it. A cycle that no input can reach is a circular wait:
+    /// every edge of it waits for items from another, e.g. an assembler whose only ingredients come from a belt
+    /// that is filled by the assembler itself. Everything downstream of such a cycle never gets any items either.
+    ///
+    /// This is a structural heuristic and not a temporal proof: a cycle reachable from an input may still deadlock
+    /// once it has filled up, see [`no_deadlock_f`](crate::backends::no_deadlock_f).
+    /// The edges of each cycle are sorted, the cycles are ordered by their lowest edge index.
+    fn startup_deadlocks(&self) -> Vec<Vec<EdgeIndex>>;
+}
+
+impl FlowGraphAnalysis for FlowGraph {
+    fn topo_order(&self) -> Result<Vec<NodeIndex>, CycleError> {
+        toposort(self, None).map_err(|c| CycleError { node: c.node_id() })
+    }
+
+    fn splitter_depth(&self) -> usize {
+        // reverse post-order of a DFS from the inputs, edges going backwards in it close a cycle
+        let starts = self
+            .node_indices()
+            .filter(|idx| matches!(self[*idx], Node::Input(_)))
+            .chain(self.node_indices());
+        let mut dfs = DfsPostOrder::empty(self);
+        let mut order = vec![];
+        for start in starts {
+            if dfs.discovered.is_visited(&start) {
+                continue;
+            }
+            dfs.move_to(start);
+            while let Some(idx) = dfs.next(self) {
+                order.push(idx);
+            }
+        }
+        order.reverse();
+        let mut position = vec![0; self.node_count()];
+        for (pos, idx) in order.iter().enumerate() {
+            position[idx.index()] = pos;
+        }
+
+        let mut depth = vec![0; self.node_count()];
+        for idx in order {
+            let in_nodes = self
+                .in_nodes(idx)
+                .into_iter()
+                .filter(|n| position[n.index()] < position[idx.index()])
+                .collect::<Vec<_>>();
+            let stage = match &self[idx] {
+                Node::Merger(_) => 1,
+                // the splitter part of a splitter entity that also merges was already counted
+                Node::Splitter(s) => in_nodes
+                    .iter()
+                    .all(|n| !matches!(&self[*n], Node::Merger(m) if m.id == s.id))
+                    as usize,
+                _ => 0,
+            };
+            depth[idx.index()] =
+                in_nodes.iter().map(|n| depth[n.index()]).max().unwrap_or(0) + stage;
+        }
+        depth.into_iter().max().unwrap_or(0)
+    }
+
+    fn capacity_histogram(&self) -> BTreeMap<GenericFraction<u128>, usize> {
+        let mut histogram = BTreeMap::new();
+        for edge in self.edge_weights() {
+            *histogram.entry(edge.capacity).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    fn connected_components(&self) -> Vec<Vec<NodeIndex>> {
+        let mut union_find = UnionFind::new(self.node_count());
+        for edge in self.edge_references() {
+            union_find.union(edge.source().index(), edge.target().index());
+        }
+        let mut components: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+        for idx in self.node_indices() {
+            components
+                .entry(union_find.find(idx.index()))
+                .or_default()
+                .push(idx);
+        }
+        let mut components = components.into_values().collect::<Vec<_>>();
+        components.sort_by_key(|c| c[0]);
+        components
+    }
+
+    fn component_subgraph(&self, nodes: &[NodeIndex]) -> FlowGraph {
+        self.filter_map(
+            |idx, node| nodes.contains(&idx).then(|| node.clone()),
+            |_, edge| Some(*edge),
+        )
+    }
+
+    fn is_trivial(&self) -> bool {
+        self.node_weights()
+            .all(|n| !matches!(n, Node::Splitter(_) | Node::Merger(_)))
+    }
+
+    fn io_dimensions(&self) -> (usize, usize) {
+        let count = |kind: fn(&Node) -> bool| self.node_weights().filter(|n| kind(n)).count();
+        (
+            count(|n| matches!(n, Node::Input(_))),
+            count(|n| matches!(n, Node::Output(_))),
+        )
+    }
+
+    fn splitter_count(&self) -> usize {
+        self.node_weights()
+            .filter_map(|n| match n {
+                Node::Splitter(s) => Some(s.id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    fn unreachable_io_pairs(&self) -> Vec<(EntityId, EntityId)> {
+        let select = |kind: fn(&Node) -> bool| {
+            self.node_indices()
+                .filter(|idx| kind(&self[*idx]))
+                .collect::<Vec<_>>()
+        };
+        let inputs = select(|n| matches!(n, Node::Input(_)));
+        let outputs = select(|n| matches!(n, Node::Output(_)));
+
+        let mut pairs = vec![];
+        for input in inputs {
+            let mut reached = vec![false; self.node_count()];
+            let mut dfs = Dfs::new(self, input);
+            while let Some(idx) = dfs.next(self) {
+                reached[idx.index()] = true;
+            }
+            pairs.extend(
+                outputs
+                    .iter()
+                    .filter(|output| !reached[output.index()])
+                    .map(|output| (self[input].get_id(), self[*output].get_id())),
+            );
+        }
+        pairs
+    }
+
+    fn output_fanin(&self) -> HashMap<EntityId, usize> {
+        let mut fanin = self
+            .node_weights()
+            .filter(|n| matches!(n, Node::Output(_)))
+            .map(|n| (n.get_id(), HashSet::new()))
+            .collect::<HashMap<_, _>>();
+        for input in self
+            .node_indices()
+            .filter(|idx| matches!(self[*idx], Node::Input(_)))
+        {
+            let mut dfs = Dfs::new(self, input);
+            while let Some(idx) = dfs.next(self) {
+                if let Node::Output(o) = &self[idx] {
+                    fanin.get_mut(&o.id).unwrap().insert(self[input].get_id());
+                }
+            }
+        }
+        fanin
+            .into_iter()
+            .map(|(id, inputs)| (id, inputs.len()))
+            .collect()
+    }
+
+    fn min_cut_value(&self) -> GenericFraction<u128> {
+        MinCut::new(self).value
+    }
+
+    fn critical_edges(&self) -> Vec<EdgeIndex> {
+        MinCut::new(self).edges
+    }
+
+    fn diff(&self, other: &FlowGraph) -> GraphDiff {
+        GraphDiff::new(self, other)
+    }
+
+    fn startup_deadlocks(&self) -> Vec<Vec<EdgeIndex>> {
+        let mut supplied = HashSet::new();
+        let mut dfs = Dfs::empty(self);
+        dfs.stack.extend(
+            self.node_indices()
+                .filter(|idx| matches!(self[*idx], Node::Input(_))),
+        );
+        while let Some(idx) = dfs.next(self) {
+            supplied.insert(idx);
+        }
+
+        /* a strongly connected component is either supplied as a whole or not at all */
+        let mut deadlocks = tarjan_scc(self)
+            .into_iter()
+            .filter(|scc| !supplied.contains(&scc[0]))
+            .map(|scc| {
+                let mut edges = self
+                    .edge_references()
+                    .filter(|e| scc.contains(&e.source()) && scc.contains(&e.target()))
+                    .map(|e| e.id())
+                    .collect::<Vec<_>>();
+                edges.sort();
+                edges
+            })
+            .filter(|edges| !edges.is_empty())
+            .collect::<Vec<_>>();
+        deadlocks.sort();
+        deadlocks
+    }
+
+    fn structural_adjacency(&self) -> Vec<(EntityId, Vec<EntityId>)> {
+        let is_skeleton = |n: &Node| matches!(n, Node::Splitter(_) | Node::Merger(_));
+        let mut adjacency: BTreeMap<EntityId, BTreeSet<EntityId>> = BTreeMap::new();
+        for idx in self.node_indices().filter(|idx| is_skeleton(&self[*idx])) {
+            let id = self[idx].get_id();
+            let neighbours = adjacency.entry(id).or_default();
+            /* walk along the connectors until reaching the next splitters or mergers */
+            let mut visited = HashSet::new();
+            let mut stack = self.out_nodes(idx);
+            while let Some(next) = stack.pop() {
+                if !visited.insert(next) {
+                    continue;
+                }
+                match &self[next] {
+                    Node::Connector(_) => stack.extend(self.out_nodes(next)),
+                    /* the merger and the splitter of a splitter entity are a single node of the skeleton */
+                    n if is_skeleton(n) && n.get_id() != id => {
+                        neighbours.insert(n.get_id());
+                    }
+                    _ => (),
+                }
+            }
+        }
+        adjacency
+            .into_iter()
+            .map(|(id, neighbours)| (id, neighbours.into_iter().collect()))
+            .collect()
+    }
+
+    fn fingerprint(&self) -> String {
+        let skeleton = self
+            .node_indices()
+            .filter(|idx| !matches!(self[*idx], Node::Connector(_)))
+            .collect::<Vec<_>>();
+        /* adjacency of the skeleton, with the edges labelled by whether they leave a splitter on its priority side */
+        let mut incoming = HashMap::<_, Vec<_>>::new();
+        let mut outgoing = HashMap::<_, Vec<_>>::new();
+        for idx in &skeleton {
+            for edge in self.edges_directed(*idx, Outgoing) {
+                let label = match &self[*idx] {
+                    Node::Splitter(s) if !s.output_priority.is_none() => {
+                        1 + u64::from(edge.weight().side == s.output_priority)
+                    }
+                    _ => 0,
+                };
+                let mut next = edge.target();
+                let mut visited = HashSet::new();
+                while matches!(self[next], Node::Connector(_)) && visited.insert(next) {
+                    match self.out_nodes(next).first() {
+                        Some(out) => next = *out,
+                        None => break,
+                    }
+                }
+                if !matches!(self[next], Node::Connector(_)) {
+                    incoming.entry(next).or_default().push((*idx, label));
+                    outgoing.entry(*idx).or_default().push((next, label));
+                }
+            }
+        }
+
+        let mut colors = skeleton
+            .iter()
+            .map(|idx| {
+                let kind = match &self[*idx] {
+                    Node::Input(_) => 0,
+                    Node::Output(_) => 1,
+                    Node::Merger(_) => 2,
+                    Node::Splitter(s) if s.output_priority.is_none() => 3,
+                    Node::Splitter(_) => 4,
+                    Node::Connector(_) => unreachable!(),
+                };
+                (*idx, fnv_hash([kind]))
+            })
+            .collect::<HashMap<_, _>>();
+        /* refine until the colour classes are stable, each round splits at least one class or ends the refinement */
+        let class_count = |colors: &HashMap<_, u64>| colors.values().collect::<HashSet<_>>().len();
+        let mut classes = class_count(&colors);
+        loop {
+            let neighbour_colors = |adjacency: &HashMap<_, Vec<_>>, idx| {
+                let mut neighbours = adjacency
+                    .get(idx)
+                    .into_iter()
+                    .flatten()
+                    .map(|(other, label)| fnv_hash([colors[other], *label]))
+                    .collect::<Vec<_>>();
+                neighbours.sort();
+                neighbours
+            };
+            let refined = skeleton
+                .iter()
+                .map(|idx| {
+                    let incoming = neighbour_colors(&incoming, idx);
+                    let outgoing = neighbour_colors(&outgoing, idx);
+                    let color = fnv_hash(
+                        [colors[idx], incoming.len() as u64]
+                            .into_iter()
+                            .chain(incoming)
+                            .chain(outgoing),
+                    );
+                    (*idx, color)
+                })
+                .collect();
+            colors = refined;
+            let refined_classes = class_count(&colors);
+            if refined_classes == classes {
+                break;
+            }
+            classes = refined_classes;
+        }
+        let mut colors = colors.into_values().collect::<Vec<_>>();
+        colors.sort();
+
+        let (inputs, outputs) = self.io_dimensions();
+        format!("{}-{}:{:016x}", inputs, outputs, fnv_hash(colors))
+    }
+}
+
+/// 64-bit FNV-1a hash of the values, used instead of the std hasher whose output may change between Rust versions
+fn fnv_hash(values: impl IntoIterator<Item = u64>) -> u64 {
+    values
+        .into_iter()
+        .flat_map(u64::to_le_bytes)
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Fingerprints of well-known designs, see [`FlowGraphAnalysis::fingerprint`]
+const KNOWN_DESIGNS: [(&str, &str); 2] = [
+    ("2-2:dde195bfef7d221f", "the standard 2-2 balancer"),
+    ("4-4:c213b6deab9ca809", "the standard 4-4 balancer"),
+];
+
+/// Returns the name of the well-known design with the given [`FlowGraphAnalysis::fingerprint`], if any.
+pub fn known_design(fingerprint: &str) -> Option<&'static str> {
+    KNOWN_DESIGNS
+        .iter()
+        .find(|(known, _)| *known == fingerprint)
+        .map(|(_, name)| *name)
+}
+
+/// Returns a lower bound on the number of splitters of a balancer with the given number of `inputs` and `outputs`.
+///
+/// Every input has to reach every output. Joining the `inputs` into a single belt takes `inputs - 1` merges
+/// and dividing it into the `outputs` takes `outputs - 1` splits. A splitter merges two belts and splits them
+/// into two, so it can do at most one of each, which requires at least `max(inputs, outputs) - 1` splitters.
+///
+/// The bound is reached by e.g. 1-N and N-1 trees of splitters, but not by every size: 4-4 balancers need at least 4 splitters.
+pub fn min_splitters(inputs: usize, outputs: usize) -> usize {
+    inputs.max(outputs).saturating_sub(1)
+}
+
+/// Minimum cut between the inputs and the outputs of a graph, computed with the Edmonds-Karp algorithm.
+struct MinCut {
+    value: GenericFraction<u128>,
+    edges: Vec<EdgeIndex>,
+}
+
+impl MinCut {
+    fn new(graph: &FlowGraph) -> Self {
+        /* arcs of the flow network: the edges of the graph, followed by arcs from a virtual source to
+         * each input and from each output to a virtual sink, which get a capacity that can't be exceeded */
+        let source = graph.node_count();
+        let sink = source + 1;
+        let unlimited = graph
+            .edge_weights()
+            .fold(GenericFraction::from(1), |acc, e| acc + e.capacity);
+        let mut arcs = graph
+            .edge_references()
+            .map(|e| (e.source().index(), e.target().index(), e.weight().capacity))
+            .collect::<Vec<_>>();
+        for idx in graph.node_indices() {
+            match graph[idx] {
+                Node::Input(_) => arcs.push((source, idx.index(), unlimited)),
+                Node::Output(_) => arcs.push((idx.index(), sink, unlimited)),
+                _ => (),
+            }
+        }
+        let mut incident = vec![vec![]; sink + 1];
+        for (i, (from, to, _)) in arcs.iter().enumerate() {
+            incident[*from].push(i);
+            incident[*to].push(i);
+        }
+
+        let mut flow = vec![GenericFraction::from(0); arcs.len()];
+        let mut value = GenericFraction::from(0);
+        loop {
+            /* breadth-first search for a shortest augmenting path in the residual network */
+            let mut parent: Vec<Option<usize>> = vec![None; sink + 1];
+            let mut visited = vec![false; sink + 1];
+            visited[source] = true;
+            let mut queue = VecDeque::from([source]);
+            while let Some(node) = queue.pop_front() {
+                for &arc in &incident[node] {
+                    let (from, to, capacity) = arcs[arc];
+                    let (next, residual) = if from == node {
+                        (to, capacity - flow[arc])
+                    } else {
+                        (from, flow[arc])
+                    };
+                    if !visited[next] && residual > GenericFraction::from(0) {
+                        visited[next] = true;
+                        parent[next] = Some(arc);
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            if !visited[sink] {
+                /* the nodes reachable from the source form the source side of the minimum cut */
+                let edges = graph
+                    .edge_references()
+                    .filter(|e| visited[e.source().index()] && !visited[e.target().index()])
+                    .map(|e| e.id())
+                    .collect();
+                return Self { value, edges };
+            }
+
+            let mut path = vec![];
+            let mut node = sink;
+            while let Some(arc) = parent[node] {
+                let (from, to, _) = arcs[arc];
+                let forward = to == node;
+                path.push((arc, forward));
+                node = if forward { from } else { to };
+            }
+            let bottleneck = path
+                .iter()
+                .map(|&(arc, forward)| {
+                    if forward {
+                        arcs[arc].2 - flow[arc]
+                    } else {
+                        flow[arc]
+                    }
+                })
+                .min()
+                .unwrap();
+            for (arc, forward) in path {
+                if forward {
+                    flow[arc] += bottleneck;
+                } else {
+                    flow[arc] -= bottleneck;
+                }
+            }
+            value += bottleneck;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, Connector, Edge, FlowGraphFun, Node},
+        utils::Side,
+    };
+
+    use super::*;
+
+    #[test]
+    fn topo_order_4_4() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let order = graph.topo_order().unwrap();
+        assert_eq!(order.len(), graph.node_count());
+
+        let last_input = order
+            .iter()
+            .rposition(|idx| matches!(graph[*idx], Node::Input(_)))
+            .unwrap();
+        let first_output = order
+            .iter()
+            .position(|idx| matches!(graph[*idx], Node::Output(_)))
+            .unwrap();
+        assert!(last_input < first_output);
+    }
+
+    #[test]
+    fn structural_adjacency_4_4() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        let expected = vec![
+            (1, vec![3, 4]),
+            (2, vec![3, 4]),
+            (3, vec![5]),
+            (4, vec![6, 7]),
+            (5, vec![6, 7]),
+            (6, vec![]),
+            (7, vec![]),
+        ];
+        assert_eq!(graph.structural_adjacency(), expected);
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert_eq!(graph.structural_adjacency(), expected);
+    }
+
+    #[test]
+    fn min_splitters_sizes() {
+        for (inputs, outputs, expected) in [
+            (1, 1, 0),
+            (1, 2, 1),
+            (2, 2, 1),
+            (1, 4, 3),
+            (4, 1, 3),
+            (2, 4, 3),
+        ] {
+            assert_eq!(
+                min_splitters(inputs, outputs),
+                expected,
+                "{}-{}",
+                inputs,
+                outputs
+            );
+        }
+        assert!(min_splitters(4, 4) <= 4);
+
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let graph = Compiler::new(entities).create_graph();
+        assert_eq!(graph.splitter_count(), 7);
+        assert_eq!(graph.splitter_efficiency(), 3.0 / 7.0);
+    }
+
+    #[test]
+    fn splitter_depth_4_4() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert_eq!(graph.splitter_depth(), 4);
+    }
+
+    /// The loop feeding an output back into the balancer doesn't add stages.
+    #[test]
+    fn splitter_depth_cycle() {
+        let entities = file_to_entities("tests/3-3").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert!(graph.topo_order().is_err());
+        assert_eq!(graph.splitter_depth(), 4);
+    }
+
+    #[test]
+    fn capacity_histogram_mixed_tiers() {
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let histogram = graph.capacity_histogram();
+        let expected = [(15, 1), (45, 3), (60, 1)]
+            .into_iter()
+            .map(|(capacity, count)| (GenericFraction::from(capacity), count))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(histogram, expected);
+    }
+
+    /// The assembler only gets its ingredients from the belt it fills itself, so its output belt never gets any items.
+    #[test]
+    fn startup_deadlocks() {
+        let entities = file_to_entities("tests/startup_deadlock").unwrap();
+        let graph = Compiler::new(entities).create_graph();
+        let deadlocks = graph.startup_deadlocks();
+        assert_eq!(deadlocks.len(), 1);
+        let mut ids = deadlocks[0]
+            .iter()
+            .map(|e| graph[graph.edge_endpoints(*e).unwrap().0].get_id())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids, [1, 2, 4]);
+
+        for file in ["tests/4-4-tu", "tests/assembler_flow", "tests/deadlock"] {
+            let entities = file_to_entities(file).unwrap();
+            let graph = Compiler::new(entities).create_graph();
+            assert!(graph.startup_deadlocks().is_empty(), "{}", file);
+        }
+    }
+
+    #[test]
+    fn connected_components_two_balancers() {
+        let entities = file_to_entities("tests/two_balancers").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        let components = graph.connected_components();
+        assert_eq!(components.len(), 2);
+        assert_eq!(
+            components.iter().map(Vec::len).sum::<usize>(),
+            graph.node_count()
+        );
+
+        let subgraphs = components
+            .iter()
+            .map(|c| graph.component_subgraph(c))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subgraphs.iter().map(|g| g.edge_count()).sum::<usize>(),
+            graph.edge_count()
+        );
+        for (subgraph, component) in subgraphs.iter().zip(&components) {
+            assert_eq!(subgraph.node_count(), component.len());
+            assert_eq!(subgraph.connected_components().len(), 1);
+        }
+    }
+
+    #[test]
+    fn is_trivial() {
+        let compile = |file| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            graph
+        };
+        assert!(compile("tests/belt_reduction").is_trivial());
+        assert!(!compile("tests/simple_splitter").is_trivial());
+    }
+
+    #[test]
+    fn fully_connected_io() {
+        let compile = |file| {
+            let entities = file_to_entities(file).unwrap();
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            graph
+        };
+        let balancer = compile("tests/4-4");
+        assert!(balancer.is_fully_connected_io());
+
+        // two independent 2-2 splitters: each input only reaches the 2 outputs of its own splitter
+        let buses = compile("tests/two_buses");
+        assert!(!buses.is_fully_connected_io());
+        let pairs = buses.unreachable_io_pairs();
+        assert_eq!(pairs.len(), 8);
+        assert!(pairs.contains(&(2, 9)));
+        assert!(!pairs.contains(&(2, 4)));
+    }
+
+    #[test]
+    fn output_fanin() {
+        let entities = file_to_entities("tests/weighted_drain").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        // the free side of splitter 3 only gets items from inputs 1 and 2, not from input 6
+        let fanin = graph.output_fanin();
+        assert_eq!(fanin, HashMap::from([(3, 2), (5, 3), (9, 3)]));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let compile = |entities| {
+            let mut graph = Compiler::new(entities).create_graph();
+            graph.simplify(&[], CoalesceStrength::Aggressive);
+            graph
+        };
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let fingerprint = compile(entities.clone()).fingerprint();
+        assert!(fingerprint.starts_with("4-4:"));
+        assert_eq!(
+            known_design(&fingerprint),
+            Some("the standard 4-4 balancer")
+        );
+
+        // the same balancer somewhere else, with the entities numbered in the opposite order
+        let count = entities.len() as EntityId;
+        let moved = entities
+            .into_iter()
+            .map(|mut e| {
+                let base = e.get_base_mut();
+                base.position.x += 17;
+                base.position.y -= 5;
+                base.id = count + 1 - base.id;
+                e
+            })
+            .collect();
+        assert_eq!(compile(moved).fingerprint(), fingerprint);
+
+        let broken = compile(file_to_entities("tests/4-4-broken").unwrap());
+        assert_ne!(broken.fingerprint(), fingerprint);
+        let two = compile(file_to_entities("tests/2-2").unwrap()).fingerprint();
+        assert_eq!(known_design(&two), Some("the standard 2-2 balancer"));
+    }
+
+    #[test]
+    fn io_dimensions() {
+        let mut graph = Compiler::new(file_to_entities("tests/belt_merge").unwrap()).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert_eq!(graph.io_dimensions(), (2, 1));
+        assert_eq!(FlowGraph::default().io_dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn critical_edges() {
+        // the yellow belt at the start of the chain limits the throughput
+        let entities = file_to_entities("tests/belt_reduction").unwrap();
+        let graph = Compiler::new(entities).create_graph();
+        let critical = graph.critical_edges();
+        assert_eq!(critical.len(), 1);
+        let (src, dst) = graph.edge_endpoints(critical[0]).unwrap();
+        assert_eq!(graph[src].get_id(), 1);
+        assert_eq!(graph[dst].get_id(), 1);
+        assert_eq!(graph.min_cut_value(), GenericFraction::from(15));
+
+        // one express and one yellow output belt
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert_eq!(graph.min_cut_value(), GenericFraction::from(60));
+        let cut = graph
+            .critical_edges()
+            .iter()
+            .map(|e| graph[*e].capacity)
+            .fold(GenericFraction::from(0), |acc, c| acc + c);
+        assert_eq!(cut, GenericFraction::from(60));
+    }
+
+    #[test]
+    fn topo_order_cycle() {
+        let mut graph = FlowGraph::default();
+        let a = graph.add_node(Node::Connector(Connector { id: 0 }));
+        let b = graph.add_node(Node::Connector(Connector { id: 1 }));
+        let edge = Edge {
+            side: Side::None,
+            capacity: 15.into(),
+        };
+        graph.add_edge(a, b, edge);
+        graph.add_edge(b, a, edge);
+        assert!(graph.topo_order().is_err());
+        assert_eq!(graph.splitter_depth(), 0);
+    }
+}
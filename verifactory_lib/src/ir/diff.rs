@@ -0,0 +1,185 @@
+//! Structural comparison of two [`FlowGraph`]s, e.g. to see what a change to the compiler did to its output.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use petgraph::visit::EdgeRef;
+
+use super::{Edge, FlowGraph};
+
+/// Differences between two graphs, see [`FlowGraphAnalysis::diff`](super::FlowGraphAnalysis::diff).
+///
+/// Nodes are identified by their kind and entity id as returned by [`Node::get_str`](super::Node::get_str),
+/// edges by the nodes they connect. As node indices are not compared, graphs built in a different order
+/// but with the same structure have no differences.
+#[derive(Debug, Clone, Default)]
+pub struct GraphDiff {
+    /// Nodes only present in the new graph
+    pub added_nodes: Vec<String>,
+    /// Nodes only present in the old graph
+    pub removed_nodes: Vec<String>,
+    /// Edges only present in the new graph as `(source, target, edge)`
+    pub added_edges: Vec<(String, String, Edge)>,
+    /// Edges only present in the old graph as `(source, target, edge)`
+    pub removed_edges: Vec<(String, String, Edge)>,
+    /// Edges present in both graphs, but with a different side or capacity, as `(source, target, old, new)`
+    pub changed_edges: Vec<(String, String, Edge, Edge)>,
+}
+
+/// Nodes of the graph with the number of times each of them occurs
+fn node_counts(graph: &FlowGraph) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for node in graph.node_weights() {
+        *counts.entry(node.get_str()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Edges of the graph grouped by the nodes they connect, ordered by side and capacity
+fn edges_by_nodes(graph: &FlowGraph) -> BTreeMap<(String, String), Vec<Edge>> {
+    let mut edges: BTreeMap<_, Vec<Edge>> = BTreeMap::new();
+    for edge in graph.edge_references() {
+        let key = (
+            graph[edge.source()].get_str(),
+            graph[edge.target()].get_str(),
+        );
+        edges.entry(key).or_default().push(*edge.weight());
+    }
+    for parallel in edges.values_mut() {
+        parallel.sort_by_key(|e| (format!("{:?}", e.side), e.capacity));
+    }
+    edges
+}
+
+impl GraphDiff {
+    /// Compares the `old` graph to the `new` one.
+    pub fn new(old: &FlowGraph, new: &FlowGraph) -> Self {
+        let mut diff = Self::default();
+
+        let (old_nodes, new_nodes) = (node_counts(old), node_counts(new));
+        for (node, count) in &old_nodes {
+            let remaining = count.saturating_sub(*new_nodes.get(node).unwrap_or(&0));
+            diff.removed_nodes.extend(vec![node.clone(); remaining]);
+        }
+        for (node, count) in &new_nodes {
+            let added = count.saturating_sub(*old_nodes.get(node).unwrap_or(&0));
+            diff.added_nodes.extend(vec![node.clone(); added]);
+        }
+
+        let (old_edges, new_edges) = (edges_by_nodes(old), edges_by_nodes(new));
+        let no_edges = vec![];
+        for (key, old_parallel) in &old_edges {
+            let new_parallel = new_edges.get(key).unwrap_or(&no_edges);
+            let (source, target) = key.clone();
+            for (i, old_edge) in old_parallel.iter().enumerate() {
+                match new_parallel.get(i) {
+                    Some(new_edge)
+                        if new_edge.side != old_edge.side
+                            || new_edge.capacity != old_edge.capacity =>
+                    {
+                        diff.changed_edges.push((
+                            source.clone(),
+                            target.clone(),
+                            *old_edge,
+                            *new_edge,
+                        ));
+                    }
+                    Some(_) => (),
+                    None => diff
+                        .removed_edges
+                        .push((source.clone(), target.clone(), *old_edge)),
+                }
+            }
+        }
+        for (key, new_parallel) in &new_edges {
+            let old_count = old_edges.get(key).map_or(0, Vec::len);
+            let (source, target) = key;
+            diff.added_edges.extend(
+                new_parallel
+                    .iter()
+                    .skip(old_count)
+                    .map(|e| (source.clone(), target.clone(), *e)),
+            );
+        }
+        diff
+    }
+
+    /// Returns true if the graphs have the same structure
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_edges.is_empty()
+    }
+}
+
+/// Lists each difference on its own line, prefixed by `+` for additions, `-` for removals and `~` for changes.
+impl Display for GraphDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for node in &self.added_nodes {
+            writeln!(f, "+ {}", node)?;
+        }
+        for node in &self.removed_nodes {
+            writeln!(f, "- {}", node)?;
+        }
+        for (source, target, edge) in &self.added_edges {
+            writeln!(f, "+ {} -> {}: {:?}", source, target, edge)?;
+        }
+        for (source, target, edge) in &self.removed_edges {
+            writeln!(f, "- {} -> {}: {:?}", source, target, edge)?;
+        }
+        for (source, target, old, new) in &self.changed_edges {
+            writeln!(f, "~ {} -> {}: {:?} => {:?}", source, target, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength, FlowGraphAnalysis, FlowGraphFun, Node, Output},
+        utils::Side,
+    };
+
+    use super::*;
+
+    #[test]
+    fn diff_modified_copy() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], CoalesceStrength::Aggressive);
+        assert!(graph.diff(&graph.clone()).is_empty());
+
+        let mut modified = graph.clone();
+        let edge = modified.edge_indices().next().unwrap();
+        modified[edge].capacity = 7.into();
+        let (source, _) = modified.edge_endpoints(edge).unwrap();
+        let output = modified.add_node(Node::Output(Output { id: 100 }));
+        let added = Edge {
+            side: Side::Left,
+            capacity: 15.into(),
+        };
+        modified.add_edge(source, output, added);
+
+        let diff = graph.diff(&modified);
+        assert_eq!(diff.added_nodes, vec!["o100".to_string()]);
+        assert!(diff.removed_nodes.is_empty());
+        assert_eq!(diff.added_edges.len(), 1);
+        assert_eq!(diff.added_edges[0].1, "o100");
+        assert!(diff.removed_edges.is_empty());
+        assert_eq!(diff.changed_edges.len(), 1);
+        let (_, _, old, new) = diff.changed_edges[0];
+        assert_eq!(old.capacity, graph[edge].capacity);
+        assert_eq!(new.capacity, 7.into());
+        assert!(diff.to_string().contains("+ o100\n"));
+
+        // the reverse diff undoes the changes
+        let reverse = modified.diff(&graph);
+        assert_eq!(reverse.removed_nodes, diff.added_nodes);
+        assert_eq!(reverse.removed_edges.len(), 1);
+        assert_eq!(reverse.changed_edges[0].3.capacity, graph[edge].capacity);
+    }
+}
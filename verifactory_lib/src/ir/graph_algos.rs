@@ -1,10 +1,40 @@
-use std::{cmp::Ordering, fs::File, io::Write};
+use std::{cmp::Ordering, fmt::Display};
 
 use crate::entities::EntityId;
 
 use super::{Connector, FlowGraph, GraphHelper, Lattice, Node};
-use graphviz_rust::{cmd::Format, exec_dot};
-use petgraph::{dot::Dot, prelude::EdgeIndex, Direction::Outgoing};
+use petgraph::{
+    dot::Dot,
+    prelude::{EdgeIndex, NodeIndex},
+    visit::{Dfs, Reversed},
+    Direction::Outgoing,
+};
+
+/// Inputs and outputs removed by [`FlowGraphFun::prune_dead_io`], identified by their entity ids
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrunedIo {
+    /// Inputs that could not reach any output
+    pub inputs: Vec<EntityId>,
+    /// Outputs that could not be reached by any input
+    pub outputs: Vec<EntityId>,
+}
+
+impl PrunedIo {
+    /// Returns true if nothing was pruned.
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty() && self.outputs.is_empty()
+    }
+}
+
+impl Display for PrunedIo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Pruned dead inputs {:?} and outputs {:?}",
+            self.inputs, self.outputs
+        )
+    }
+}
 
 /// Indicates how much a graph is coalesced.
 /// Coalescing is performed on a Connector S, where A->S->B, with in_deg(S) = out_deg(S) = 1.
@@ -22,16 +52,25 @@ pub enum CoalesceStrength {
     /// Coalesced only if:
     /// A, B are {Connector, Input, Output}
     Aggressive,
+    /// Coalescing like [`CoalesceStrength::Aggressive`], but keeping the connector next to each input and output.
+    /// Used for displaying the graph, as the endpoints can be recognized in the blueprint.
+    /// Not coalesced if:
+    /// A (or B) is the Input/Output of S's entity and B (or A) is not part of S's entity
+    DisplayFriendly,
 }
 
 // TODO: docs
 /// Trait to define helper functions for graph simplification on a [`petgraph::Graph`] type.
 trait FlowGraphSimplify {
-    /// Merges superfluous nodes given a [`CoalesceStrength`] strategy.
+    /// Merges superfluous nodes given a [`CoalesceStrength`] strategy, never merging away the nodes matching `preserve`.
     ///
     /// Returns `true` after the first node has been mutated.
     /// Otherwise, if no node has been mutated, return `false`.
-    fn coalesce_nodes(&mut self, strength: CoalesceStrength) -> bool;
+    fn coalesce_nodes(
+        &mut self,
+        strength: CoalesceStrength,
+        preserve: &dyn Fn(&Node) -> bool,
+    ) -> bool;
     /// Shrinks the capacities of all edges to their minimum bound.
     ///
     /// Returns `true` after the first edge has been mutated.
@@ -64,17 +103,53 @@ trait ShrinkNodes {
     ) -> bool;
 }
 
-/// Trait exposing the simplification and exporting of the IR graph
+/// Trait exposing the simplification of the IR graph
 pub trait FlowGraphFun {
     fn simplify(&mut self, exclude_list: &[EntityId], strength: CoalesceStrength);
-    fn to_svg(&self, path: &str) -> anyhow::Result<()>;
+    /// Same as [`FlowGraphFun::simplify`], but the nodes matching `preserve` are never merged into their neighbours,
+    /// e.g. to keep a belt under investigation visible in the simplified graph and its model.
+    ///
+    /// Nodes without in- or out-edges are still removed, as no flow can pass through them.
+    fn simplify_preserving(
+        &mut self,
+        exclude_list: &[EntityId],
+        strength: CoalesceStrength,
+        preserve: &dyn Fn(&Node) -> bool,
+    );
+    /// Removes the inputs associated with `inputs` and the outputs associated with `outputs`.
+    ///
+    /// Unlike the exclude list of [`FlowGraphFun::simplify`], this keeps the input and output of a
+    /// tap apart, as they share the same [`EntityId`].
+    fn remove_io(&mut self, inputs: &[EntityId], outputs: &[EntityId]);
+    /// Removes the inputs that can not reach any output and the outputs that can not be reached by any input,
+    /// e.g. a stub of belt feeding into a closed loop.
+    ///
+    /// Such dead ends can never carry any flow, so including them in the proofs only produces trivial imbalances.
+    /// Returns the pruned inputs and outputs, each sorted by entity id.
+    fn prune_dead_io(&mut self) -> PrunedIo;
+    /// Swaps the left and right side of every edge and the priority of every splitter and merger,
+    /// without reversing the direction of the edges like [`Reversable::reverse`](super::Reversable::reverse) does.
+    ///
+    /// Used to debug the side semantics apart from the reversal, e.g. when the forward and reversed models disagree.
+    fn mirror_sides(&mut self);
+    /// Returns the graph in the Graphviz DOT format, labeling nodes and edges with their contents.
+    fn to_dot_string(&self) -> String;
 }
 
 impl FlowGraphFun for FlowGraph {
     fn simplify(&mut self, exclude_list: &[EntityId], strength: CoalesceStrength) {
+        self.simplify_preserving(exclude_list, strength, &|_| false);
+    }
+
+    fn simplify_preserving(
+        &mut self,
+        exclude_list: &[EntityId],
+        strength: CoalesceStrength,
+        preserve: &dyn Fn(&Node) -> bool,
+    ) {
         self.remove_false_io(exclude_list);
         loop {
-            if self.coalesce_nodes(strength) {
+            if self.coalesce_nodes(strength, preserve) {
                 continue;
             }
 
@@ -85,18 +160,84 @@ impl FlowGraphFun for FlowGraph {
         }
     }
 
-    fn to_svg(&self, path: &str) -> anyhow::Result<()> {
-        let svg = exec_dot(
-            format!("{:?}", Dot::with_config(self, &[])),
-            vec![Format::Svg.into()],
-        )?;
-        File::create(path)?.write_all(&svg)?;
-        Ok(())
+    fn remove_io(&mut self, inputs: &[EntityId], outputs: &[EntityId]) {
+        self.retain_nodes(|g, idx| match &g[idx] {
+            Node::Input(i) => !inputs.contains(&i.id),
+            Node::Output(o) => !outputs.contains(&o.id),
+            _ => true,
+        });
+    }
+
+    fn prune_dead_io(&mut self) -> PrunedIo {
+        let inputs = self
+            .node_indices()
+            .filter(|idx| matches!(self[*idx], Node::Input(_)))
+            .collect::<Vec<_>>();
+        let outputs = self
+            .node_indices()
+            .filter(|idx| matches!(self[*idx], Node::Output(_)))
+            .collect::<Vec<_>>();
+
+        /* walk forward from all the inputs and backward from all the outputs at once */
+        let mut reached_from_inputs = vec![false; self.node_count()];
+        let mut dfs = Dfs::empty(&*self);
+        dfs.stack.extend(&inputs);
+        while let Some(idx) = dfs.next(&*self) {
+            reached_from_inputs[idx.index()] = true;
+        }
+        let mut reaches_outputs = vec![false; self.node_count()];
+        let mut dfs = Dfs::empty(Reversed(&*self));
+        dfs.stack.extend(&outputs);
+        while let Some(idx) = dfs.next(Reversed(&*self)) {
+            reaches_outputs[idx.index()] = true;
+        }
+
+        let dead_inputs = inputs
+            .into_iter()
+            .filter(|idx| !reaches_outputs[idx.index()]);
+        let dead_outputs = outputs
+            .into_iter()
+            .filter(|idx| !reached_from_inputs[idx.index()]);
+        let dead = dead_inputs.chain(dead_outputs).collect::<Vec<_>>();
+
+        let mut pruned = PrunedIo::default();
+        for idx in &dead {
+            match &self[*idx] {
+                Node::Input(i) => pruned.inputs.push(i.id),
+                Node::Output(o) => pruned.outputs.push(o.id),
+                _ => unreachable!(),
+            }
+        }
+        pruned.inputs.sort();
+        pruned.outputs.sort();
+        self.retain_nodes(|_, idx| !dead.contains(&idx));
+        pruned
+    }
+
+    fn mirror_sides(&mut self) {
+        for edge in self.edge_weights_mut() {
+            edge.side = -edge.side;
+        }
+        for node in self.node_weights_mut() {
+            match node {
+                Node::Splitter(s) => s.output_priority = -s.output_priority,
+                Node::Merger(m) => m.input_priority = -m.input_priority,
+                _ => (),
+            }
+        }
+    }
+
+    fn to_dot_string(&self) -> String {
+        format!("{:?}", Dot::with_config(self, &[]))
     }
 }
 
 impl FlowGraphSimplify for FlowGraph {
-    fn coalesce_nodes(&mut self, strength: CoalesceStrength) -> bool {
+    fn coalesce_nodes(
+        &mut self,
+        strength: CoalesceStrength,
+        preserve: &dyn Fn(&Node) -> bool,
+    ) -> bool {
         for node_idx in self.node_indices() {
             let in_deg = self.in_deg(node_idx);
             let out_deg = self.out_deg(node_idx);
@@ -115,6 +256,9 @@ impl FlowGraphSimplify for FlowGraph {
                 self.remove_node(node_idx);
                 return true;
             }
+            if preserve(node) {
+                continue;
+            }
             let source_node = self.in_nodes(node_idx)[0];
             let target_node = self.out_nodes(node_idx)[0];
 
@@ -141,6 +285,18 @@ impl FlowGraphSimplify for FlowGraph {
                             continue;
                         }
                     }
+                    if let CoalesceStrength::DisplayFriendly = strength {
+                        let id = self[node_idx].get_id();
+                        let is_own_io = |n: NodeIndex| {
+                            matches!(self[n], Node::Input(_) | Node::Output(_))
+                                && self[n].get_id() == id
+                        };
+                        if (is_own_io(source_node) && self[target_node].get_id() != id)
+                            || (is_own_io(target_node) && self[source_node].get_id() != id)
+                        {
+                            continue;
+                        }
+                    }
                 }
                 Node::Merger(_) | Node::Splitter(_) => {
                     // skip if fully populated
@@ -192,9 +348,16 @@ impl FlowGraphSimplify for FlowGraph {
             let node = &self[node_idx];
             let changed = match node {
                 Node::Connector(_) => {
-                    let in_idx = self.in_edge_idx(node_idx)[0];
-                    let out_idx = self.out_edge_idx(node_idx)[0];
-                    self.shrink_capacity_connector(in_idx, out_idx)
+                    /* an isolated connector, e.g. of a lone tapped belt, has nothing to shrink */
+                    match (
+                        self.in_edge_idx(node_idx).first(),
+                        self.out_edge_idx(node_idx).first(),
+                    ) {
+                        (Some(in_idx), Some(out_idx)) => {
+                            self.shrink_capacity_connector(*in_idx, *out_idx)
+                        }
+                        _ => false,
+                    }
                 }
                 Node::Splitter(s) => {
                     let in_idx = self.in_edge_idx(node_idx)[0];
@@ -331,8 +494,13 @@ mod test {
     use crate::{
         frontend::Compiler,
         import::file_to_entities,
-        ir::{graph_algos::FlowGraphSimplify, CoalesceStrength::Aggressive, FlowGraphFun},
+        ir::{
+            graph_algos::FlowGraphSimplify,
+            CoalesceStrength::{Aggressive, DisplayFriendly},
+            FlowGraph, FlowGraphFun, GraphHelper, Node,
+        },
     };
+    use petgraph::visit::EdgeRef;
 
     #[test]
     fn test_shrinking() {
@@ -344,6 +512,33 @@ mod test {
         assert_eq!(graph.edge_count(), 9);
     }
 
+    #[test]
+    fn mirror_sides() {
+        let entities = file_to_entities("tests/prio_splitter").unwrap();
+        let graph = Compiler::new(entities).create_graph();
+        let debug = |g: &FlowGraph| {
+            let nodes = g.node_weights().map(|n| format!("{:?}", n));
+            let edges = g.edge_weights().map(|e| format!("{:?}", e));
+            nodes.chain(edges).collect::<Vec<_>>()
+        };
+
+        let mut mirrored = graph.clone();
+        mirrored.mirror_sides();
+        assert_ne!(debug(&mirrored), debug(&graph));
+        for (a, b) in graph.node_weights().zip(mirrored.node_weights()) {
+            assert_eq!(std::mem::discriminant(a), std::mem::discriminant(b));
+        }
+        for (a, b) in graph.edge_references().zip(mirrored.edge_references()) {
+            assert_eq!(
+                (a.source(), a.target(), a.weight().side),
+                (b.source(), b.target(), -b.weight().side)
+            );
+        }
+
+        mirrored.mirror_sides();
+        assert_eq!(debug(&mirrored), debug(&graph));
+    }
+
     #[test]
     fn belt_reduction() {
         let entities = file_to_entities("tests/belt_reduction").unwrap();
@@ -372,10 +567,74 @@ mod test {
         assert_eq!(graph.edge_count(), 16);
     }
 
+    #[test]
+    fn display_friendly() {
+        let entities = file_to_entities("tests/mixed_output_tiers").unwrap();
+        let compile = |strength| {
+            let mut graph = Compiler::new(entities.clone()).create_graph();
+            graph.simplify(&[], strength);
+            graph
+        };
+        let io_ids = |graph: &FlowGraph| {
+            let mut ids = graph
+                .node_weights()
+                .filter(|n| matches!(n, Node::Input(_) | Node::Output(_)))
+                .map(|n| n.get_id())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        let aggressive = compile(Aggressive);
+        let display = compile(DisplayFriendly);
+        assert_eq!(io_ids(&display), io_ids(&aggressive));
+        assert!(display.node_count() > aggressive.node_count());
+
+        // each input and output is next to a connector of its own entity
+        for idx in display.node_indices() {
+            let neighbor = match &display[idx] {
+                Node::Input(_) => display.out_nodes(idx)[0],
+                Node::Output(_) => display.in_nodes(idx)[0],
+                _ => continue,
+            };
+            assert!(matches!(display[neighbor], Node::Connector(_)));
+            assert_eq!(display[neighbor].get_id(), display[idx].get_id());
+        }
+    }
+
+    #[test]
+    fn simplify_preserving() {
+        let entities = file_to_entities("tests/simple_belt").unwrap();
+        let is_tagged = |n: &Node| matches!(n, Node::Connector(c) if c.id == 2);
+
+        let mut graph = Compiler::new(entities.clone()).create_graph();
+        graph.simplify(&[], Aggressive);
+        assert!(!graph.node_weights().any(is_tagged));
+
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify_preserving(&[], Aggressive, &is_tagged);
+        // both connectors of the belt are kept between the input and the output
+        assert_eq!(graph.node_count(), 4);
+        assert_eq!(graph.edge_count(), 3);
+        assert_eq!(graph.node_weights().filter(|n| is_tagged(n)).count(), 2);
+    }
+
     #[test]
     fn prio_splitter() {
         let entities = file_to_entities("tests/prio_splitter").unwrap();
         let mut graph = Compiler::new(entities).create_graph();
         graph.simplify(&[], Aggressive);
     }
+
+    #[test]
+    fn to_dot_string() {
+        let entities = file_to_entities("tests/belt_reduction").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], Aggressive);
+        let dot = graph.to_dot_string();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("Input(Input { id: "));
+        assert!(dot.contains("Output(Output { id: "));
+        assert!(dot.contains("capacity: 15 "));
+    }
 }
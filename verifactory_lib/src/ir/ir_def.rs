@@ -149,11 +149,16 @@ pub struct Edge {
 
 impl Debug for Edge {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let denom = *self.capacity.denom().unwrap() as f64;
-        let numer = *self.capacity.numer().unwrap() as f64;
+        let denom = *self.capacity.denom().unwrap();
+        let numer = *self.capacity.numer().unwrap();
+        let capacity = if denom == 1 {
+            format!("{}", numer)
+        } else {
+            format!("{}/{} ({})", numer, denom, numer as f64 / denom as f64)
+        };
         f.debug_struct("Edge")
             .field("side", &self.side)
-            .field("capacity", &(numer / denom))
+            .field("capacity", &format_args!("{}", capacity))
             .finish()
     }
 }
@@ -261,3 +266,25 @@ impl GraphHelper for FlowGraph {
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_debug_exact() {
+        let edge = Edge {
+            side: Side::None,
+            capacity: GenericFraction::new(45u128, 2u128),
+        };
+        assert_eq!(
+            format!("{:?}", edge),
+            "Edge { side: None, capacity: 45/2 (22.5) }"
+        );
+        let edge = Edge {
+            side: Side::None,
+            capacity: 15.into(),
+        };
+        assert_eq!(format!("{:?}", edge), "Edge { side: None, capacity: 15 }");
+    }
+}
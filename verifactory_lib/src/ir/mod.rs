@@ -1,9 +1,16 @@
 //! The graph-based intermediate representation used for the conversion from a Factorio blueprint to a z3 model
+//!
+//! The IR does not depend on the file system, graphviz or z3, so that it can be reused on targets like WASM.
+//! Rendering the graph as an image is done by the [`render`](crate::render) module.
 
+mod analysis;
+mod diff;
 mod graph_algos;
 mod ir_def;
 mod reverse;
 
 pub use self::reverse::Reversable;
+pub use analysis::*;
+pub use diff::*;
 pub use graph_algos::*;
 pub use ir_def::*;
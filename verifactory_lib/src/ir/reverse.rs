@@ -75,6 +75,6 @@ mod test {
         let entities = file_to_entities("tests/3-2").unwrap();
         let mut graph = Compiler::new(entities).create_graph();
         graph.simplify(&[3], Aggressive);
-        let rev = graph.reverse();
+        let _rev = graph.reverse();
     }
 }
@@ -1,6 +1,12 @@
+pub mod analyze;
 pub mod backends;
 pub mod entities;
+pub mod export;
 pub mod frontend;
 pub mod import;
 pub mod ir;
+#[cfg(feature = "graphviz")]
+pub mod render;
+#[cfg(feature = "z3")]
+pub mod session;
 pub mod utils;
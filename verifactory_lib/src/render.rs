@@ -0,0 +1,196 @@
+//! Rendering of the IR graph with graphviz.
+//!
+//! Kept out of the [`ir`](crate::ir) module, as it runs the `dot` executable and writes files,
+//! which is not available on every target, e.g. WASM.
+
+use std::{collections::HashMap, fs::File, io::Write};
+
+use anyhow::Result;
+use graphviz_rust::{cmd::CommandArg, exec_dot};
+use petgraph::{
+    dot::{Config, Dot},
+    prelude::EdgeIndex,
+    visit::EdgeRef,
+};
+
+use crate::ir::{FlowGraph, FlowGraphFun};
+
+pub use graphviz_rust::cmd::Format as ExportFormat;
+
+/// Options used when exporting a [`FlowGraph`] as an image
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    /// Output format, e.g. [`ExportFormat::Svg`], [`ExportFormat::Png`] or [`ExportFormat::Pdf`]
+    pub format: ExportFormat,
+    /// Resolution in dots per inch, uses the graphviz default (96) if `None`
+    pub dpi: Option<u32>,
+    /// Maximum width and height of the drawing in inches, unbounded if `None`
+    pub size: Option<(f32, f32)>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Svg,
+            dpi: None,
+            size: None,
+        }
+    }
+}
+
+impl ExportOptions {
+    fn to_args(self) -> Vec<CommandArg> {
+        let mut args = vec![self.format.into()];
+        if let Some(dpi) = self.dpi {
+            args.push(CommandArg::Custom(format!("-Gdpi={}", dpi)));
+        }
+        if let Some((width, height)) = self.size {
+            args.push(CommandArg::Custom(format!("-Gsize={},{}", width, height)));
+        }
+        args
+    }
+}
+
+/// Trait exposing the exporting of the IR graph as an image
+pub trait FlowGraphRender {
+    fn to_svg(&self, path: &str) -> Result<()>;
+    /// Renders the graph using graphviz with the given `options` and writes it to `path`.
+    fn export(&self, path: &str, options: &ExportOptions) -> Result<()>;
+    /// Returns the graph in the Graphviz DOT format as a flow diagram of the given `flow`, e.g. from
+    /// [`saturated_flow`](crate::backends::saturated_flow).
+    ///
+    /// Each edge is labelled with its flow and capacity, and is drawn thicker and redder the more items it carries.
+    /// Edges missing from `flow` carry no items.
+    fn to_flow_dot_string(&self, flow: &HashMap<EdgeIndex, f64>) -> String;
+    /// Same as [`FlowGraphRender::export`], but renders the flow diagram of [`FlowGraphRender::to_flow_dot_string`].
+    fn export_flow(
+        &self,
+        path: &str,
+        flow: &HashMap<EdgeIndex, f64>,
+        options: &ExportOptions,
+    ) -> Result<()>;
+}
+
+impl FlowGraphRender for FlowGraph {
+    fn to_svg(&self, path: &str) -> Result<()> {
+        self.export(path, &ExportOptions::default())
+    }
+
+    fn export(&self, path: &str, options: &ExportOptions) -> Result<()> {
+        render(self.to_dot_string(), path, options, exec_dot)
+    }
+
+    fn to_flow_dot_string(&self, flow: &HashMap<EdgeIndex, f64>) -> String {
+        let max = flow.values().copied().fold(0.0, f64::max);
+        let edge_attributes =
+            |_, edge: <&FlowGraph as petgraph::visit::IntoEdgeReferences>::EdgeRef| {
+                let value = flow.get(&edge.id()).copied().unwrap_or(0.0);
+                let share = if max > 0.0 { value / max } else { 0.0 };
+                /* graphviz reads three numbers as a hue, saturation and value */
+                format!(
+                    "label = \"{}/{}\" penwidth = {:.2} color = \"0.0 {:.3} 0.8\"",
+                    value,
+                    edge.weight().capacity,
+                    1.0 + 4.0 * share,
+                    share
+                )
+            };
+        let dot =
+            Dot::with_attr_getters(self, &[Config::EdgeNoLabel], &edge_attributes, &|_, _| {
+                String::new()
+            });
+        format!("{:?}", dot)
+    }
+
+    fn export_flow(
+        &self,
+        path: &str,
+        flow: &HashMap<EdgeIndex, f64>,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        render(self.to_flow_dot_string(flow), path, options, exec_dot)
+    }
+}
+
+/// Renders the `dot` graph with the given `renderer`, usually [`exec_dot`], and writes the image to `path`.
+///
+/// The renderer is replaced in the tests, as the `dot` executable is not installed everywhere.
+fn render<R>(dot: String, path: &str, options: &ExportOptions, renderer: R) -> Result<()>
+where
+    R: FnOnce(String, Vec<CommandArg>) -> std::io::Result<Vec<u8>>,
+{
+    let image = renderer(dot, options.to_args())?;
+    File::create(path)?.write_all(&image)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frontend::Compiler,
+        import::file_to_entities,
+        ir::{CoalesceStrength::Aggressive, FlowGraphFun},
+    };
+
+    use super::*;
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn flow_dot_string() {
+        use crate::backends::{saturated_flow, ModelFlags};
+        use z3::{Config as Z3Config, Context};
+
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], Aggressive);
+        let cfg = Z3Config::new();
+        let ctx = Context::new(&cfg);
+        let flow = saturated_flow(&graph, &ctx, ModelFlags::empty()).unwrap();
+        assert_eq!(flow.len(), graph.edge_count());
+
+        let dot = graph.to_flow_dot_string(&flow);
+        let edges = dot.lines().filter(|l| l.contains("->")).collect::<Vec<_>>();
+        assert_eq!(edges.len(), graph.edge_count());
+        for edge in edges {
+            assert!(edge.contains("penwidth"), "{}", edge);
+            assert!(!edge.contains("Edge {"), "{}", edge);
+        }
+        // the saturated inputs carry full yellow belts, the splitters in between carry two of them
+        assert!(dot.contains("label = \"15/15\" penwidth = 3.00"));
+        assert!(dot.contains("label = \"30/30\" penwidth = 5.00"));
+    }
+
+    /// The format and the resolution are passed on to graphviz, which is replaced by a renderer recording its arguments
+    #[test]
+    fn export_png_dpi() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let mut graph = Compiler::new(entities).create_graph();
+        graph.simplify(&[], Aggressive);
+        let dir = std::env::temp_dir();
+        let export = |name: &str, dpi| {
+            let path = dir.join(name).to_string_lossy().to_string();
+            let options = ExportOptions {
+                format: ExportFormat::Png,
+                dpi,
+                size: None,
+            };
+            let mut args = vec![];
+            render(graph.to_dot_string(), &path, &options, |dot, a| {
+                args = a;
+                Ok(dot.into_bytes())
+            })
+            .unwrap();
+            (args, std::fs::read_to_string(&path).unwrap())
+        };
+
+        let (args, image) = export("verifactory_default.png", None);
+        assert!(matches!(&args[..], [CommandArg::Format(ExportFormat::Png)]));
+        assert_eq!(image, graph.to_dot_string());
+
+        let (args, _) = export("verifactory_high_dpi.png", Some(300));
+        assert!(matches!(
+            &args[..],
+            [CommandArg::Format(ExportFormat::Png), CommandArg::Custom(dpi)] if dpi == "-Gdpi=300"
+        ));
+    }
+}
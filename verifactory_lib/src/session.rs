@@ -0,0 +1,141 @@
+//! Snapshots of an analysis session, to share the exact state of a debugging session.
+//!
+//! A session bundles the entities of a blueprint, the selection of inputs, outputs and taps,
+//! and the results of the proofs into a single JSON file.
+//! The entities are stored as a blueprint string, so the blueprint can also be extracted and imported in game.
+
+use std::fs;
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    analyze::{Analysis, AnalyzeOptions},
+    entities::FBEntity,
+    export::entities_to_string,
+    import::string_to_entities,
+};
+
+/// Version of the session format, bumped on incompatible changes
+const SESSION_VERSION: u32 = 1;
+
+/// Entities, analysis options and proof results of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    version: u32,
+    /// Entities of the blueprint, stored as a blueprint string
+    #[serde(rename = "blueprint", with = "blueprint_string")]
+    pub entities: Vec<FBEntity<i32>>,
+    /// Selection of inputs, outputs and taps
+    pub options: AnalyzeOptions,
+    /// Results of the proofs, if they were run
+    pub analysis: Option<Analysis>,
+}
+
+impl Session {
+    pub fn new(
+        entities: Vec<FBEntity<i32>>,
+        options: AnalyzeOptions,
+        analysis: Option<Analysis>,
+    ) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            entities,
+            options,
+            analysis,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a session, rejecting sessions saved in a different version of the format.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let session: Self = serde_json::from_str(json)?;
+        if session.version != SESSION_VERSION {
+            anyhow::bail!(
+                "unsupported session version {}, expected {}",
+                session.version,
+                SESSION_VERSION
+            );
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, file: &str) -> Result<()> {
+        Ok(fs::write(file, self.to_json()?)?)
+    }
+
+    pub fn load(file: &str) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(file)?)
+    }
+}
+
+/// (De)serializes the entities as a blueprint string, see [`entities_to_string`].
+mod blueprint_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        entities: &[FBEntity<i32>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let blueprint = entities_to_string(entities, None).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&blueprint)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<FBEntity<i32>>, D::Error> {
+        let blueprint = String::deserialize(deserializer)?;
+        string_to_entities(&blueprint).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analyze::{analyze, AnalyzeOptions},
+        frontend::IoRule,
+        import::file_to_entities,
+    };
+
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let entities = file_to_entities("tests/4-4").unwrap();
+        let options = AnalyzeOptions {
+            removed_inputs: vec![1],
+            removed_outputs: vec![6],
+            taps: vec![],
+            io_rule: IoRule::Boundary,
+            prune_dead_io: true,
+        };
+        let analysis = analyze(entities.clone(), &options);
+        let session = Session::new(entities, options.clone(), Some(analysis));
+
+        let restored = Session::from_json(&session.to_json().unwrap()).unwrap();
+        assert_eq!(restored.options, options);
+        assert_eq!(restored.analysis, Some(analysis));
+        assert_eq!(restored.entities.len(), session.entities.len());
+
+        /* the restored session reproduces the same results */
+        let reanalyzed = analyze(restored.entities, &restored.options);
+        let results = |a: &Analysis| {
+            (
+                a.balancer,
+                a.equal_drain,
+                a.throughput_unlimited,
+                a.universal,
+            )
+        };
+        assert_eq!(results(&reanalyzed), results(&analysis));
+
+        let json = session
+            .to_json()
+            .unwrap()
+            .replace("\"version\": 1", "\"version\": 0");
+        assert!(Session::from_json(&json).is_err());
+    }
+}
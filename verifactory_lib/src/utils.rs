@@ -2,6 +2,7 @@
 
 use std::{
     f32::consts::PI,
+    fmt::Display,
     ops::{Add, Neg, Sub},
 };
 
@@ -17,6 +18,12 @@ pub struct Position<T> {
     pub y: T,
 }
 
+impl<T: Display> Display for Position<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
 impl<T> Position<T>
 where
     T: Add<Output = T> + Sub<Output = T> + Copy,
@@ -35,6 +42,25 @@ where
     }
 }
 
+/// Returns the lowest and the highest corner of the box containing all the `positions`, `None` if there are none.
+pub fn bounding_box(
+    positions: impl IntoIterator<Item = Position<i32>>,
+) -> Option<(Position<i32>, Position<i32>)> {
+    positions.into_iter().fold(None, |bounds, p| {
+        let (min, max) = bounds.unwrap_or((p, p));
+        Some((
+            Position {
+                x: min.x.min(p.x),
+                y: min.y.min(p.y),
+            },
+            Position {
+                x: max.x.max(p.x),
+                y: max.y.max(p.y),
+            },
+        ))
+    })
+}
+
 impl<T> std::ops::Add for Position<T>
 where
     T: Add<Output = T>,
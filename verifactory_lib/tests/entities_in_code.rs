@@ -0,0 +1,77 @@
+//! Builds blueprints entirely in code, without a blueprint string, and proves their properties.
+
+#![cfg(feature = "z3")]
+
+use std::collections::HashSet;
+
+use verifactory_lib::{
+    backends::{belt_balancer_f, BlueprintProofEntity, ModelFlags, ProofResult},
+    entities::{
+        add_phantoms, merge_entities, FBBaseEntity, FBBelt, FBEntity, FBSplitter, Priority,
+    },
+    frontend::Compiler,
+    ir::{CoalesceStrength, FlowGraphAnalysis, FlowGraphFun, Node},
+    utils::{Direction, Position},
+};
+
+fn belt(id: i32, x: i32, y: i32) -> FBEntity<i32> {
+    let base = FBBaseEntity::new(id, Position { x, y }, Direction::North, 15.0);
+    FBEntity::Belt(FBBelt::new(base))
+}
+
+/// Builds a 2-2 balancer: two input belts feeding a splitter, which feeds two output belts
+fn balancer_2_2() -> Vec<FBEntity<i32>> {
+    let splitter = FBBaseEntity::new(3, Position { x: 1, y: 1 }, Direction::North, 15.0);
+    let mut entities = vec![
+        belt(1, 0, 0),
+        belt(2, 1, 0),
+        FBEntity::Splitter(FBSplitter::new(splitter, Priority::None, Priority::None)),
+        belt(4, 0, 2),
+        belt(5, 1, 2),
+    ];
+    add_phantoms(&mut entities);
+    entities
+}
+
+#[test]
+fn balancer_2_2_in_code() {
+    let mut graph = Compiler::new(balancer_2_2()).create_graph();
+    graph.simplify(&[], CoalesceStrength::Aggressive);
+    let count = |f: fn(&Node) -> bool| graph.node_weights().filter(|n| f(n)).count();
+    assert_eq!(count(|n| matches!(n, Node::Input(_))), 2);
+    assert_eq!(count(|n| matches!(n, Node::Output(_))), 2);
+
+    let res = BlueprintProofEntity::new(graph, Some("QF_LRA"))
+        .model(belt_balancer_f, ModelFlags::empty());
+    assert_eq!(res, ProofResult::Sat);
+}
+
+/// Two 2-2 balancers next to each other compile to two independent components with disjoint ids
+#[test]
+fn merge_balancers() {
+    let entities = merge_entities(balancer_2_2(), balancer_2_2(), Position { x: 3, y: 0 });
+    let ids = entities
+        .iter()
+        .filter(|e| !matches!(e, FBEntity::SplitterPhantom(_)))
+        .map(|e| e.get_base().id)
+        .collect::<HashSet<_>>();
+    assert_eq!(ids.len(), 10);
+
+    let mut graph = Compiler::new(entities).create_graph();
+    graph.simplify(&[], CoalesceStrength::Aggressive);
+    let components = graph.connected_components();
+    assert_eq!(components.len(), 2);
+
+    let io_ids = |nodes: &[_]| {
+        nodes
+            .iter()
+            .map(|idx| &graph[*idx])
+            .filter(|n| matches!(n, Node::Input(_) | Node::Output(_)))
+            .map(Node::get_id)
+            .collect::<HashSet<_>>()
+    };
+    let (first, second) = (io_ids(&components[0]), io_ids(&components[1]));
+    assert_eq!(first.len(), 4);
+    assert_eq!(second.len(), 4);
+    assert!(first.is_disjoint(&second));
+}